@@ -0,0 +1,15 @@
+#![no_main]
+
+extern crate libfuzzer_sys;
+extern crate nanoset_py;
+extern crate nanoset_py_fuzz;
+extern crate pyo3;
+
+use libfuzzer_sys::fuzz_target;
+use nanoset_py::PicoSet;
+use nanoset_py_fuzz::Op;
+use pyo3::prelude::*;
+
+fuzz_target!(|ops: Vec<Op>| {
+    nanoset_py_fuzz::run(&ops, |py| PyCell::new(py, PicoSet::new()).map(AsRef::as_ref));
+});
@@ -0,0 +1,337 @@
+//! Shared driver of the fuzzing targets.
+//!
+//! Each target applies an arbitrary sequence of operations to a new instance
+//! of a nanoset type. Elements may be hostile: their `__hash__` and `__eq__`
+//! methods can raise, or modify the instance under test while it is looking
+//! them up. Python exceptions are expected, but the instance must keep its
+//! invariants after every operation, and driving the same sequence twice must
+//! not leak references with interpreters built with `--with-pydebug`.
+//!
+//! Run the targets with `cargo fuzz run nanoset -- -detect_leaks=0`, since
+//! the allocations made when initializing the interpreter are never freed.
+
+extern crate arbitrary;
+extern crate nanoset_py;
+extern crate pyo3;
+
+use std::sync::OnceLock;
+
+use arbitrary::Arbitrary;
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use pyo3::types::PyDict;
+use pyo3::types::PyList;
+use pyo3::types::PyModule;
+use pyo3::types::PyTuple;
+use pyo3::GILPool;
+
+/// The helpers used by the driver, with the `Hostile` element type.
+const HELPERS: &str = r#"
+target = None
+busy = False
+
+class Hostile(object):
+
+    def __init__(self, key, behaviour):
+        self.key = key
+        self.behaviour = behaviour
+
+    def __repr__(self):
+        return "Hostile({!r}, {!r})".format(self.key, self.behaviour)
+
+    def attack(self, when):
+        global busy
+        if self.behaviour == "raise_on_" + when:
+            raise ValueError(self)
+        if busy or target is None:
+            return
+        busy = True
+        try:
+            if self.behaviour == "clear_on_" + when:
+                target.clear()
+            elif self.behaviour == "add_on_" + when:
+                target.add(self.key)
+            elif self.behaviour == "discard_on_" + when:
+                target.discard(self.key)
+        finally:
+            busy = False
+
+    def __hash__(self):
+        if self.behaviour == "unhashable":
+            raise TypeError("unhashable type: 'Hostile'")
+        self.attack("hash")
+        # collide with other elements so that `__eq__` gets called
+        return hash(self.key) & 3
+
+    def __eq__(self, other):
+        self.attack("eq")
+        return isinstance(other, Hostile) and self.key == other.key
+"#;
+
+/// What a hostile element does when it is hashed or compared.
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum Behaviour {
+    Plain,
+    Unhashable,
+    RaiseOnHash,
+    RaiseOnEq,
+    ClearOnHash,
+    ClearOnEq,
+    AddOnEq,
+    DiscardOnEq,
+}
+
+impl Behaviour {
+    fn name(self) -> &'static str {
+        match self {
+            Behaviour::Plain => "plain",
+            Behaviour::Unhashable => "unhashable",
+            Behaviour::RaiseOnHash => "raise_on_hash",
+            Behaviour::RaiseOnEq => "raise_on_eq",
+            Behaviour::ClearOnHash => "clear_on_hash",
+            Behaviour::ClearOnEq => "clear_on_eq",
+            Behaviour::AddOnEq => "add_on_eq",
+            Behaviour::DiscardOnEq => "discard_on_eq",
+        }
+    }
+}
+
+/// An element given to the instance under test.
+#[derive(Arbitrary, Clone, Debug)]
+pub enum Elem {
+    Int(i8),
+    Str(u8),
+    Hostile(u8, Behaviour),
+}
+
+/// A binary set operator.
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum Operator {
+    And,
+    Or,
+    Sub,
+    Xor,
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// An in-place set operator.
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum InPlaceOperator {
+    And,
+    Or,
+    Sub,
+    Xor,
+}
+
+/// An operation applied to the instance under test.
+#[derive(Arbitrary, Clone, Debug)]
+pub enum Op {
+    Add(Elem),
+    Discard(Elem),
+    Remove(Elem),
+    Contains(Elem),
+    Pop,
+    Clear,
+    Copy,
+    Pickle,
+    Init(Vec<Elem>),
+    SetState(Option<Vec<Elem>>),
+    Update(Vec<Vec<Elem>>),
+    DifferenceUpdate(Vec<Vec<Elem>>),
+    IntersectionUpdate(Vec<Vec<Elem>>),
+    SymmetricDifferenceUpdate(Vec<Elem>),
+    Binary(Operator, Vec<Elem>, bool),
+    InPlace(InPlaceOperator, Vec<Elem>),
+    Iterate(Elem),
+    CopyOnWrite(bool),
+}
+
+// ---------------------------------------------------------------------------
+
+/// Get the `nanoset` module, registering it in `sys.modules` on first use so
+/// that instances can be pickled.
+fn nanoset(py: Python<'_>) -> &PyModule {
+    let modules = py.import("sys").unwrap().get("modules").unwrap();
+    let modules = modules.downcast::<PyDict>().unwrap();
+    if let Some(module) = modules.get_item("nanoset") {
+        return module.downcast::<PyModule>().unwrap();
+    }
+    let module = PyModule::new(py, "nanoset").unwrap();
+    nanoset_py::init(py, module).unwrap();
+    modules.set_item("nanoset", module).unwrap();
+    module
+}
+
+/// Get the module with the driver helpers, creating it on first use.
+fn helpers(py: Python<'_>) -> &PyModule {
+    static HELPERS_MODULE: OnceLock<PyObject> = OnceLock::new();
+    let module = HELPERS_MODULE.get_or_init(|| {
+        PyModule::from_code(py, HELPERS, "helpers.py", "helpers")
+            .unwrap()
+            .to_object(py)
+    });
+    module.as_ref(py).downcast::<PyModule>().unwrap()
+}
+
+impl Elem {
+    fn to_object(&self, py: Python) -> PyObject {
+        match *self {
+            Elem::Int(n) => n.to_object(py),
+            Elem::Str(c) => char::from(b'a' + c % 8).to_string().to_object(py),
+            Elem::Hostile(key, behaviour) => helpers(py)
+                .call1("Hostile", (key % 8, behaviour.name()))
+                .unwrap()
+                .to_object(py),
+        }
+    }
+}
+
+fn list<'p>(py: Python<'p>, elems: &[Elem]) -> &'p PyList {
+    PyList::new(py, elems.iter().map(|e| e.to_object(py)).collect::<Vec<_>>())
+}
+
+fn set<'p>(py: Python<'p>, elems: &[Elem]) -> PyResult<&'p PyAny> {
+    py.import("builtins")?.call1("set", (list(py, elems),))
+}
+
+/// Apply `op` to `obj`, returning the instance to use in the next steps.
+fn apply<'p>(py: Python<'p>, obj: &'p PyAny, op: &Op) -> PyResult<&'p PyAny> {
+    let operator = py.import("operator")?;
+    match op {
+        Op::Add(e) => obj.call_method1("add", (e.to_object(py),)).map(drop),
+        Op::Discard(e) => obj.call_method1("discard", (e.to_object(py),)).map(drop),
+        Op::Remove(e) => obj.call_method1("remove", (e.to_object(py),)).map(drop),
+        Op::Contains(e) => obj.call_method1("__contains__", (e.to_object(py),)).map(drop),
+        Op::Pop => obj.call_method0("pop").map(drop),
+        Op::Clear => obj.call_method0("clear").map(drop),
+        Op::Copy => return obj.call_method0("copy"),
+        Op::Pickle => {
+            let pickle = py.import("pickle")?;
+            let data = pickle.call1("dumps", (obj,))?;
+            return pickle.call1("loads", (data,));
+        }
+        Op::Init(elems) => obj.call_method1("__init__", (list(py, elems),)).map(drop),
+        Op::SetState(state) => {
+            let state = match state {
+                None => py.None(),
+                Some(elems) => set(py, elems)?.to_object(py),
+            };
+            obj.call_method1("__setstate__", (state,)).map(drop)
+        }
+        Op::Update(args) | Op::DifferenceUpdate(args) | Op::IntersectionUpdate(args) => {
+            let name = match op {
+                Op::Update(_) => "update",
+                Op::DifferenceUpdate(_) => "difference_update",
+                _ => "intersection_update",
+            };
+            let args: Vec<&PyList> = args.iter().map(|elems| list(py, elems)).collect();
+            obj.call_method1(name, PyTuple::new(py, args)).map(drop)
+        }
+        Op::SymmetricDifferenceUpdate(elems) => obj
+            .call_method1("symmetric_difference_update", (list(py, elems),))
+            .map(drop),
+        Op::Binary(op, elems, reflected) => {
+            let other = set(py, elems)?;
+            let name = match op {
+                Operator::And => "and_",
+                Operator::Or => "or_",
+                Operator::Sub => "sub",
+                Operator::Xor => "xor",
+                Operator::Eq => "eq",
+                Operator::Lt => "lt",
+                Operator::Le => "le",
+                Operator::Gt => "gt",
+                Operator::Ge => "ge",
+            };
+            let args = if *reflected { (other, obj) } else { (obj, other) };
+            operator.call1(name, args).map(drop)
+        }
+        Op::InPlace(op, elems) => {
+            let other = set(py, elems)?;
+            let name = match op {
+                InPlaceOperator::And => "iand",
+                InPlaceOperator::Or => "ior",
+                InPlaceOperator::Sub => "isub",
+                InPlaceOperator::Xor => "ixor",
+            };
+            operator.call1(name, (obj, other)).map(drop)
+        }
+        Op::Iterate(e) => {
+            // modify the instance while iterating over it
+            let it = obj.iter()?;
+            obj.call_method1("add", (e.to_object(py),))?;
+            it.collect::<PyResult<Vec<&PyAny>>>().map(drop)
+        }
+        Op::CopyOnWrite(enabled) => nanoset(py).call1("set_copy_on_write", (*enabled,)).map(drop),
+    }
+    .map(|_| obj)
+}
+
+/// Check whether a set is allocated in `obj`, using `__sizeof__` since it
+/// also counts an empty set which the other methods would hide.
+fn is_allocated(obj: &PyAny) -> bool {
+    let size: usize = obj.call_method0("__sizeof__").unwrap().extract().unwrap();
+    let basic: usize = obj.get_type().getattr("__basicsize__").unwrap().extract().unwrap();
+    size > basic
+}
+
+/// Check the invariants of `obj`, which must have been left in a consistent
+/// state even if the last operation raised an exception.
+fn check(obj: &PyAny) {
+    // check the allocation before iterating, since an exhausted iterator
+    // releases an empty set
+    let allocated = is_allocated(obj);
+    let items = obj.iter().unwrap().collect::<PyResult<Vec<&PyAny>>>().unwrap();
+    assert_eq!(obj.len().unwrap(), items.len(), "length of {:?}", obj);
+    assert_eq!(obj.is_true().unwrap(), !items.is_empty(), "truth of {:?}", obj);
+    assert_eq!(allocated, !items.is_empty(), "allocation of {:?}", obj);
+    assert_eq!(obj.call_method0("copy").unwrap().len().unwrap(), items.len());
+}
+
+/// Apply all the `ops` to a new instance created with `new`.
+fn drive(ops: &[Op], new: for<'p> fn(Python<'p>) -> PyResult<&'p PyAny>) {
+    // use a new pool so that all temporary objects are released on return
+    let pool = unsafe { GILPool::new() };
+    let py = pool.python();
+    let helpers = helpers(py);
+    nanoset(py).call1("set_copy_on_write", (false,)).unwrap();
+
+    let mut obj = new(py).unwrap();
+    for op in ops {
+        helpers.setattr("target", obj).unwrap();
+        if let Ok(next) = apply(py, obj, op) {
+            obj = next;
+        }
+        helpers.setattr("target", py.None()).unwrap();
+        check(obj);
+    }
+
+    nanoset(py).call1("set_copy_on_write", (false,)).unwrap();
+}
+
+/// Get the total reference count, only available with debug interpreters.
+fn total_refcount(py: Python) -> Option<isize> {
+    let sys = py.import("sys").ok()?;
+    sys.call0("gettotalrefcount").ok()?.extract().ok()
+}
+
+/// Run a fuzzing input with instances created by `new`.
+pub fn run(ops: &[Op], new: for<'p> fn(Python<'p>) -> PyResult<&'p PyAny>) {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    let gc = py.import("gc").unwrap();
+
+    // run once to populate the interpreter caches, then check that running
+    // again does not change the total reference count
+    drive(ops, new);
+    gc.call0("collect").unwrap();
+    let before = total_refcount(py);
+    drive(ops, new);
+    gc.call0("collect").unwrap();
+    let after = total_refcount(py);
+    assert_eq!(before, after, "reference count changed after {:?}", ops);
+}
@@ -0,0 +1,320 @@
+# Type stubs for the `nanoset` extension module.
+#
+# Keep in sync with the methods exported by `nanoset/lib.rs`, this is
+# checked by `tests/test_stubs.py`.
+
+from typing import (
+    AbstractSet,
+    Any,
+    Dict,
+    FrozenSet,
+    Generic,
+    Hashable,
+    Iterable,
+    Iterator,
+    List,
+    Mapping,
+    MutableSet,
+    Optional,
+    Set,
+    Tuple,
+    Type,
+    TypeVar,
+    Union,
+)
+
+from . import debug as debug
+from . import json as json
+
+_T = TypeVar("_T")
+_S = TypeVar("_S")
+
+__version__: str
+__author__: str
+__build__: Dict[str, Any]
+__divergences__: Dict[str, str]
+
+EMPTY: FrozenSet[Any]
+
+def set_legacy_repr(legacy: bool) -> bool: ...
+def set_copy_on_write(enabled: bool) -> bool: ...
+def set_deterministic(enabled: bool) -> bool: ...
+def union_all(__sets: Iterable[Iterable[_T]]) -> NanoSet[_T]: ...
+def intersection_all(__sets: Iterable[Iterable[_T]]) -> NanoSet[_T]: ...
+def add_to_each(__sets: Iterable[MutableSet[_T]], __item: _T) -> None: ...
+def configure(impl: Union[str, Type[NanoSet[Any]], Type[PicoSet[Any]], None]) -> None: ...
+
+# only available with the `stats` feature
+def stats() -> Dict[str, Any]: ...
+def reset_stats() -> None: ...
+
+class Int64Array:
+    def tolist(self) -> List[int]: ...
+    def __len__(self) -> int: ...
+
+class NanoSetIterator(Iterator[_T], Generic[_T]):
+    def __iter__(self) -> NanoSetIterator[_T]: ...
+    def __next__(self) -> _T: ...
+    def __length_hint__(self) -> int: ...
+    def __reduce__(self) -> Tuple[Any, ...]: ...
+
+class NanoSet(MutableSet[_T], Generic[_T]):
+    def __init__(self, __iterable: Iterable[_T] = ...) -> None: ...
+    @classmethod
+    def __class_getitem__(cls, __item: Any) -> Any: ...
+    def __getstate__(self) -> Optional[Set[_T]]: ...
+    def __setstate__(self, __state: Optional[Set[_T]]) -> None: ...
+    def __reduce__(self) -> Tuple[Type[NanoSet[_T]], Tuple[Any, ...]]: ...
+    def __copy__(self) -> NanoSet[_T]: ...
+    def __deepcopy__(self, __memo: Dict[int, Any]) -> NanoSet[_T]: ...
+    def add(self, __element: _T) -> None: ...
+    def clear(self) -> None: ...
+    def copy(self) -> NanoSet[_T]: ...
+    def sorted_iter(self) -> NanoSetIterator[_T]: ...
+    def freeze(self) -> FrozenSet[_T]: ...
+    @classmethod
+    def from_array(cls, __buffer: Any) -> NanoSet[int]: ...
+    @classmethod
+    def view(cls, __mapping: Mapping[_S, Any]) -> NanoSetView[_S]: ...
+    def to_array(self) -> Int64Array: ...
+    def difference(self, *s: Iterable[Any]) -> NanoSet[_T]: ...
+    def difference_update(self, *s: Iterable[Any]) -> None: ...
+    def discard(self, __element: _T) -> None: ...
+    def intersection(self, *s: Iterable[Any]) -> NanoSet[_T]: ...
+    def intersection_update(self, *s: Iterable[Any]) -> None: ...
+    def isdisjoint(self, __s: Iterable[Any]) -> bool: ...
+    def issubset(self, __s: Iterable[Any]) -> bool: ...
+    def issuperset(self, __s: Iterable[Any]) -> bool: ...
+    def pop(self) -> _T: ...
+    def remove(self, __element: _T) -> None: ...
+    def symmetric_difference(self, __s: Iterable[_T]) -> NanoSet[_T]: ...
+    def symmetric_difference_update(self, __s: Iterable[_T]) -> None: ...
+    def union(self, *s: Iterable[_S]) -> NanoSet[Union[_T, _S]]: ...
+    def update(self, *s: Iterable[_T]) -> None: ...
+    def __iter__(self) -> NanoSetIterator[_T]: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, __o: object) -> bool: ...
+    def __bool__(self) -> bool: ...
+    def __repr__(self) -> str: ...
+    def __sizeof__(self) -> int: ...
+    def __and__(self, __s: AbstractSet[object]) -> NanoSet[_T]: ...
+    def __rand__(self, __s: AbstractSet[object]) -> NanoSet[_T]: ...
+    def __or__(self, __s: AbstractSet[_S]) -> NanoSet[Union[_T, _S]]: ...
+    def __ror__(self, __s: AbstractSet[_S]) -> NanoSet[Union[_T, _S]]: ...
+    def __sub__(self, __s: AbstractSet[Optional[_T]]) -> NanoSet[_T]: ...
+    def __rsub__(self, __s: AbstractSet[_S]) -> NanoSet[_S]: ...
+    def __xor__(self, __s: AbstractSet[_S]) -> NanoSet[Union[_T, _S]]: ...
+    def __rxor__(self, __s: AbstractSet[_S]) -> NanoSet[Union[_T, _S]]: ...
+    def __iand__(self, __s: AbstractSet[object]) -> NanoSet[_T]: ...
+    def __ior__(self, __s: AbstractSet[_T]) -> NanoSet[_T]: ...
+    def __isub__(self, __s: AbstractSet[object]) -> NanoSet[_T]: ...
+    def __ixor__(self, __s: AbstractSet[_T]) -> NanoSet[_T]: ...
+    def __eq__(self, __o: object) -> bool: ...
+    def __ne__(self, __o: object) -> bool: ...
+    def __le__(self, __s: AbstractSet[object]) -> bool: ...
+    def __lt__(self, __s: AbstractSet[object]) -> bool: ...
+    def __ge__(self, __s: AbstractSet[object]) -> bool: ...
+    def __gt__(self, __s: AbstractSet[object]) -> bool: ...
+    __hash__: None  # type: ignore
+
+class NanoSetView(AbstractSet[_T], Generic[_T]):
+    @property
+    def mapping(self) -> Optional[Mapping[_T, Any]]: ...
+    def add(self, __element: _T) -> None: ...
+    def clear(self) -> None: ...
+    def copy(self) -> NanoSet[_T]: ...
+    def discard(self, __element: _T) -> None: ...
+    def isdisjoint(self, __s: Iterable[Any]) -> bool: ...
+    def pop(self) -> _T: ...
+    def remove(self, __element: _T) -> None: ...
+    def __iter__(self) -> Iterator[_T]: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, __o: object) -> bool: ...
+    def __bool__(self) -> bool: ...
+    def __repr__(self) -> str: ...
+    def __and__(self, __s: AbstractSet[object]) -> NanoSet[_T]: ...
+    def __rand__(self, __s: AbstractSet[object]) -> NanoSet[_T]: ...
+    def __or__(self, __s: AbstractSet[_S]) -> NanoSet[Union[_T, _S]]: ...
+    def __ror__(self, __s: AbstractSet[_S]) -> NanoSet[Union[_T, _S]]: ...
+    def __sub__(self, __s: AbstractSet[Optional[_T]]) -> NanoSet[_T]: ...
+    def __rsub__(self, __s: AbstractSet[_S]) -> NanoSet[_S]: ...
+    def __xor__(self, __s: AbstractSet[_S]) -> NanoSet[Union[_T, _S]]: ...
+    def __rxor__(self, __s: AbstractSet[_S]) -> NanoSet[Union[_T, _S]]: ...
+    def __iand__(self, __s: AbstractSet[object]) -> NanoSetView[_T]: ...
+    def __ior__(self, __s: AbstractSet[_T]) -> NanoSetView[_T]: ...
+    def __isub__(self, __s: AbstractSet[object]) -> NanoSetView[_T]: ...
+    def __ixor__(self, __s: AbstractSet[_T]) -> NanoSetView[_T]: ...
+    def __eq__(self, __o: object) -> bool: ...
+    def __ne__(self, __o: object) -> bool: ...
+    def __le__(self, __s: AbstractSet[object]) -> bool: ...
+    def __lt__(self, __s: AbstractSet[object]) -> bool: ...
+    def __ge__(self, __s: AbstractSet[object]) -> bool: ...
+    def __gt__(self, __s: AbstractSet[object]) -> bool: ...
+    __hash__: None  # type: ignore
+
+class PicoSet(MutableSet[_T], Generic[_T]):
+    def __init__(self, __iterable: Iterable[_T] = ...) -> None: ...
+    @classmethod
+    def __class_getitem__(cls, __item: Any) -> Any: ...
+    def __getstate__(self) -> Optional[Set[_T]]: ...
+    def __setstate__(self, __state: Optional[Set[_T]]) -> None: ...
+    def __reduce__(self) -> Tuple[Type[PicoSet[_T]], Tuple[Any, ...]]: ...
+    def __copy__(self) -> PicoSet[_T]: ...
+    def __deepcopy__(self, __memo: Dict[int, Any]) -> PicoSet[_T]: ...
+    def add(self, __element: _T) -> None: ...
+    def clear(self) -> None: ...
+    def copy(self) -> PicoSet[_T]: ...
+    def sorted_iter(self) -> NanoSetIterator[_T]: ...
+    def freeze(self) -> FrozenSet[_T]: ...
+    @classmethod
+    def from_array(cls, __buffer: Any) -> PicoSet[int]: ...
+    def to_array(self) -> Int64Array: ...
+    def difference(self, *s: Iterable[Any]) -> PicoSet[_T]: ...
+    def difference_update(self, *s: Iterable[Any]) -> None: ...
+    def discard(self, __element: _T) -> None: ...
+    def intersection(self, *s: Iterable[Any]) -> PicoSet[_T]: ...
+    def intersection_update(self, *s: Iterable[Any]) -> None: ...
+    def isdisjoint(self, __s: Iterable[Any]) -> bool: ...
+    def issubset(self, __s: Iterable[Any]) -> bool: ...
+    def issuperset(self, __s: Iterable[Any]) -> bool: ...
+    def pop(self) -> _T: ...
+    def remove(self, __element: _T) -> None: ...
+    def symmetric_difference(self, __s: Iterable[_T]) -> PicoSet[_T]: ...
+    def symmetric_difference_update(self, __s: Iterable[_T]) -> None: ...
+    def union(self, *s: Iterable[_S]) -> PicoSet[Union[_T, _S]]: ...
+    def update(self, *s: Iterable[_T]) -> None: ...
+    def __iter__(self) -> NanoSetIterator[_T]: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, __o: object) -> bool: ...
+    def __bool__(self) -> bool: ...
+    def __repr__(self) -> str: ...
+    def __sizeof__(self) -> int: ...
+    def __and__(self, __s: AbstractSet[object]) -> PicoSet[_T]: ...
+    def __rand__(self, __s: AbstractSet[object]) -> PicoSet[_T]: ...
+    def __or__(self, __s: AbstractSet[_S]) -> PicoSet[Union[_T, _S]]: ...
+    def __ror__(self, __s: AbstractSet[_S]) -> PicoSet[Union[_T, _S]]: ...
+    def __sub__(self, __s: AbstractSet[Optional[_T]]) -> PicoSet[_T]: ...
+    def __rsub__(self, __s: AbstractSet[_S]) -> PicoSet[_S]: ...
+    def __xor__(self, __s: AbstractSet[_S]) -> PicoSet[Union[_T, _S]]: ...
+    def __rxor__(self, __s: AbstractSet[_S]) -> PicoSet[Union[_T, _S]]: ...
+    def __iand__(self, __s: AbstractSet[object]) -> PicoSet[_T]: ...
+    def __ior__(self, __s: AbstractSet[_T]) -> PicoSet[_T]: ...
+    def __isub__(self, __s: AbstractSet[object]) -> PicoSet[_T]: ...
+    def __ixor__(self, __s: AbstractSet[_T]) -> PicoSet[_T]: ...
+    def __eq__(self, __o: object) -> bool: ...
+    def __ne__(self, __o: object) -> bool: ...
+    def __le__(self, __s: AbstractSet[object]) -> bool: ...
+    def __lt__(self, __s: AbstractSet[object]) -> bool: ...
+    def __ge__(self, __s: AbstractSet[object]) -> bool: ...
+    def __gt__(self, __s: AbstractSet[object]) -> bool: ...
+    __hash__: None  # type: ignore
+
+class PersistentNanoSetIterator(Iterator[_T], Generic[_T]):
+    def __iter__(self) -> PersistentNanoSetIterator[_T]: ...
+    def __next__(self) -> _T: ...
+
+class PersistentNanoSetEvolver(Generic[_T]):
+    def add(self, __element: _T) -> None: ...
+    def discard(self, __element: _T) -> None: ...
+    def remove(self, __element: _T) -> None: ...
+    def update(self, *s: Iterable[_T]) -> None: ...
+    def is_dirty(self) -> bool: ...
+    def persistent(self) -> PersistentNanoSet[_T]: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, __o: object) -> bool: ...
+
+class PersistentNanoSet(AbstractSet[_T], Hashable, Generic[_T]):
+    def __new__(cls, __iterable: Iterable[_T] = ...) -> PersistentNanoSet[_T]: ...
+    @classmethod
+    def __class_getitem__(cls, __item: Any) -> Any: ...
+    def __reduce__(self) -> Tuple[Type[PersistentNanoSet[_T]], Tuple[Any, ...]]: ...
+    def __copy__(self) -> PersistentNanoSet[_T]: ...
+    def add(self, __element: _T) -> PersistentNanoSet[_T]: ...
+    def copy(self) -> PersistentNanoSet[_T]: ...
+    def difference(self, *s: Iterable[Any]) -> PersistentNanoSet[_T]: ...
+    def discard(self, __element: _T) -> PersistentNanoSet[_T]: ...
+    def evolver(self) -> PersistentNanoSetEvolver[_T]: ...
+    def intersection(self, *s: Iterable[Any]) -> PersistentNanoSet[_T]: ...
+    def isdisjoint(self, __s: Iterable[Any]) -> bool: ...
+    def issubset(self, __s: Iterable[Any]) -> bool: ...
+    def issuperset(self, __s: Iterable[Any]) -> bool: ...
+    def remove(self, __element: _T) -> PersistentNanoSet[_T]: ...
+    def symmetric_difference(self, __s: Iterable[_T]) -> PersistentNanoSet[_T]: ...
+    def union(self, *s: Iterable[_S]) -> PersistentNanoSet[Union[_T, _S]]: ...
+    def __iter__(self) -> PersistentNanoSetIterator[_T]: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, __o: object) -> bool: ...
+    def __bool__(self) -> bool: ...
+    def __repr__(self) -> str: ...
+    def __hash__(self) -> int: ...
+    def __and__(self, __s: AbstractSet[object]) -> PersistentNanoSet[_T]: ...
+    def __rand__(self, __s: AbstractSet[object]) -> PersistentNanoSet[_T]: ...
+    def __or__(self, __s: AbstractSet[_S]) -> PersistentNanoSet[Union[_T, _S]]: ...
+    def __ror__(self, __s: AbstractSet[_S]) -> PersistentNanoSet[Union[_T, _S]]: ...
+    def __sub__(self, __s: AbstractSet[Optional[_T]]) -> PersistentNanoSet[_T]: ...
+    def __rsub__(self, __s: AbstractSet[_S]) -> PersistentNanoSet[_S]: ...
+    def __xor__(self, __s: AbstractSet[_S]) -> PersistentNanoSet[Union[_T, _S]]: ...
+    def __rxor__(self, __s: AbstractSet[_S]) -> PersistentNanoSet[Union[_T, _S]]: ...
+    def __eq__(self, __o: object) -> bool: ...
+    def __ne__(self, __o: object) -> bool: ...
+    def __le__(self, __s: AbstractSet[object]) -> bool: ...
+    def __lt__(self, __s: AbstractSet[object]) -> bool: ...
+    def __ge__(self, __s: AbstractSet[object]) -> bool: ...
+    def __gt__(self, __s: AbstractSet[object]) -> bool: ...
+
+class ConcurrentNanoSetIterator(Iterator[_T], Generic[_T]):
+    def __iter__(self) -> ConcurrentNanoSetIterator[_T]: ...
+    def __next__(self) -> _T: ...
+
+class ConcurrentNanoSet(MutableSet[_T], Generic[_T]):
+    def __new__(cls, __iterable: Iterable[_T] = ...) -> ConcurrentNanoSet[_T]: ...
+    @classmethod
+    def __class_getitem__(cls, __item: Any) -> Any: ...
+    def __reduce__(self) -> Tuple[Type[ConcurrentNanoSet[_T]], Tuple[Any, ...]]: ...
+    def __copy__(self) -> ConcurrentNanoSet[_T]: ...
+    def add(self, __element: _T) -> None: ...
+    def clear(self) -> None: ...
+    def copy(self) -> ConcurrentNanoSet[_T]: ...
+    def difference(self, *s: Iterable[Any]) -> ConcurrentNanoSet[_T]: ...
+    def difference_update(self, *s: Iterable[Any]) -> None: ...
+    def discard(self, __element: _T) -> None: ...
+    def intersection(self, *s: Iterable[Any]) -> ConcurrentNanoSet[_T]: ...
+    def intersection_update(self, *s: Iterable[Any]) -> None: ...
+    def isdisjoint(self, __s: Iterable[Any]) -> bool: ...
+    def issubset(self, __s: Iterable[Any]) -> bool: ...
+    def issuperset(self, __s: Iterable[Any]) -> bool: ...
+    def pop(self) -> _T: ...
+    def remove(self, __element: _T) -> None: ...
+    def symmetric_difference(self, __s: Iterable[_T]) -> ConcurrentNanoSet[_T]: ...
+    def symmetric_difference_update(self, __s: Iterable[_T]) -> None: ...
+    def union(self, *s: Iterable[_S]) -> ConcurrentNanoSet[Union[_T, _S]]: ...
+    def update(self, *s: Iterable[_T]) -> None: ...
+    def __iter__(self) -> ConcurrentNanoSetIterator[_T]: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, __o: object) -> bool: ...
+    def __bool__(self) -> bool: ...
+    def __repr__(self) -> str: ...
+    def __and__(self, __s: AbstractSet[object]) -> ConcurrentNanoSet[_T]: ...
+    def __rand__(self, __s: AbstractSet[object]) -> ConcurrentNanoSet[_T]: ...
+    def __or__(self, __s: AbstractSet[_S]) -> ConcurrentNanoSet[Union[_T, _S]]: ...
+    def __ror__(self, __s: AbstractSet[_S]) -> ConcurrentNanoSet[Union[_T, _S]]: ...
+    def __sub__(self, __s: AbstractSet[Optional[_T]]) -> ConcurrentNanoSet[_T]: ...
+    def __rsub__(self, __s: AbstractSet[_S]) -> ConcurrentNanoSet[_S]: ...
+    def __xor__(self, __s: AbstractSet[_S]) -> ConcurrentNanoSet[Union[_T, _S]]: ...
+    def __rxor__(self, __s: AbstractSet[_S]) -> ConcurrentNanoSet[Union[_T, _S]]: ...
+    def __iand__(self, __s: AbstractSet[object]) -> ConcurrentNanoSet[_T]: ...
+    def __ior__(self, __s: AbstractSet[_T]) -> ConcurrentNanoSet[_T]: ...
+    def __isub__(self, __s: AbstractSet[object]) -> ConcurrentNanoSet[_T]: ...
+    def __ixor__(self, __s: AbstractSet[_T]) -> ConcurrentNanoSet[_T]: ...
+    def __eq__(self, __o: object) -> bool: ...
+    def __ne__(self, __o: object) -> bool: ...
+    def __le__(self, __s: AbstractSet[object]) -> bool: ...
+    def __lt__(self, __s: AbstractSet[object]) -> bool: ...
+    def __ge__(self, __s: AbstractSet[object]) -> bool: ...
+    def __gt__(self, __s: AbstractSet[object]) -> bool: ...
+    __hash__: None  # type: ignore
+
+class Set(MutableSet[_T], Generic[_T]):
+    def __new__(cls, __iterable: Iterable[_T] = ...) -> Union[NanoSet[_T], PicoSet[_T]]: ...  # type: ignore
+    @classmethod
+    def __class_getitem__(cls, __item: Any) -> Any: ...
@@ -0,0 +1,183 @@
+//! Conversion between nanosets of integers and buffers of `int64` values.
+//!
+//! Nanosets always store their elements as Python objects, so both
+//! conversions go through one Python `int` per element. `to_array` copies
+//! the elements into a new `Int64Array`, which exposes the copy through the
+//! buffer protocol so that `numpy.asarray` or `memoryview` can use it without
+//! copying it again. `from_array` reads the values of any buffer of integers
+//! and creates an `int` object for each of them to store in the wrapped set.
+
+use std::os::raw::c_int;
+use std::os::raw::c_void;
+
+use pyo3::buffer::Element;
+use pyo3::buffer::PyBuffer;
+use pyo3::class::PyBufferProtocol;
+use pyo3::class::PySequenceProtocol;
+use pyo3::exceptions::BufferError;
+use pyo3::exceptions::TypeError;
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use pyo3::types::PyList;
+use pyo3::types::PySet;
+use pyo3::AsPyPointer;
+
+/// The buffer format of `Int64Array`, a native `long long`.
+const FORMAT: &[u8] = b"q\0";
+
+#[pyclass(module = "nanoset")]
+#[derive(Debug)]
+/// A read-only array of `int64` values, exported with the buffer protocol.
+pub struct Int64Array {
+    data: Vec<i64>,
+    /// The shape and strides of the exported buffer, which must stay valid
+    /// for as long as the buffer is in use.
+    shape: [ffi::Py_ssize_t; 1],
+    strides: [ffi::Py_ssize_t; 1],
+}
+
+impl Int64Array {
+    fn new(data: Vec<i64>) -> Self {
+        let shape = [data.len() as ffi::Py_ssize_t];
+        let strides = [std::mem::size_of::<i64>() as ffi::Py_ssize_t];
+        Self { data, shape, strides }
+    }
+}
+
+#[pymethods]
+impl Int64Array {
+    #[text_signature = "($self)"]
+    fn tolist(&self) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        Ok(PyList::new(py, &self.data).to_object(py))
+    }
+}
+
+#[pyproto]
+impl PyBufferProtocol for Int64Array {
+    // the signature is imposed by the trait, the pointer comes from Python
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn bf_getbuffer(slf: PyRefMut<Self>, view: *mut ffi::Py_buffer, flags: c_int) -> PyResult<()> {
+        // SAFETY: the view is either null or a valid pointer given by Python
+        let view = match unsafe { view.as_mut() } {
+            None => return BufferError::into("View is null"),
+            Some(view) => view,
+        };
+        if (flags & ffi::PyBUF_WRITABLE) == ffi::PyBUF_WRITABLE {
+            return BufferError::into("Int64Array is read-only");
+        }
+
+        // the values are never modified once the array is created, so the
+        // buffer stays valid as long as the view holds a reference to us
+        view.obj = slf.as_ptr();
+        unsafe { ffi::Py_INCREF(view.obj) };
+        view.buf = slf.data.as_ptr() as *mut c_void;
+        view.len = (slf.data.len() * std::mem::size_of::<i64>()) as ffi::Py_ssize_t;
+        view.readonly = 1;
+        view.itemsize = std::mem::size_of::<i64>() as ffi::Py_ssize_t;
+        view.format = std::ptr::null_mut();
+        if (flags & ffi::PyBUF_FORMAT) == ffi::PyBUF_FORMAT {
+            view.format = FORMAT.as_ptr() as *mut _;
+        }
+        view.ndim = 1;
+        view.shape = std::ptr::null_mut();
+        if (flags & ffi::PyBUF_ND) == ffi::PyBUF_ND {
+            view.shape = slf.shape.as_ptr() as *mut _;
+        }
+        view.strides = std::ptr::null_mut();
+        if (flags & ffi::PyBUF_STRIDES) == ffi::PyBUF_STRIDES {
+            view.strides = slf.strides.as_ptr() as *mut _;
+        }
+        view.suboffsets = std::ptr::null_mut();
+        view.internal = std::ptr::null_mut();
+
+        Ok(())
+    }
+}
+
+#[pyproto]
+impl PySequenceProtocol for Int64Array {
+    fn __len__(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// Write the integers of `set` into a new `Int64Array`.
+///
+/// Raises a `TypeError` for elements which are not integers, and an
+/// `OverflowError` for integers which do not fit in 64 bits.
+pub fn to_array(py: Python, set: Option<Py<PySet>>) -> PyResult<Int64Array> {
+    let set = match set {
+        None => return Ok(Int64Array::new(Vec::new())),
+        Some(set) => set,
+    };
+    let set: &PyAny = set.as_ref(py);
+    let mut data = Vec::with_capacity(set.len()?);
+    for item in set.iter()? {
+        data.push(item?.extract::<i64>()?);
+    }
+    Ok(Int64Array::new(data))
+}
+
+/// Add the items of `buffer` to `set` if it holds values of type `T`.
+///
+/// Returns `false` if the buffer has another type.
+fn add_items<T: Element + ToPyObject>(py: Python, buffer: &PyAny, set: &Py<PySet>) -> PyResult<bool> {
+    let buffer = match PyBuffer::<T>::get(buffer) {
+        Ok(buffer) => buffer,
+        Err(_) => return Ok(false),
+    };
+    let add = |value: T| -> PyResult<()> {
+        let item = value.to_object(py);
+        match unsafe { ffi::PySet_Add(set.as_ptr(), item.as_ptr()) } {
+            -1 => Err(PyErr::fetch(py)),
+            _ => Ok(()),
+        }
+    };
+    // read contiguous buffers in place, and copy any other buffer first
+    let result = match buffer.as_slice(py) {
+        Some(slice) => slice.iter().try_for_each(|cell| add(cell.get())),
+        None => buffer.to_vec(py)?.into_iter().try_for_each(add),
+    };
+    buffer.release(py);
+    result.map(|_| true)
+}
+
+/// Get the size of `buffer` in bytes, failing if it is not a buffer.
+fn buffer_size(py: Python, buffer: &PyAny) -> PyResult<usize> {
+    let mut view = ffi::Py_buffer::new();
+    match unsafe { ffi::PyObject_GetBuffer(buffer.as_ptr(), &mut view, ffi::PyBUF_FULL_RO) } {
+        -1 => Err(PyErr::fetch(py)),
+        _ => {
+            let size = view.len as usize;
+            unsafe { ffi::PyBuffer_Release(&mut view) };
+            Ok(size)
+        }
+    }
+}
+
+/// Create a set with the integers of `buffer`, or `None` if it is empty.
+pub fn set_from_buffer(py: Python, buffer: &PyAny) -> PyResult<Option<Py<PySet>>> {
+    // empty buffers may not be aligned, and are never given a set anyway
+    if buffer_size(py, buffer)? == 0 {
+        return Ok(None);
+    }
+    let set = super::new_set(py, None)?;
+    let added = add_items::<i64>(py, buffer, &set)?
+        || add_items::<i32>(py, buffer, &set)?
+        || add_items::<i16>(py, buffer, &set)?
+        || add_items::<i8>(py, buffer, &set)?
+        || add_items::<u64>(py, buffer, &set)?
+        || add_items::<u32>(py, buffer, &set)?
+        || add_items::<u16>(py, buffer, &set)?
+        || add_items::<u8>(py, buffer, &set)?;
+    if !added {
+        return TypeError::into("expected a buffer of integers");
+    }
+    match unsafe { ffi::PySet_Size(set.as_ptr()) } {
+        0 => Ok(None),
+        _ => Ok(Some(set)),
+    }
+}
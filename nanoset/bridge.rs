@@ -0,0 +1,312 @@
+//! Optional `serde` support for `NanoSet` and `PicoSet`.
+//!
+//! A nanoset is serialized as an optional sequence of its elements: an
+//! instance which never allocated its inner set is serialized as `none`,
+//! which most formats write as `null`. The elements themselves are converted
+//! from and to Python objects by a `Bridge`, so that applications can choose
+//! how to map their own element types; `Primitives` handles the builtin
+//! types most likely to be stored in a set.
+
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::marker::PhantomData;
+
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use pyo3::types::PyBool;
+use pyo3::types::PyBytes;
+use pyo3::types::PyFloat;
+use pyo3::types::PySet;
+use pyo3::types::PyString;
+use pyo3::types::PyTuple;
+use pyo3::PyNativeType;
+use serde::de::DeserializeSeed;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::ser::SerializeSeq;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+
+use super::NanoSet;
+use super::PicoSet;
+
+/// A conversion between Python objects and the `serde` data model.
+pub trait Bridge {
+    /// Serialize a single Python object.
+    fn serialize<S: Serializer>(obj: &PyAny, serializer: S) -> Result<S::Ok, S::Error>;
+
+    /// Deserialize a single Python object, which must be hashable to be
+    /// added to a set.
+    fn deserialize<'de, D: Deserializer<'de>>(py: Python, deserializer: D) -> Result<PyObject, D::Error>;
+}
+
+/// Get the message of a Python exception, to report it as a `serde` error.
+fn message(py: Python, err: PyErr) -> String {
+    let value = err.to_object(py);
+    match value.as_ref(py).str() {
+        Ok(msg) => msg.to_string_lossy().into_owned(),
+        Err(_) => String::from("unprintable Python exception"),
+    }
+}
+
+/// A Python object serialized with the bridge `B`.
+struct Element<'a, B> {
+    obj: &'a PyAny,
+    bridge: PhantomData<B>,
+}
+
+impl<'a, B> Element<'a, B> {
+    fn new(obj: &'a PyAny) -> Self {
+        Self { obj, bridge: PhantomData }
+    }
+}
+
+impl<'a, B: Bridge> Serialize for Element<'a, B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        B::serialize(self.obj, serializer)
+    }
+}
+
+/// The elements of a Python iterable serialized with the bridge `B`.
+struct Elements<'a, B> {
+    set: &'a PyAny,
+    bridge: PhantomData<B>,
+}
+
+impl<'a, B: Bridge> Serialize for Elements<'a, B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let py = self.set.py();
+        let len = self.set.len().map_err(|e| S::Error::custom(message(py, e)))?;
+        let mut seq = serializer.serialize_seq(Some(len))?;
+        for item in self.set.iter().map_err(|e| S::Error::custom(message(py, e)))? {
+            let item = item.map_err(|e| S::Error::custom(message(py, e)))?;
+            seq.serialize_element(&Element::<B>::new(item))?;
+        }
+        seq.end()
+    }
+}
+
+/// A seed deserializing a Python object with the bridge `B`.
+struct Seed<'py, B> {
+    py: Python<'py>,
+    bridge: PhantomData<B>,
+}
+
+impl<'py, B> Seed<'py, B> {
+    fn new(py: Python<'py>) -> Self {
+        Self { py, bridge: PhantomData }
+    }
+}
+
+impl<'py, B> Clone for Seed<'py, B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'py, B> Copy for Seed<'py, B> {}
+
+impl<'py, 'de, B: Bridge> DeserializeSeed<'de> for Seed<'py, B> {
+    type Value = PyObject;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<PyObject, D::Error> {
+        B::deserialize(self.py, deserializer)
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// The default bridge, for `None`, `bool`, `int`, `float`, `str`, `bytes`,
+/// and `tuple` of these types.
+///
+/// Integers must fit in 64 bits, and tuples are serialized as sequences,
+/// which are always deserialized as tuples so that they can be hashed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Primitives;
+
+impl Bridge for Primitives {
+    fn serialize<S: Serializer>(obj: &PyAny, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        if obj.is_none() {
+            serializer.serialize_unit()
+        } else if let Ok(b) = obj.downcast::<PyBool>() {
+            serializer.serialize_bool(b.is_true())
+        } else if let Ok(n) = obj.extract::<i64>() {
+            serializer.serialize_i64(n)
+        } else if let Ok(n) = obj.extract::<u64>() {
+            serializer.serialize_u64(n)
+        } else if let Ok(f) = obj.downcast::<PyFloat>() {
+            serializer.serialize_f64(f.value())
+        } else if let Ok(s) = obj.downcast::<PyString>() {
+            let s = s.to_string().map_err(|e| S::Error::custom(message(obj.py(), e)))?;
+            serializer.serialize_str(&s)
+        } else if let Ok(b) = obj.downcast::<PyBytes>() {
+            serializer.serialize_bytes(b.as_bytes())
+        } else if let Ok(t) = obj.downcast::<PyTuple>() {
+            let mut seq = serializer.serialize_seq(Some(t.len()))?;
+            for item in t.iter() {
+                seq.serialize_element(&Element::<Self>::new(item))?;
+            }
+            seq.end()
+        } else {
+            let ty = obj.get_type().name();
+            Err(S::Error::custom(format!("cannot serialize object of type {}", ty)))
+        }
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(py: Python, deserializer: D) -> Result<PyObject, D::Error> {
+        deserializer.deserialize_any(PrimitiveVisitor { py })
+    }
+}
+
+/// The visitor building Python objects for `Primitives`.
+struct PrimitiveVisitor<'py> {
+    py: Python<'py>,
+}
+
+impl<'py, 'de> Visitor<'de> for PrimitiveVisitor<'py> {
+    type Value = PyObject;
+
+    fn expecting(&self, f: &mut Formatter) -> FmtResult {
+        f.write_str("a hashable value")
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<PyObject, E> {
+        Ok(self.py.None())
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> Result<PyObject, E> {
+        Ok(self.py.None())
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<PyObject, D::Error> {
+        Primitives::deserialize(self.py, deserializer)
+    }
+
+    fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<PyObject, E> {
+        Ok(v.to_object(self.py))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<PyObject, E> {
+        Ok(v.to_object(self.py))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<PyObject, E> {
+        Ok(v.to_object(self.py))
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<PyObject, E> {
+        Ok(v.to_object(self.py))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<PyObject, E> {
+        Ok(v.to_object(self.py))
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<PyObject, E> {
+        Ok(PyBytes::new(self.py, v).to_object(self.py))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<PyObject, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element_seed(Seed::<Primitives>::new(self.py))? {
+            items.push(item);
+        }
+        Ok(PyTuple::new(self.py, items).to_object(self.py))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// The visitor building a nanoset from an optional sequence of elements.
+struct SetVisitor<'py, B> {
+    py: Python<'py>,
+    bridge: PhantomData<B>,
+}
+
+impl<'py, 'de, B: Bridge> Visitor<'de> for SetVisitor<'py, B> {
+    type Value = Option<Py<PySet>>;
+
+    fn expecting(&self, f: &mut Formatter) -> FmtResult {
+        f.write_str("an optional sequence of hashable values")
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        use serde::de::Error;
+
+        // only allocate a set once we know there is an element to store
+        let seed = Seed::<B>::new(self.py);
+        let mut set: Option<&PySet> = None;
+        while let Some(item) = seq.next_element_seed(seed)? {
+            let error = |e| A::Error::custom(message(self.py, e));
+            let set = match set {
+                Some(set) => set,
+                None => set.get_or_insert(PySet::empty(self.py).map_err(error)?),
+            };
+            set.add(item).map_err(error)?;
+        }
+        Ok(set.map(Py::from))
+    }
+}
+
+macro_rules! serde_impl {
+    ($cls:ident) => {
+        impl $cls {
+            /// Serialize the elements of the set with the bridge `B`.
+            pub fn serialize_with<B: Bridge, S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                match self.inner.get(py) {
+                    None => serializer.serialize_none(),
+                    Some(set) => serializer.serialize_some(&Elements::<B> {
+                        set: set.as_ref(py),
+                        bridge: PhantomData,
+                    }),
+                }
+            }
+
+            /// Deserialize a set with elements built by the bridge `B`.
+            pub fn deserialize_with<'de, B: Bridge, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                let visitor = SetVisitor::<B> { py, bridge: PhantomData };
+                match deserializer.deserialize_option(visitor)? {
+                    None => Ok(Self::new()),
+                    Some(set) => Ok(Self::from_set(set)),
+                }
+            }
+        }
+
+        impl Serialize for $cls {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.serialize_with::<Primitives, S>(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $cls {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Self::deserialize_with::<Primitives, D>(deserializer)
+            }
+        }
+    };
+}
+
+serde_impl!(NanoSet);
+serde_impl!(PicoSet);
@@ -0,0 +1,112 @@
+//! Bulk operations over many sets at once.
+//!
+//! The loops run in Rust and work directly on the sets wrapped by nanosets,
+//! so no intermediate wrapper is ever created, and empty nanosets are never
+//! given a set to hold their (missing) elements.
+
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use pyo3::types::PySet;
+
+use super::NanoSet;
+use super::PicoSet;
+
+/// Get the elements of `obj`, i.e. the set wrapped by a nanoset, or `obj`
+/// itself for any other iterable. Returns `None` for empty nanosets.
+fn elements(py: Python, obj: &PyAny) -> Option<PyObject> {
+    if let Ok(set) = obj.extract::<PyRef<NanoSet>>() {
+        set.inner.get(py).map(PyObject::from)
+    } else if let Ok(set) = obj.extract::<PyRef<PicoSet>>() {
+        set.inner.get(py).map(PyObject::from)
+    } else {
+        Some(obj.to_object(py))
+    }
+}
+
+/// Create a new set with the elements of `iterable`.
+fn new_set(py: Python, iterable: &PyObject) -> PyResult<Py<PySet>> {
+    super::new_set(py, Some(iterable.as_ref(py)))
+}
+
+/// Wrap `set` in a `NanoSet`, unless it is missing or empty.
+fn wrap(py: Python, set: Option<Py<PySet>>) -> PyResult<NanoSet> {
+    match set {
+        Some(set) if !set.as_ref(py).is_empty() => Ok(NanoSet::from_set(set)),
+        _ => Ok(NanoSet::new()),
+    }
+}
+
+/// Get a `NanoSet` with the elements of all the given sets.
+#[pyfunction]
+#[text_signature = "(sets)"]
+fn union_all(py: Python, sets: &PyAny) -> PyResult<NanoSet> {
+    let mut result: Option<Py<PySet>> = None;
+    for obj in sets.iter()? {
+        let elements = match elements(py, obj?) {
+            None => continue,
+            Some(elements) => elements,
+        };
+        match result {
+            None => result = Some(new_set(py, &elements)?),
+            Some(ref set) => {
+                set.as_ref(py).call_method1("update", (elements,))?;
+            }
+        }
+    }
+    wrap(py, result)
+}
+
+/// Get a `NanoSet` with the elements common to all the given sets.
+///
+/// The iterable is not consumed any further once the intersection is
+/// known to be empty. Returns an empty set if no set is given.
+#[pyfunction]
+#[text_signature = "(sets)"]
+fn intersection_all(py: Python, sets: &PyAny) -> PyResult<NanoSet> {
+    let mut result: Option<Py<PySet>> = None;
+    for obj in sets.iter()? {
+        let elements = match elements(py, obj?) {
+            None => return Ok(NanoSet::new()),
+            Some(elements) => elements,
+        };
+        let set = match result {
+            None => result.get_or_insert(new_set(py, &elements)?),
+            Some(ref set) => {
+                set.as_ref(py).call_method1("intersection_update", (elements,))?;
+                set
+            }
+        };
+        if set.as_ref(py).is_empty() {
+            return Ok(NanoSet::new());
+        }
+    }
+    wrap(py, result)
+}
+
+/// Add `item` to each of the given sets.
+///
+/// Nanosets are updated directly, and any other object must have an `add`
+/// method. Stops at the first error, leaving the previous sets modified.
+#[pyfunction]
+#[text_signature = "(sets, item)"]
+fn add_to_each(sets: &PyAny, item: &PyAny) -> PyResult<()> {
+    for obj in sets.iter()? {
+        let obj = obj?;
+        if let Ok(set) = obj.extract::<PyRef<NanoSet>>() {
+            set.add(item)?;
+        } else if let Ok(set) = obj.extract::<PyRef<PicoSet>>() {
+            set.add(item)?;
+        } else {
+            obj.call_method1("add", (item,))?;
+        }
+    }
+    Ok(())
+}
+
+/// Add the bulk operations to the `nanoset` module.
+pub fn init(m: &PyModule) -> PyResult<()> {
+    m.add_wrapped(pyo3::wrap_pyfunction!(union_all))?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(intersection_all))?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(add_to_each))?;
+    Ok(())
+}
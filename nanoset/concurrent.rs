@@ -0,0 +1,713 @@
+//! Concurrent sets of primitive keys, usable from Rust without the GIL.
+//!
+//! A `ConcurrentNanoSet` converts its elements to Rust-owned [`Key`]s when
+//! they are added, and stores them in a [`KeySet`] split into shards locked
+//! independently of each other. Rust code can get the key set of an instance
+//! with [`ConcurrentNanoSet::key_set`], and keep using it inside a closure
+//! given to `Python::allow_threads`, while Python threads keep using the
+//! instance like a builtin set. Locks are never held while Python code is
+//! running, so a thread never waits for longer than a hash table operation.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+use std::sync::RwLockWriteGuard;
+
+use pyo3::class::basic::CompareOp;
+use pyo3::class::PyIterProtocol;
+use pyo3::class::PyNumberProtocol;
+use pyo3::class::PyObjectProtocol;
+use pyo3::class::PySequenceProtocol;
+use pyo3::exceptions::KeyError;
+use pyo3::exceptions::RecursionError;
+use pyo3::exceptions::TypeError;
+use pyo3::prelude::*;
+use pyo3::type_object::PyTypeObject;
+use pyo3::types::PyAny;
+use pyo3::types::PyBytes;
+use pyo3::types::PyLong;
+use pyo3::types::PyString;
+use pyo3::types::PyTuple;
+use pyo3::types::PyType;
+use pyo3::PyNativeType;
+
+use super::order;
+use super::util;
+use super::util::as_set;
+use super::util::contains;
+use super::util::is_set;
+
+/// The number of shards of a key set.
+const SHARDS: usize = 16;
+
+/// The maximum nesting depth of the tuples converted to keys.
+const MAX_DEPTH: usize = 32;
+
+// ---------------------------------------------------------------------------
+
+/// An element of a `ConcurrentNanoSet`, owned by Rust.
+///
+/// Keys compare equal exactly when the Python objects they were converted
+/// from do, so a set of keys behaves like a set of these objects. Keys of
+/// different types are ordered by type name, like `sorted_iter` does.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Key {
+    Bytes(Vec<u8>),
+    Int(i64),
+    Str(String),
+    Tuple(Vec<Key>),
+}
+
+impl Key {
+    fn extract_nested(obj: &PyAny, depth: usize) -> PyResult<Self> {
+        if obj.downcast::<PyLong>().is_ok() {
+            obj.extract::<i64>().map(Key::Int)
+        } else if let Ok(s) = obj.downcast::<PyString>() {
+            s.to_string().map(|s| Key::Str(s.into_owned()))
+        } else if let Ok(b) = obj.downcast::<PyBytes>() {
+            Ok(Key::Bytes(b.as_bytes().to_vec()))
+        } else if let Ok(t) = obj.downcast::<PyTuple>() {
+            if depth >= MAX_DEPTH {
+                return RecursionError::into("ConcurrentNanoSet keys are nested too deeply");
+            }
+            t.iter()
+                .map(|item| Self::extract_nested(item, depth + 1))
+                .collect::<PyResult<Vec<_>>>()
+                .map(Key::Tuple)
+        } else {
+            TypeError::into(format!(
+                "ConcurrentNanoSet keys must be int, str, bytes or tuples of these, not '{}'",
+                obj.get_type().name(),
+            ))
+        }
+    }
+
+    /// Convert `obj` to a key to look it up, or get `None` if no key can
+    /// be equal to `obj`.
+    ///
+    /// Like with builtin sets, a `TypeError` is raised if `obj` is not
+    /// hashable, even if it could not be in the set anyway.
+    fn lookup(obj: &PyAny) -> PyResult<Option<Self>> {
+        match obj.extract::<Self>() {
+            Ok(key) => Ok(Some(key)),
+            Err(_) => obj.hash().map(|_| None),
+        }
+    }
+}
+
+impl<'source> FromPyObject<'source> for Key {
+    fn extract(obj: &'source PyAny) -> PyResult<Self> {
+        Self::extract_nested(obj, 0)
+    }
+}
+
+impl ToPyObject for Key {
+    fn to_object(&self, py: Python) -> PyObject {
+        match self {
+            Key::Int(n) => n.to_object(py),
+            Key::Str(s) => s.to_object(py),
+            Key::Bytes(b) => PyBytes::new(py, b).to_object(py),
+            Key::Tuple(t) => PyTuple::new(py, t.iter().map(|key| key.to_object(py))).to_object(py),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+type Shard = RwLock<HashSet<Key>>;
+
+/// A set of keys which can be shared and modified by several threads.
+///
+/// Keys are spread over several shards, each with its own lock, so that
+/// threads working with different keys rarely wait for each other. The
+/// shards are only allocated when the first key is inserted. Methods
+/// looking at all the shards, like `len` or `keys`, do not lock them all
+/// at once, and may miss concurrent modifications.
+#[derive(Debug, Default)]
+pub struct KeySet {
+    shards: OnceLock<Box<[Shard]>>,
+}
+
+impl KeySet {
+    /// Create a new empty key set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn index(key: &Key) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() as usize % SHARDS
+    }
+
+    fn shards(&self) -> &[Shard] {
+        match self.shards.get() {
+            Some(shards) => shards,
+            None => &[],
+        }
+    }
+
+    fn read(shard: &Shard) -> RwLockReadGuard<'_, HashSet<Key>> {
+        // the shards are never left in an inconsistent state by a panic
+        shard.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(shard: &Shard) -> RwLockWriteGuard<'_, HashSet<Key>> {
+        shard.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Add `key` to the set, returning whether it was not already there.
+    pub fn insert(&self, key: Key) -> bool {
+        let shards = self.shards.get_or_init(|| (0..SHARDS).map(|_| Shard::default()).collect());
+        Self::write(&shards[Self::index(&key)]).insert(key)
+    }
+
+    /// Remove `key` from the set, returning whether it was there.
+    pub fn remove(&self, key: &Key) -> bool {
+        match self.shards().get(Self::index(key)) {
+            Some(shard) => Self::write(shard).remove(key),
+            None => false,
+        }
+    }
+
+    /// Check whether `key` is in the set.
+    pub fn contains(&self, key: &Key) -> bool {
+        match self.shards().get(Self::index(key)) {
+            Some(shard) => Self::read(shard).contains(key),
+            None => false,
+        }
+    }
+
+    /// Remove and return an arbitrary key from the set, if any.
+    pub fn pop(&self) -> Option<Key> {
+        self.shards().iter().find_map(|shard| {
+            let mut keys = Self::write(shard);
+            let key = keys.iter().next().cloned()?;
+            keys.remove(&key);
+            Some(key)
+        })
+    }
+
+    /// Remove all the keys from the set.
+    pub fn clear(&self) {
+        for shard in self.shards() {
+            // release the memory of the shard, and not only its keys
+            *Self::write(shard) = HashSet::new();
+        }
+    }
+
+    /// Keep only the keys for which `f` returns `true`.
+    ///
+    /// `f` is called while a shard is locked, so it must not use the set.
+    pub fn retain<F: FnMut(&Key) -> bool>(&self, mut f: F) {
+        for shard in self.shards() {
+            Self::write(shard).retain(|key| f(key));
+        }
+    }
+
+    /// Get the number of keys in the set.
+    pub fn len(&self) -> usize {
+        self.shards().iter().map(|shard| Self::read(shard).len()).sum()
+    }
+
+    /// Check whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.shards().iter().all(|shard| Self::read(shard).is_empty())
+    }
+
+    /// Get a copy of the keys in the set.
+    pub fn keys(&self) -> Vec<Key> {
+        let mut keys = Vec::new();
+        for shard in self.shards() {
+            keys.extend(Self::read(shard).iter().cloned());
+        }
+        keys
+    }
+}
+
+impl Clone for KeySet {
+    fn clone(&self) -> Self {
+        let copy = Self::new();
+        for key in self.keys() {
+            copy.insert(key);
+        }
+        copy
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Get the key set of `obj`, if it is a `ConcurrentNanoSet`.
+fn key_set_of(obj: &PyAny) -> PyResult<Option<Arc<KeySet>>> {
+    match obj.downcast::<PyCell<ConcurrentNanoSet>>() {
+        Err(_) => Ok(None),
+        Ok(cell) => Ok(Some(cell.try_borrow()?.key_set())),
+    }
+}
+
+/// Get the elements of `obj` which can be in a key set, deduplicated.
+fn lookup_all(obj: &PyAny) -> PyResult<HashSet<Key>> {
+    if let Some(keys) = key_set_of(obj)? {
+        return Ok(keys.keys().into_iter().collect());
+    }
+    let mut keys = HashSet::new();
+    for item in obj.iter()? {
+        keys.extend(Key::lookup(item?)?);
+    }
+    Ok(keys)
+}
+
+#[pyclass(module = "nanoset")]
+#[text_signature = "(iterable=None)"]
+#[derive(Debug, Default)]
+/// A mutable set of `int`, `str` and `bytes` keys, and of tuples of these,
+/// which can be used from Rust without holding the GIL.
+///
+/// Elements are converted to Rust values when added, so instances of
+/// subclasses are stored as their base type, e.g. `True` as `1`. Integers
+/// must fit in 64 bits. Iterating over the set gives the elements it had
+/// when the iteration started, regardless of concurrent modifications.
+pub struct ConcurrentNanoSet {
+    keys: Arc<KeySet>,
+}
+
+impl ConcurrentNanoSet {
+    /// Create a new set wrapping `keys`, which can be shared with Rust code.
+    pub fn from_key_set(keys: Arc<KeySet>) -> Self {
+        Self { keys }
+    }
+
+    /// Get the keys of the set, to use them from Rust without the GIL.
+    pub fn key_set(&self) -> Arc<KeySet> {
+        self.keys.clone()
+    }
+
+    /// Get a copy of the keys, in order in deterministic mode.
+    fn snapshot(&self) -> Vec<Key> {
+        let mut keys = self.keys.keys();
+        if order::is_deterministic() {
+            keys.sort();
+        }
+        keys
+    }
+
+    /// Add the elements of `other` to the set.
+    fn extend(&self, other: &PyAny) -> PyResult<()> {
+        if let Some(keys) = key_set_of(other)? {
+            for key in keys.keys() {
+                self.keys.insert(key);
+            }
+            return Ok(());
+        }
+        for item in other.iter()? {
+            self.keys.insert(item?.extract()?);
+        }
+        Ok(())
+    }
+
+    /// Check whether all the elements of the set are in `other`.
+    fn is_within(&self, other: &PyAny) -> PyResult<bool> {
+        if let Some(keys) = key_set_of(other)? {
+            return Ok(self.keys.keys().iter().all(|key| keys.contains(key)));
+        }
+        let py = other.py();
+        for key in self.keys.keys() {
+            if !contains(other, key.to_object(py).as_ref(py))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Check whether all the elements of `other` are in the set.
+    fn covers(&self, other: &PyAny) -> PyResult<bool> {
+        for item in other.iter()? {
+            match Key::lookup(item?)? {
+                Some(key) if self.keys.contains(&key) => (),
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[pymethods]
+impl ConcurrentNanoSet {
+    #[new]
+    fn __new__(iterable: Option<&PyAny>) -> PyResult<Self> {
+        let set = Self::default();
+        if let Some(it) = iterable {
+            set.extend(it)?;
+        }
+        Ok(set)
+    }
+
+    #[classmethod]
+    #[text_signature = "($cls, item)"]
+    fn __class_getitem__(cls: &PyType, item: &PyAny) -> PyResult<PyObject> {
+        util::class_getitem(cls, item)
+    }
+
+    #[text_signature = "($self)"]
+    fn __reduce__(&self) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let ty = Self::type_object(py);
+        let keys = self.snapshot();
+        if keys.is_empty() {
+            return Ok((ty, PyTuple::empty(py)).to_object(py));
+        }
+        let items = PyTuple::new(py, keys.iter().map(|key| key.to_object(py)));
+        Ok((ty, (items,)).to_object(py))
+    }
+
+    #[text_signature = "($self)"]
+    fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    #[text_signature = "($self)"]
+    fn copy(&self) -> Self {
+        Self::from_key_set(Arc::new(KeySet::clone(&self.keys)))
+    }
+
+    #[text_signature = "($self, item)"]
+    fn add(&self, item: &PyAny) -> PyResult<()> {
+        self.keys.insert(item.extract()?);
+        Ok(())
+    }
+
+    #[text_signature = "($self)"]
+    fn clear(&self) {
+        self.keys.clear()
+    }
+
+    #[text_signature = "($self, item)"]
+    fn discard(&self, item: &PyAny) -> PyResult<()> {
+        if let Some(key) = Key::lookup(item)? {
+            self.keys.remove(&key);
+        }
+        Ok(())
+    }
+
+    #[text_signature = "($self, item)"]
+    fn remove(&self, item: &PyAny) -> PyResult<()> {
+        match Key::lookup(item)? {
+            Some(ref key) if self.keys.remove(key) => Ok(()),
+            _ => KeyError::into(item.to_object(item.py())),
+        }
+    }
+
+    #[text_signature = "($self)"]
+    fn pop(&self) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        match self.keys.pop() {
+            Some(key) => Ok(key.to_object(py)),
+            None => KeyError::into("pop from an empty set"),
+        }
+    }
+
+    #[args(others = "*")]
+    #[text_signature = "($self, *others)"]
+    fn update(&self, others: &PyTuple) -> PyResult<()> {
+        others.iter().try_for_each(|other| self.extend(other))
+    }
+
+    #[args(others = "*")]
+    #[text_signature = "($self, *others)"]
+    fn difference_update(&self, others: &PyTuple) -> PyResult<()> {
+        for other in others.iter() {
+            for key in lookup_all(other)? {
+                self.keys.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    #[args(others = "*")]
+    #[text_signature = "($self, *others)"]
+    fn intersection_update(&self, others: &PyTuple) -> PyResult<()> {
+        let mut others = others
+            .iter()
+            .map(lookup_all)
+            .collect::<PyResult<Vec<_>>>()?;
+        // only keep the keys found in every other iterable
+        if let Some(mut kept) = others.pop() {
+            for other in others {
+                kept.retain(|key| other.contains(key));
+            }
+            self.keys.retain(|key| kept.contains(key));
+        }
+        Ok(())
+    }
+
+    #[text_signature = "($self, other)"]
+    fn symmetric_difference_update(&self, other: &PyAny) -> PyResult<()> {
+        // deduplicate the elements of `other` first, so that an element
+        // given twice is not toggled twice
+        let keys = match key_set_of(other)? {
+            Some(keys) => keys.keys(),
+            None => {
+                let mut keys = HashSet::new();
+                for item in other.iter()? {
+                    keys.insert(item?.extract::<Key>()?);
+                }
+                keys.into_iter().collect()
+            }
+        };
+        for key in keys {
+            if !self.keys.remove(&key) {
+                self.keys.insert(key);
+            }
+        }
+        Ok(())
+    }
+
+    #[args(others = "*")]
+    #[text_signature = "($self, *others)"]
+    fn difference(&self, others: &PyTuple) -> PyResult<Self> {
+        let set = self.copy();
+        set.difference_update(others)?;
+        Ok(set)
+    }
+
+    #[args(others = "*")]
+    #[text_signature = "($self, *others)"]
+    fn intersection(&self, others: &PyTuple) -> PyResult<Self> {
+        let set = self.copy();
+        set.intersection_update(others)?;
+        Ok(set)
+    }
+
+    #[text_signature = "($self, other)"]
+    fn symmetric_difference(&self, other: &PyAny) -> PyResult<Self> {
+        let set = self.copy();
+        set.symmetric_difference_update(other)?;
+        Ok(set)
+    }
+
+    #[args(others = "*")]
+    #[text_signature = "($self, *others)"]
+    fn union(&self, others: &PyTuple) -> PyResult<Self> {
+        let set = self.copy();
+        set.update(others)?;
+        Ok(set)
+    }
+
+    #[text_signature = "($self, other)"]
+    fn isdisjoint(&self, other: &PyAny) -> PyResult<bool> {
+        for item in other.iter()? {
+            if let Some(key) = Key::lookup(item?)? {
+                if self.keys.contains(&key) {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    #[text_signature = "($self, other)"]
+    fn issubset(&self, other: &PyAny) -> PyResult<bool> {
+        let other = as_set(other)?;
+        Ok(self.keys.len() <= other.len()? && self.is_within(other)?)
+    }
+
+    #[text_signature = "($self, other)"]
+    fn issuperset(&self, other: &PyAny) -> PyResult<bool> {
+        self.covers(other)
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for ConcurrentNanoSet {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<ConcurrentNanoSetIterator> {
+        Ok(ConcurrentNanoSetIterator {
+            keys: slf.snapshot().into_iter(),
+        })
+    }
+}
+
+#[pyproto]
+impl PyNumberProtocol for ConcurrentNanoSet {
+    fn __and__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, _) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        let args: Py<PyTuple> = (other,).into_py(py);
+        this.intersection(args.as_ref(py))
+            .and_then(|s| Py::new(py, s))
+            .map(PyObject::from)
+    }
+
+    fn __sub__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, reflected) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        let result = if reflected {
+            // `other` is on the left of the operator, and `rhs` is us
+            let result = Self::default();
+            result.extend(other)?;
+            for key in this.keys.keys() {
+                result.keys.remove(&key);
+            }
+            result
+        } else {
+            let args: Py<PyTuple> = (other,).into_py(py);
+            this.difference(args.as_ref(py))?
+        };
+        Py::new(py, result).map(PyObject::from)
+    }
+
+    fn __or__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, _) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        let args: Py<PyTuple> = (other,).into_py(py);
+        this.union(args.as_ref(py))
+            .and_then(|s| Py::new(py, s))
+            .map(PyObject::from)
+    }
+
+    fn __xor__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, _) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        this.symmetric_difference(other)
+            .and_then(|s| Py::new(py, s))
+            .map(PyObject::from)
+    }
+
+    fn __iand__(&mut self, other: &PyAny) -> PyResult<()> {
+        match util::inplace_operand(self, other, "&=", is_set)? {
+            None => Ok(()),
+            Some(other) => self.intersection_update(PyTuple::new(other.py(), [other])),
+        }
+    }
+
+    fn __isub__(&mut self, other: &PyAny) -> PyResult<()> {
+        match util::inplace_operand(self, other, "-=", is_set)? {
+            None => {
+                self.clear();
+                Ok(())
+            }
+            Some(other) => self.difference_update(PyTuple::new(other.py(), [other])),
+        }
+    }
+
+    fn __ior__(&mut self, other: &PyAny) -> PyResult<()> {
+        match util::inplace_operand(self, other, "|=", is_set)? {
+            None => Ok(()),
+            Some(other) => self.update(PyTuple::new(other.py(), [other])),
+        }
+    }
+
+    fn __ixor__(&mut self, other: &PyAny) -> PyResult<()> {
+        match util::inplace_operand(self, other, "^=", is_set)? {
+            None => {
+                self.clear();
+                Ok(())
+            }
+            Some(other) => self.symmetric_difference_update(other),
+        }
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for ConcurrentNanoSet {
+    fn __repr__(&self) -> PyResult<String> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let keys = self.snapshot();
+        if keys.is_empty() {
+            return Ok("ConcurrentNanoSet()".to_string());
+        }
+        // keys cannot contain the set, so there is no recursion to detect
+        let items = keys
+            .iter()
+            .map(|key| key.to_object(py).as_ref(py).repr().map(|r| r.to_string_lossy().into_owned()))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(format!("ConcurrentNanoSet({{{}}})", items.join(", ")))
+    }
+
+    fn __bool__(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp) -> PyResult<PyObject> {
+        use self::CompareOp::*;
+
+        let py = other.py();
+        if !is_set(other)? {
+            return Ok(py.NotImplemented());
+        }
+
+        let (len, other_len) = (self.keys.len(), other.len()?);
+        let result = match op {
+            Eq => len == other_len && self.is_within(other)?,
+            Ne => len != other_len || !self.is_within(other)?,
+            Le => len <= other_len && self.is_within(other)?,
+            Lt => len < other_len && self.is_within(other)?,
+            Ge => len >= other_len && self.covers(other)?,
+            Gt => len > other_len && self.covers(other)?,
+        };
+        Ok(result.to_object(py))
+    }
+}
+
+#[pyproto]
+impl PySequenceProtocol for ConcurrentNanoSet {
+    fn __len__(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn __contains__(&self, item: &PyAny) -> PyResult<bool> {
+        Ok(Key::lookup(item)?.is_some_and(|key| self.keys.contains(&key)))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+#[pyclass(module = "nanoset")]
+#[derive(Debug)]
+/// An iterator over a snapshot of the elements of a `ConcurrentNanoSet`.
+pub struct ConcurrentNanoSetIterator {
+    keys: std::vec::IntoIter<Key>,
+}
+
+#[pyproto]
+impl PyIterProtocol for ConcurrentNanoSetIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<Py<Self>> {
+        Ok(slf.into())
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> Option<PyObject> {
+        let key = slf.keys.next()?;
+        Some(key.to_object(slf.py()))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Add the concurrent set types to the `nanoset` module.
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<ConcurrentNanoSet>()?;
+    m.add_class::<ConcurrentNanoSetIterator>()?;
+    let set = py.import("collections.abc")?.get("MutableSet")?;
+    set.call_method1("register", (ConcurrentNanoSet::type_object(py),))?;
+    Ok(())
+}
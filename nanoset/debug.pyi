@@ -0,0 +1,8 @@
+# Type stubs for the `nanoset.debug` module.
+
+from typing import Any, Iterator, Set
+
+ENABLED: bool
+TRACEMALLOC_DOMAIN: int
+
+def iter_hidden_sets() -> Iterator[Set[Any]]: ...
@@ -0,0 +1,139 @@
+//! Debugging helpers to inspect the sets hidden inside `PicoSet` instances.
+//!
+//! `PicoSet` does not support the garbage collector protocol, so the sets
+//! it wraps cannot be found by heap profilers walking the GC graph. With the
+//! `registry` feature, the address of every set stored in a `PicoSet` is
+//! kept in a global registry, and the set is traced by `tracemalloc` in a
+//! dedicated domain. Without the feature, the tracking functions are no-ops.
+//!
+//! The allocations of the sets are already traced by `tracemalloc` in the
+//! default domain `0`, so the traces of the dedicated domain are duplicates
+//! meant to attribute that memory to `PicoSet`: snapshots must be filtered
+//! with `tracemalloc.DomainFilter(False, TRACEMALLOC_DOMAIN)` to compute the
+//! total memory usage, and with `DomainFilter(True, TRACEMALLOC_DOMAIN)` to
+//! only get the sets hidden in `PicoSet` instances.
+
+#[cfg(feature = "registry")]
+use std::collections::HashMap;
+#[cfg(feature = "registry")]
+use std::sync::Mutex;
+
+#[cfg(not(feature = "registry"))]
+use pyo3::exceptions::RuntimeError;
+use pyo3::prelude::*;
+#[cfg(feature = "registry")]
+use pyo3::types::PyList;
+
+/// The `tracemalloc` domain used to trace the sets hidden in a `PicoSet`,
+/// which duplicates their traces in the default domain.
+pub const TRACEMALLOC_DOMAIN: std::os::raw::c_uint = 0x6e61_6e6f;
+
+#[cfg(feature = "registry")]
+extern "C" {
+    fn PyTraceMalloc_Track(
+        domain: std::os::raw::c_uint,
+        ptr: usize,
+        size: usize,
+    ) -> std::os::raw::c_int;
+    fn PyTraceMalloc_Untrack(domain: std::os::raw::c_uint, ptr: usize) -> std::os::raw::c_int;
+}
+
+/// The addresses of all the sets currently stored in a `PicoSet`, with the
+/// number of slots they are stored in, since copy-on-write copies share
+/// the same set.
+#[cfg(feature = "registry")]
+static REGISTRY: Mutex<Option<HashMap<usize, usize>>> = Mutex::new(None);
+
+/// Register a set stored in a hidden slot.
+#[cfg(feature = "registry")]
+pub fn track(ptr: *mut pyo3::ffi::PyObject) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        let count = registry.get_or_insert_with(HashMap::new).entry(ptr as usize).or_insert(0);
+        *count += 1;
+        // a set shared by several slots is only traced once
+        if *count > 1 {
+            return;
+        }
+    }
+
+    trace(ptr);
+}
+
+/// Update the size traced for a registered set after it was modified.
+#[cfg(feature = "registry")]
+pub fn retrack(ptr: *mut pyo3::ffi::PyObject) {
+    let registered = match REGISTRY.lock() {
+        Ok(registry) => registry.as_ref().is_some_and(|sets| sets.contains_key(&(ptr as usize))),
+        Err(_) => false,
+    };
+    if registered {
+        trace(ptr);
+    }
+}
+
+/// Trace the set at `ptr` with its current size, replacing its previous
+/// trace if any.
+#[cfg(feature = "registry")]
+fn trace(ptr: *mut pyo3::ffi::PyObject) {
+    let py = unsafe { Python::assume_gil_acquired() };
+    let obj = unsafe { py.from_borrowed_ptr::<PyAny>(ptr) };
+    if let Ok(size) = obj.call_method0("__sizeof__").and_then(PyAny::extract::<usize>) {
+        unsafe { PyTraceMalloc_Track(TRACEMALLOC_DOMAIN, ptr as usize, size) };
+    }
+}
+
+/// Unregister a set removed from a hidden slot.
+#[cfg(feature = "registry")]
+pub fn untrack(ptr: *mut pyo3::ffi::PyObject) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        if let Some(sets) = registry.as_mut() {
+            // only untrack the set once it is removed from its last slot
+            if let Some(count) = sets.get_mut(&(ptr as usize)) {
+                *count -= 1;
+                if *count > 0 {
+                    return;
+                }
+            }
+            sets.remove(&(ptr as usize));
+        }
+    }
+    unsafe {
+        PyTraceMalloc_Untrack(TRACEMALLOC_DOMAIN, ptr as usize);
+    }
+}
+
+/// Iterate over the sets wrapped by all live `PicoSet` instances.
+///
+/// The sets are the actual objects stored in the `PicoSet` instances, and
+/// must not be modified. Raises a `RuntimeError` if `nanoset` was built
+/// without the `registry` feature.
+#[pyfunction]
+#[text_signature = "()"]
+fn iter_hidden_sets(py: Python) -> PyResult<PyObject> {
+    #[cfg(feature = "registry")]
+    {
+        // take new references while the registry is locked, so that no set
+        // can be released before we own a reference to it
+        let sets = PyList::empty(py);
+        if let Ok(registry) = REGISTRY.lock() {
+            for &ptr in registry.iter().flat_map(HashMap::keys) {
+                let set = unsafe { PyObject::from_borrowed_ptr(py, ptr as *mut _) };
+                sets.append(set)?;
+            }
+        }
+        sets.to_object(py).call_method0(py, "__iter__")
+    }
+    #[cfg(not(feature = "registry"))]
+    {
+        let _ = py;
+        RuntimeError::into("nanoset was built without the `registry` feature")
+    }
+}
+
+/// Add the debugging helpers to the `nanoset.debug` module.
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add("ENABLED", cfg!(feature = "registry"))?;
+    m.add("TRACEMALLOC_DOMAIN", TRACEMALLOC_DOMAIN)?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(iter_hidden_sets))?;
+    Ok(())
+}
@@ -0,0 +1,144 @@
+//! Factory selecting either `NanoSet` or `PicoSet` for the current process.
+//!
+//! The implementation is selected when `nanoset.Set` is first called, using
+//! the `NANOSET_IMPL` environment variable if it is set, or `PicoSet` when
+//! Python runs with optimizations enabled and `NanoSet` otherwise, unless
+//! an implementation was chosen explicitly with `nanoset.configure`.
+
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+
+use pyo3::exceptions::TypeError;
+use pyo3::exceptions::ValueError;
+use pyo3::prelude::*;
+use pyo3::type_object::PyTypeObject;
+use pyo3::types::PyAny;
+use pyo3::types::PyDict;
+use pyo3::types::PyTuple;
+use pyo3::types::PyType;
+use pyo3::AsPyPointer;
+
+use super::NanoSet;
+use super::PicoSet;
+
+const UNRESOLVED: u8 = 0;
+const NANOSET: u8 = 1;
+const PICOSET: u8 = 2;
+
+/// The implementation used by `nanoset.Set`, or `UNRESOLVED`.
+static IMPL: AtomicU8 = AtomicU8::new(UNRESOLVED);
+
+/// Get the implementation with the given name, ignoring case.
+fn from_name(name: &str) -> PyResult<u8> {
+    match name.to_lowercase().as_str() {
+        "nanoset" => Ok(NANOSET),
+        "picoset" => Ok(PICOSET),
+        _ => ValueError::into(format!(
+            "unknown nanoset implementation: {:?} (expected 'NanoSet' or 'PicoSet')",
+            name
+        )),
+    }
+}
+
+/// Detect the implementation to use from the environment.
+fn detect(py: Python) -> PyResult<u8> {
+    if let Some(name) = std::env::var_os("NANOSET_IMPL") {
+        return from_name(&name.to_string_lossy());
+    }
+    let optimize: i64 = py.import("sys")?.get("flags")?.getattr("optimize")?.extract()?;
+    if optimize > 0 {
+        Ok(PICOSET)
+    } else {
+        Ok(NANOSET)
+    }
+}
+
+/// Get the type selected for `nanoset.Set`, detecting it if needed.
+fn resolve(py: Python<'_>) -> PyResult<&PyType> {
+    let mut selected = IMPL.load(Ordering::Acquire);
+    if selected == UNRESOLVED {
+        let detected = detect(py)?;
+        // keep the implementation selected by another thread, if any
+        let (order, failure) = (Ordering::AcqRel, Ordering::Acquire);
+        selected = match IMPL.compare_exchange(UNRESOLVED, detected, order, failure) {
+            Ok(_) => detected,
+            Err(current) => current,
+        };
+    }
+    match selected {
+        PICOSET => Ok(PicoSet::type_object(py)),
+        _ => Ok(NanoSet::type_object(py)),
+    }
+}
+
+/// Create a new instance of the selected implementation.
+#[pyfunction]
+#[text_signature = "(cls, iterable=None)"]
+fn new(py: Python, _cls: &PyAny, iterable: Option<&PyAny>) -> PyResult<PyObject> {
+    let ty = resolve(py)?;
+    match iterable {
+        None => ty.call0(),
+        Some(it) => ty.call1((it,)),
+    }
+    .map(|obj| obj.to_object(py))
+}
+
+/// Select the implementation returned by `nanoset.Set`.
+///
+/// `impl` can be either `NanoSet` or `PicoSet`, or the name of one of these
+/// types. Passing `None` restores the automatic selection. This should be
+/// called before `nanoset.Set` is first used, since existing instances are
+/// not converted to the new implementation.
+#[pyfunction(args = "*", kwargs = "**")]
+#[text_signature = "(impl)"]
+fn configure(py: Python, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+    // `impl` is a Rust keyword, so the argument is extracted manually
+    let value = match (args.len(), kwargs.and_then(|kw| kw.get_item("impl"))) {
+        (1, None) => args.get_item(0),
+        (0, Some(value)) => value,
+        _ => return TypeError::into("configure() takes exactly one argument: 'impl'"),
+    };
+    if kwargs.map(|kw| kw.len()).unwrap_or(0) > 1 {
+        return TypeError::into("configure() got an unexpected keyword argument");
+    }
+
+    let selected = if value.is_none() {
+        UNRESOLVED
+    } else if value.as_ptr() == NanoSet::type_object(py).as_ptr() {
+        NANOSET
+    } else if value.as_ptr() == PicoSet::type_object(py).as_ptr() {
+        PICOSET
+    } else if let Ok(name) = value.extract::<&str>() {
+        from_name(name)?
+    } else {
+        return TypeError::into("expected NanoSet, PicoSet, a type name or None");
+    };
+
+    IMPL.store(selected, Ordering::Release);
+    Ok(())
+}
+
+/// Add `Set` and `configure` to the `nanoset` module.
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+    let builtins = py.import("builtins")?;
+
+    // create `Set` as an abstract class with both implementations registered
+    // as virtual subclasses, so that `isinstance` works with either of them
+    let ns = PyDict::new(py);
+    let new = builtins.call1("staticmethod", (pyo3::wrap_pyfunction!(new)(py),))?;
+    ns.set_item("__module__", "nanoset")?;
+    ns.set_item("__doc__", "A set using the implementation selected for this process.")?;
+    ns.set_item("__slots__", PyTuple::empty(py))?;
+    ns.set_item("__new__", new)?;
+    if let Ok(alias) = py.import("types")?.getattr("GenericAlias") {
+        ns.set_item("__class_getitem__", builtins.call1("classmethod", (alias,))?)?;
+    }
+    let bases = (py.import("collections.abc")?.get("MutableSet")?,);
+    let set = py.import("abc")?.call1("ABCMeta", ("Set", bases, ns))?;
+    set.call_method1("register", (NanoSet::type_object(py),))?;
+    set.call_method1("register", (PicoSet::type_object(py),))?;
+
+    m.add("Set", set)?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(configure))?;
+    Ok(())
+}
@@ -0,0 +1,339 @@
+//! Hash array mapped trie storing Python objects, used by persistent sets.
+//!
+//! Nodes are reference-counted and never modified once shared: a mutation
+//! copies the nodes on the path to the modified key if they are shared, and
+//! modifies them in place otherwise, so that persistent sets and their
+//! evolvers can share the same implementation. Python code is only ever run
+//! to hash and compare keys, never while a node is being modified.
+
+use std::sync::Arc;
+
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use pyo3::AsPyPointer;
+use pyo3::PyNativeType;
+
+/// The number of hash bits used at each level of the trie.
+const BITS: u32 = 5;
+/// The mask to extract the hash bits used at a single level.
+const MASK: u64 = (1 << BITS) - 1;
+
+/// An entry of a branch node.
+#[derive(Clone, Debug)]
+enum Entry {
+    /// A single key, along with its hash.
+    Key(u64, PyObject),
+    /// A subtree with all the keys sharing the same hash prefix.
+    Node(Arc<Node>),
+}
+
+/// A node of the trie.
+///
+/// Subtrees always contain at least two keys: a subtree left with a single
+/// key after a removal is replaced by that key in its parent.
+#[derive(Clone, Debug)]
+enum Node {
+    /// Entries indexed by a bitmap of the hash bits used at this level.
+    Branch(u32, Vec<Entry>),
+    /// Keys with exactly the same hash.
+    Collision(u64, Vec<PyObject>),
+}
+
+/// Get the hash bits of `hash` used at the level starting at `shift`.
+#[inline]
+fn fragment(hash: u64, shift: u32) -> u32 {
+    ((hash >> shift) & MASK) as u32
+}
+
+/// Get the position of the entry with the given bit in a branch.
+#[inline]
+fn position(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+/// Compare two keys for equality, like sets do.
+fn eq(stored: &PyObject, key: &PyAny) -> PyResult<bool> {
+    let py = key.py();
+    match unsafe { pyo3::ffi::PyObject_RichCompareBool(stored.as_ptr(), key.as_ptr(), pyo3::ffi::Py_EQ) } {
+        -1 => Err(PyErr::fetch(py)),
+        n => Ok(n != 0),
+    }
+}
+
+/// Hash a key, as an unsigned value to index the trie.
+fn hash(key: &PyAny) -> PyResult<u64> {
+    key.hash().map(|h| h as u64)
+}
+
+/// Build a node holding two entries with different hashes.
+fn split(h1: u64, e1: Entry, h2: u64, e2: Entry, shift: u32) -> Node {
+    let (f1, f2) = (fragment(h1, shift), fragment(h2, shift));
+    if f1 == f2 {
+        let child = split(h1, e1, h2, e2, shift + BITS);
+        Node::Branch(1 << f1, vec![Entry::Node(Arc::new(child))])
+    } else if f1 < f2 {
+        Node::Branch((1 << f1) | (1 << f2), vec![e1, e2])
+    } else {
+        Node::Branch((1 << f1) | (1 << f2), vec![e2, e1])
+    }
+}
+
+impl Node {
+    /// Find the stored key equal to `key`, if any.
+    fn find<'a>(mut node: &'a Node, h: u64, key: &PyAny) -> PyResult<Option<&'a PyObject>> {
+        let mut shift = 0;
+        loop {
+            match node {
+                Node::Branch(bitmap, entries) => {
+                    let bit = 1 << fragment(h, shift);
+                    if bitmap & bit == 0 {
+                        return Ok(None);
+                    }
+                    match entries[position(*bitmap, bit)] {
+                        Entry::Key(stored_hash, ref stored) => {
+                            let found = stored_hash == h && eq(stored, key)?;
+                            return Ok(if found { Some(stored) } else { None });
+                        }
+                        Entry::Node(ref child) => {
+                            node = child;
+                            shift += BITS;
+                        }
+                    }
+                }
+                Node::Collision(stored_hash, keys) => {
+                    if *stored_hash == h {
+                        for stored in keys {
+                            if eq(stored, key)? {
+                                return Ok(Some(stored));
+                            }
+                        }
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Insert a key known not to be in the subtree rooted at `node`.
+    fn insert(node: &mut Arc<Node>, h: u64, key: PyObject, shift: u32) {
+        // a collision node for another hash is split before anything else
+        if let Node::Collision(stored_hash, _) = **node {
+            if stored_hash != h {
+                let old = Entry::Node(node.clone());
+                *node = Arc::new(split(stored_hash, old, h, Entry::Key(h, key), shift));
+                return;
+            }
+        }
+
+        match Arc::make_mut(node) {
+            Node::Collision(_, keys) => keys.push(key),
+            Node::Branch(bitmap, entries) => {
+                let bit = 1 << fragment(h, shift);
+                let i = position(*bitmap, bit);
+                if *bitmap & bit == 0 {
+                    *bitmap |= bit;
+                    entries.insert(i, Entry::Key(h, key));
+                    return;
+                }
+                let replacement = match entries[i] {
+                    Entry::Node(ref mut child) => return Node::insert(child, h, key, shift + BITS),
+                    Entry::Key(stored_hash, ref stored) if stored_hash == h => {
+                        Node::Collision(h, vec![stored.clone(), key])
+                    }
+                    Entry::Key(stored_hash, ref stored) => {
+                        let old = Entry::Key(stored_hash, stored.clone());
+                        split(stored_hash, old, h, Entry::Key(h, key), shift + BITS)
+                    }
+                };
+                entries[i] = Entry::Node(Arc::new(replacement));
+            }
+        }
+    }
+
+    /// Remove the stored key `ptr` from the subtree rooted at `node`.
+    ///
+    /// Returns the previous key, which must be dropped by the caller once
+    /// the trie is consistent again.
+    fn remove(node: &mut Arc<Node>, h: u64, ptr: *mut pyo3::ffi::PyObject, shift: u32) -> Option<PyObject> {
+        match Arc::make_mut(node) {
+            Node::Collision(_, keys) => {
+                let i = keys.iter().position(|k| k.as_ptr() == ptr)?;
+                Some(keys.remove(i))
+            }
+            Node::Branch(bitmap, entries) => {
+                let bit = 1 << fragment(h, shift);
+                if *bitmap & bit == 0 {
+                    return None;
+                }
+                let i = position(*bitmap, bit);
+                match entries[i] {
+                    Entry::Key(_, ref stored) if stored.as_ptr() == ptr => (),
+                    Entry::Key(..) => return None,
+                    Entry::Node(ref mut child) => {
+                        let removed = Node::remove(child, h, ptr, shift + BITS)?;
+                        if let Some(entry) = child.single() {
+                            entries[i] = entry;
+                        }
+                        return Some(removed);
+                    }
+                }
+                // the key was stored directly in this branch
+                *bitmap &= !bit;
+                match entries.remove(i) {
+                    Entry::Key(_, stored) => Some(stored),
+                    Entry::Node(_) => None,
+                }
+            }
+        }
+    }
+
+    /// Get the only entry of a subtree left with a single key.
+    fn single(&self) -> Option<Entry> {
+        match self {
+            Node::Branch(_, entries) if entries.len() == 1 => match entries[0] {
+                Entry::Key(..) => Some(entries[0].clone()),
+                Entry::Node(_) => None,
+            },
+            Node::Collision(h, keys) if keys.len() == 1 => Some(Entry::Key(*h, keys[0].clone())),
+            _ => None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// A set of Python objects stored in a hash array mapped trie.
+///
+/// Cloning a tree only clones a reference to its root, and an empty tree
+/// does not allocate anything.
+#[derive(Clone, Debug, Default)]
+pub struct Tree {
+    root: Option<Arc<Node>>,
+    len: usize,
+}
+
+impl Tree {
+    /// Create a tree with all the elements of `iterable`.
+    pub fn from_iterable(iterable: &PyAny) -> PyResult<Self> {
+        let mut tree = Self::default();
+        for item in iterable.iter()? {
+            tree.add(item?)?;
+        }
+        Ok(tree)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Check whether both trees are the same object.
+    pub fn same(&self, other: &Tree) -> bool {
+        match (&self.root, &other.root) {
+            (Some(l), Some(r)) => Arc::ptr_eq(l, r),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    pub fn contains(&self, key: &PyAny) -> PyResult<bool> {
+        // keep the nodes alive while keys are compared, since `__eq__` may
+        // run arbitrary code
+        match self.root.clone() {
+            None => Ok(false),
+            Some(root) => Ok(Node::find(&root, hash(key)?, key)?.is_some()),
+        }
+    }
+
+    /// Add `key` to the tree, returning whether it was not already present.
+    pub fn add(&mut self, key: &PyAny) -> PyResult<bool> {
+        let h = hash(key)?;
+        if let Some(root) = self.root.clone() {
+            if Node::find(&root, h, key)?.is_some() {
+                return Ok(false);
+            }
+        }
+        let key = key.to_object(key.py());
+        match self.root {
+            Some(ref mut root) => Node::insert(root, h, key, 0),
+            None => {
+                let branch = Node::Branch(1 << fragment(h, 0), vec![Entry::Key(h, key)]);
+                self.root = Some(Arc::new(branch));
+            }
+        }
+        self.len += 1;
+        Ok(true)
+    }
+
+    /// Remove `key` from the tree, returning whether it was present.
+    pub fn discard(&mut self, key: &PyAny) -> PyResult<bool> {
+        let h = hash(key)?;
+        let root = match self.root.clone() {
+            None => return Ok(false),
+            Some(root) => root,
+        };
+        let ptr = match Node::find(&root, h, key)? {
+            None => return Ok(false),
+            Some(stored) => stored.as_ptr(),
+        };
+        drop(root);
+
+        // the root is the only node allowed to hold less than two keys
+        let removed = self.root.as_mut().and_then(|root| Node::remove(root, h, ptr, 0));
+        if removed.is_some() {
+            self.len -= 1;
+            if self.len == 0 {
+                self.root = None;
+            }
+        }
+        Ok(removed.is_some())
+    }
+
+    /// Get a cursor over the keys of the tree.
+    pub fn keys(&self) -> Cursor {
+        Cursor {
+            stack: self.root.iter().map(|root| (root.clone(), 0)).collect(),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// A cursor over the keys of a tree, keeping the visited nodes alive.
+#[derive(Debug, Default)]
+pub struct Cursor {
+    stack: Vec<(Arc<Node>, usize)>,
+}
+
+impl Iterator for Cursor {
+    type Item = PyObject;
+
+    fn next(&mut self) -> Option<PyObject> {
+        loop {
+            let child = {
+                let (node, index) = self.stack.last_mut()?;
+                *index += 1;
+                match **node {
+                    Node::Branch(_, ref entries) => match entries.get(*index - 1) {
+                        Some(Entry::Key(_, key)) => return Some(key.clone()),
+                        Some(Entry::Node(child)) => Some(child.clone()),
+                        None => None,
+                    },
+                    Node::Collision(_, ref keys) => match keys.get(*index - 1) {
+                        Some(key) => return Some(key.clone()),
+                        None => None,
+                    },
+                }
+            };
+            match child {
+                Some(child) => self.stack.push((child, 0)),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
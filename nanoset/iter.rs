@@ -0,0 +1,230 @@
+//! Iterator over the elements of a nanoset.
+
+use pyo3::class::PyGCProtocol;
+use pyo3::class::PyIterProtocol;
+use pyo3::exceptions::RuntimeError;
+use pyo3::gc::PyTraverseError;
+use pyo3::gc::PyVisit;
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use pyo3::types::PyList;
+use pyo3::types::PySet;
+use pyo3::AsPyPointer;
+
+use super::order;
+use super::slot::Slot;
+
+/// A function giving access to the slot of a nanoset wrapper.
+pub type SlotAccessor = fn(&PyAny, &mut dyn FnMut(&Slot));
+
+#[pyclass(gc, module = "nanoset")]
+/// An iterator over the elements of a `NanoSet` or a `PicoSet`.
+pub struct NanoSetIterator {
+    /// The wrapper being iterated, or `None` once the iterator is exhausted.
+    owner: Option<PyObject>,
+    /// The set wrapped by the owner when the iterator was created.
+    set: Option<Py<PySet>>,
+    /// The iterator over the wrapped set, or over a sorted copy of it.
+    iter: Option<PyObject>,
+    /// The size of the wrapped set, when iterating over a sorted copy.
+    size: Option<isize>,
+    /// The accessor for the slot of the owner.
+    accessor: SlotAccessor,
+    /// Whether a mutation of the owner was detected.
+    changed: bool,
+}
+
+impl NanoSetIterator {
+    /// Create a new iterator over the content of `owner`.
+    pub fn new(py: Python, owner: &PyAny, accessor: SlotAccessor) -> PyResult<Self> {
+        Self::build(py, owner, accessor, false)
+    }
+
+    /// Create a new iterator over the content of `owner`, in a stable order.
+    pub fn sorted(py: Python, owner: &PyAny, accessor: SlotAccessor) -> PyResult<Self> {
+        Self::build(py, owner, accessor, true)
+    }
+
+    fn build(py: Python, owner: &PyAny, accessor: SlotAccessor, sorted: bool) -> PyResult<Self> {
+        let mut set = None;
+        accessor(owner, &mut |slot| set = slot.get_raw(py));
+        let (iter, size) = match set {
+            // the sorted copy cannot detect changes of the set by itself,
+            // so its size is checked at every step, like set iterators do
+            Some(ref s) if sorted => {
+                let items = order::sorted(py, s.as_ref(py))?;
+                let size = unsafe { pyo3::ffi::PySet_Size(s.as_ptr()) };
+                (Some(items.call_method0("__iter__")?.to_object(py)), Some(size))
+            }
+            Some(ref s) => (Some(s.as_ref(py).call_method0("__iter__")?.to_object(py)), None),
+            None => (None, None),
+        };
+        Ok(Self {
+            owner: Some(owner.to_object(py)),
+            set,
+            iter,
+            size,
+            accessor,
+            changed: false,
+        })
+    }
+
+    /// Check whether the owner still wraps the set being iterated.
+    fn is_unchanged(&self, owner: &PyAny) -> bool {
+        let expected = match self.set {
+            Some(ref s) => s.as_ptr(),
+            None => std::ptr::null_mut(),
+        };
+        let mut current = std::ptr::null_mut();
+        (self.accessor)(owner, &mut |slot| current = slot.as_ptr());
+        current == expected
+    }
+
+    /// Take the references held by the iterator, so that they can be
+    /// released with [`release`] once the iterator is not borrowed anymore.
+    fn take(&mut self) -> References {
+        (self.accessor, self.owner.take(), self.set.take(), self.iter.take())
+    }
+
+    /// Check whether the set being iterated in order did not change size.
+    fn is_same_size(&self) -> bool {
+        match (&self.set, self.size) {
+            (Some(s), Some(size)) => unsafe { pyo3::ffi::PySet_Size(s.as_ptr()) == size },
+            _ => true,
+        }
+    }
+}
+
+#[pymethods]
+impl NanoSetIterator {
+    #[text_signature = "($self)"]
+    fn __length_hint__(&self) -> PyResult<usize> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        match (&self.owner, &self.iter) {
+            (Some(_), Some(it)) if !self.changed => it
+                .call_method0(py, "__length_hint__")
+                .and_then(|n| n.extract(py)),
+            _ => Ok(0),
+        }
+    }
+
+    #[text_signature = "($self)"]
+    fn __reduce__(&self) -> PyResult<PyObject> {
+        // set iterators are pickled as an iterator over a list with the
+        // remaining elements, since the order of set items is undefined
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let iter = py.import("builtins")?.get("iter")?;
+        match (&self.owner, &self.iter) {
+            (Some(_), Some(it)) if !self.changed => it.call_method0(py, "__reduce__"),
+            _ => Ok((iter, (PyList::empty(py),)).to_object(py)),
+        }
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for NanoSetIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<Py<Self>> {
+        Ok(slf.into())
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<PyObject>> {
+        let py = slf.py();
+
+        // bail out if the iterator was already exhausted
+        let owner = match slf.owner {
+            Some(ref owner) => owner.clone_ref(py),
+            None => return Ok(None),
+        };
+
+        // check the owner did not allocate, release or replace its set
+        // since the iterator was created
+        if slf.changed || !slf.is_unchanged(owner.as_ref(py)) || !slf.is_same_size() {
+            slf.changed = true;
+            return RuntimeError::into("Set changed size during iteration");
+        }
+
+        // get the next item, letting the set iterator detect any change
+        // of the set size
+        let item = match slf.iter {
+            Some(ref it) => unsafe {
+                let ptr = pyo3::ffi::PyIter_Next(it.as_ptr());
+                PyObject::from_owned_ptr_or_opt(py, ptr)
+            },
+            None => None,
+        };
+        if item.is_none() {
+            if PyErr::occurred(py) {
+                return Err(PyErr::fetch(py));
+            }
+            // release the references once exhausted, outside of the borrow
+            let references = slf.take();
+            drop(slf);
+            let gil = Python::acquire_gil();
+            release(gil.python(), references);
+        }
+
+        Ok(item)
+    }
+}
+
+#[pyproto]
+impl PyGCProtocol for NanoSetIterator {
+    fn __traverse__(&'p self, visit: PyVisit) -> Result<(), PyTraverseError> {
+        if let Some(ref owner) = self.owner {
+            visit.call(owner)?;
+            // an owner without GC support which is only referenced by this
+            // iterator is treated as a part of the iterator, so that cycles
+            // going through the set it wraps can still be collected
+            let ptr = owner.as_ptr();
+            unsafe {
+                let gc = pyo3::ffi::PyObject_IS_GC(ptr) != 0;
+                if !gc && pyo3::ffi::Py_REFCNT(ptr) == 1 {
+                    let gil = Python::assume_gil_acquired();
+                    let mut result = Ok(());
+                    (self.accessor)(owner.as_ref(gil), &mut |slot| result = slot.traverse(&visit));
+                    result?;
+                }
+            }
+        }
+        if let Some(ref set) = self.set {
+            visit.call(set)?;
+        }
+        if let Some(ref iter) = self.iter {
+            visit.call(iter)?;
+        }
+        Ok(())
+    }
+
+    fn __clear__(&'p mut self) {
+        let gil = Python::acquire_gil();
+        release(gil.python(), self.take());
+    }
+}
+
+impl Drop for NanoSetIterator {
+    fn drop(&mut self) {
+        if self.owner.is_some() {
+            let gil = Python::acquire_gil();
+            release(gil.python(), self.take());
+        }
+    }
+}
+
+/// The references held by an iterator, with the accessor for the owner.
+type References = (SlotAccessor, Option<PyObject>, Option<Py<PySet>>, Option<PyObject>);
+
+/// Release the references taken from an iterator.
+///
+/// The owner cannot release its set when it is emptied while an iterator
+/// references it, so it is released here once the iterator over the set is
+/// gone, if the owner still wraps it and it is still empty.
+fn release(py: Python, (accessor, owner, set, iter): References) {
+    drop(iter);
+    if let (Some(owner), Some(set)) = (&owner, &set) {
+        let mut released = None;
+        accessor(owner.as_ref(py), &mut |slot| released = slot.release_if_empty(set));
+        drop(released);
+    }
+}
@@ -0,0 +1,19 @@
+# Type stubs for the `nanoset.json` module.
+
+import json
+from typing import Any, Dict, Union
+
+TAG: str
+EXT_CODE: int
+
+class NanoSetEncoder(json.JSONEncoder):
+    tagged: bool
+    def __init__(self, *, tagged: bool = ..., **kwargs: Any) -> None: ...
+    def default(self, o: Any) -> Any: ...
+
+def default(obj: Any, tagged: bool = ...) -> Any: ...
+def object_hook(obj: Dict[str, Any]) -> Any: ...
+def dumps(obj: Any, tagged: bool = ..., **kwargs: Any) -> str: ...
+def loads(s: Union[str, bytes], **kwargs: Any) -> Any: ...
+def msgpack_default(obj: Any) -> Any: ...
+def msgpack_ext_hook(code: int, data: bytes) -> Any: ...
@@ -0,0 +1,634 @@
+//! JSON and MessagePack encoding helpers for nanosets.
+//!
+//! Nanosets are encoded as arrays of their elements, or, when tagged, as an
+//! object `{"__nanoset__": "NanoSet", "items": [...]}` which `object_hook`
+//! decodes back into an instance of the original class. Since arrays are
+//! decoded as lists, which cannot be stored in a set, the arrays nested in
+//! the items of a tagged object are decoded as tuples.
+//!
+//! `dumps` writes nanosets of `None`, `bool`, `int`, `float`, `str` and
+//! tuples of these types directly in Rust, with the same output as
+//! `json.dumps`, and falls back to `NanoSetEncoder` for anything else. The
+//! MessagePack codec stores nanosets as an extension type, with a payload
+//! always written and read in Rust.
+
+use std::sync::OnceLock;
+
+use pyo3::exceptions::TypeError;
+use pyo3::exceptions::ValueError;
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::type_object::PyTypeObject;
+use pyo3::types::PyAny;
+use pyo3::types::PyBool;
+use pyo3::types::PyBytes;
+use pyo3::types::PyDict;
+use pyo3::types::PyFloat;
+use pyo3::types::PyList;
+use pyo3::types::PyLong;
+use pyo3::types::PyString;
+use pyo3::types::PyTuple;
+use pyo3::AsPyPointer;
+use pyo3::PyNativeType;
+
+use super::order;
+use super::NanoSet;
+use super::PicoSet;
+
+/// The key storing the class name in tagged objects.
+pub const TAG: &str = "__nanoset__";
+/// The key storing the elements in tagged objects.
+const ITEMS: &str = "items";
+/// The MessagePack extension type code used for nanosets (`'N'`).
+pub const EXT_CODE: i8 = 0x4e;
+/// The maximum nesting depth of tuples written or read by the fast paths.
+const MAX_DEPTH: usize = 256;
+
+/// The `NanoSetEncoder` class, created when the module is initialized.
+static ENCODER: OnceLock<PyObject> = OnceLock::new();
+
+/// A nanoset class, as identified in tagged objects and MessagePack data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    NanoSet = 0,
+    PicoSet = 1,
+}
+
+impl Kind {
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "NanoSet" => Ok(Kind::NanoSet),
+            "PicoSet" => Ok(Kind::PicoSet),
+            _ => ValueError::into(format!("unknown nanoset class: {:?}", name)),
+        }
+    }
+
+    fn from_byte(byte: u8) -> PyResult<Self> {
+        match byte {
+            0 => Ok(Kind::NanoSet),
+            1 => Ok(Kind::PicoSet),
+            _ => ValueError::into(format!("unknown nanoset class: {}", byte)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::NanoSet => "NanoSet",
+            Kind::PicoSet => "PicoSet",
+        }
+    }
+
+    /// Create an instance of the class with the elements of `items`.
+    fn build(self, py: Python, items: &PyAny) -> PyResult<PyObject> {
+        match self {
+            Kind::NanoSet => Py::new(py, NanoSet::try_from_any(py, items)?).map(|s| s.to_object(py)),
+            Kind::PicoSet => Py::new(py, PicoSet::try_from_any(py, items)?).map(|s| s.to_object(py)),
+        }
+    }
+}
+
+/// Get the class and the wrapped set of `obj`, if it is a nanoset.
+fn unwrap(py: Python, obj: &PyAny) -> Option<(Kind, Option<PyObject>)> {
+    if let Ok(set) = obj.extract::<PyRef<NanoSet>>() {
+        Some((Kind::NanoSet, set.inner.get(py).map(PyObject::from)))
+    } else if let Ok(set) = obj.extract::<PyRef<PicoSet>>() {
+        Some((Kind::PicoSet, set.inner.get(py).map(PyObject::from)))
+    } else {
+        None
+    }
+}
+
+/// Collect the elements of the set wrapped by a nanoset in a list, sorted
+/// if the deterministic mode is enabled.
+fn items(py: Python<'_>, set: Option<PyObject>) -> PyResult<&PyList> {
+    let items = PyList::empty(py);
+    if let Some(set) = set {
+        if order::is_deterministic() {
+            return order::sorted(py, set.as_ref(py));
+        }
+        for item in set.as_ref(py).iter()? {
+            items.append(item?)?;
+        }
+    }
+    Ok(items)
+}
+
+/// Check whether `obj` is exactly of type `ty`, and not of a subclass.
+fn is_exact<T: PyTypeObject>(obj: &PyAny) -> bool {
+    obj.get_type().as_ptr() == T::type_object(obj.py()).as_ptr()
+}
+
+// --- JSON ------------------------------------------------------------------
+
+/// Convert a nanoset to an object serializable by `json`.
+///
+/// Use as the `default` argument of `json.dump` or `json.dumps`. Nanosets
+/// are converted to a list of their elements, or to a tagged `dict` if
+/// `tagged` is true. Raises `TypeError` for any other object.
+#[pyfunction(tagged = "false")]
+#[text_signature = "(obj, tagged=False)"]
+fn default(py: Python, obj: &PyAny, tagged: bool) -> PyResult<PyObject> {
+    let (kind, set) = match unwrap(py, obj) {
+        Some(unwrapped) => unwrapped,
+        None => {
+            let ty = obj.get_type().name();
+            return TypeError::into(format!("Object of type {} is not JSON serializable", ty));
+        }
+    };
+    let items = items(py, set)?;
+    if !tagged {
+        return Ok(items.to_object(py));
+    }
+    let object = PyDict::new(py);
+    object.set_item(TAG, kind.name())?;
+    object.set_item(ITEMS, items)?;
+    Ok(object.to_object(py))
+}
+
+/// Convert the arrays decoded as lists in `obj` to tuples, recursively.
+fn freeze(py: Python, obj: &PyAny, depth: usize) -> PyResult<PyObject> {
+    if depth > MAX_DEPTH {
+        return ValueError::into("nanoset items are nested too deeply");
+    }
+    match obj.downcast::<PyList>() {
+        Err(_) => Ok(obj.to_object(py)),
+        Ok(list) => {
+            let items = list.iter().map(|item| freeze(py, item, depth + 1)).collect::<PyResult<Vec<_>>>()?;
+            Ok(PyTuple::new(py, items).to_object(py))
+        }
+    }
+}
+
+/// Decode the tagged objects created by `default` back into nanosets.
+///
+/// Use as the `object_hook` argument of `json.load` or `json.loads`. Any
+/// other object is returned unchanged.
+#[pyfunction]
+#[text_signature = "(obj)"]
+fn object_hook(py: Python, obj: &PyDict) -> PyResult<PyObject> {
+    let (name, items) = match (obj.len(), obj.get_item(TAG), obj.get_item(ITEMS)) {
+        (2, Some(name), Some(items)) => (name, items),
+        _ => return Ok(obj.to_object(py)),
+    };
+    let kind = Kind::from_name(name.extract()?)?;
+    if items.downcast::<PyList>().is_err() {
+        return TypeError::into("expected a list of nanoset items");
+    }
+    kind.build(py, freeze(py, items, 0)?.as_ref(py))
+}
+
+/// Write `s` as a JSON string, escaping non-ASCII characters.
+fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\x08' => out.push_str("\\b"),
+            '\x0c' => out.push_str("\\f"),
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    out.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    out.push('"');
+}
+
+/// Write `obj` like `json.dumps` does with the default options.
+///
+/// Returns `false` if `obj` is not supported by the fast path, in which
+/// case `out` is left in an unspecified state.
+fn write_json(out: &mut String, obj: &PyAny, depth: usize) -> PyResult<bool> {
+    if depth > MAX_DEPTH {
+        return Ok(false);
+    }
+    if obj.is_none() {
+        out.push_str("null");
+    } else if is_exact::<PyBool>(obj) {
+        out.push_str(if obj.is_true()? { "true" } else { "false" });
+    } else if is_exact::<PyLong>(obj) {
+        out.push_str(&obj.str()?.to_string()?);
+    } else if is_exact::<PyFloat>(obj) {
+        let value = obj.downcast::<PyFloat>()?.value();
+        if value.is_nan() {
+            out.push_str("NaN");
+        } else if value.is_infinite() {
+            out.push_str(if value > 0.0 { "Infinity" } else { "-Infinity" });
+        } else {
+            out.push_str(&obj.repr()?.to_string()?);
+        }
+    } else if is_exact::<PyString>(obj) {
+        // strings with lone surrogates are left to `json`
+        match obj.downcast::<PyString>()?.to_string() {
+            Ok(s) => write_str(out, &s),
+            Err(_) => return Ok(false),
+        }
+    } else if is_exact::<PyTuple>(obj) {
+        out.push('[');
+        for (i, item) in obj.downcast::<PyTuple>()?.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            if !write_json(out, item, depth + 1)? {
+                return Ok(false);
+            }
+        }
+        out.push(']');
+    } else {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Write the nanoset `kind` wrapping `set` as JSON, if possible.
+fn write_set(py: Python, kind: Kind, set: Option<PyObject>, tagged: bool) -> PyResult<Option<String>> {
+    let mut out = String::new();
+    if tagged {
+        out.push('{');
+        write_str(&mut out, TAG);
+        out.push_str(": ");
+        write_str(&mut out, kind.name());
+        out.push_str(", ");
+        write_str(&mut out, ITEMS);
+        out.push_str(": ");
+    }
+    out.push('[');
+    if let Some(set) = set {
+        let items: &PyAny = if order::is_deterministic() {
+            order::sorted(py, set.as_ref(py))?
+        } else {
+            set.as_ref(py)
+        };
+        for (i, item) in items.iter()?.enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            if !write_json(&mut out, item?, 1)? {
+                return Ok(None);
+            }
+        }
+    }
+    out.push(']');
+    if tagged {
+        out.push('}');
+    }
+    Ok(Some(out))
+}
+
+/// Serialize `obj` to a JSON string, encoding nanosets with `default`.
+///
+/// Nanosets of `None`, `bool`, `int`, `float`, `str` and tuples of these
+/// types are written directly when no other keyword argument is given;
+/// anything else is given to `json.dumps` with `NanoSetEncoder`.
+#[pyfunction(tagged = "false", kwargs = "**")]
+#[text_signature = "(obj, tagged=False, **kwargs)"]
+fn dumps(py: Python, obj: &PyAny, tagged: bool, kwargs: Option<&PyDict>) -> PyResult<PyObject> {
+    if kwargs.map(PyDict::is_empty).unwrap_or(true) {
+        if let Some((kind, set)) = unwrap(py, obj) {
+            if let Some(s) = write_set(py, kind, set, tagged)? {
+                return Ok(s.to_object(py));
+            }
+        }
+    }
+    let kw = match kwargs {
+        Some(kw) => kw.copy()?,
+        None => PyDict::new(py),
+    };
+    kw.set_item("cls", encoder(py)?)?;
+    kw.set_item("tagged", tagged)?;
+    py.import("json")?.call("dumps", (obj,), Some(kw)).map(|s| s.to_object(py))
+}
+
+/// Deserialize a JSON string, decoding tagged objects with `object_hook`.
+#[pyfunction(kwargs = "**")]
+#[text_signature = "(s, **kwargs)"]
+fn loads(py: Python, s: &PyAny, kwargs: Option<&PyDict>) -> PyResult<PyObject> {
+    let kw = match kwargs {
+        Some(kw) => kw.copy()?,
+        None => PyDict::new(py),
+    };
+    kw.set_item("object_hook", pyo3::wrap_pyfunction!(object_hook)(py))?;
+    py.import("json")?.call("loads", (s,), Some(kw)).map(|s| s.to_object(py))
+}
+
+/// Get the `NanoSetEncoder` class.
+fn encoder(py: Python) -> PyResult<PyObject> {
+    match ENCODER.get() {
+        Some(encoder) => Ok(encoder.clone_ref(py)),
+        None => ValueError::into("nanoset.json was not initialized"),
+    }
+}
+
+/// The `__init__` method of `NanoSetEncoder`, accepting a `tagged` flag.
+#[pyfunction(args = "*", kwargs = "**")]
+#[text_signature = "(encoder, *, tagged=False, **kwargs)"]
+fn encoder_init(py: Python, encoder: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+    let kw = match kwargs {
+        Some(kw) => kw.copy()?,
+        None => PyDict::new(py),
+    };
+    let tagged = match kw.get_item("tagged") {
+        None => false,
+        Some(tagged) => {
+            let tagged = tagged.is_true()?;
+            kw.del_item("tagged")?;
+            tagged
+        }
+    };
+    let mut init_args = vec![encoder.to_object(py)];
+    init_args.extend(args.iter().map(|arg| arg.to_object(py)));
+    let base = py.import("json")?.get("JSONEncoder")?;
+    base.getattr("__init__")?.call(PyTuple::new(py, init_args), Some(kw))?;
+    encoder.setattr("tagged", tagged)
+}
+
+/// The `default` method of `NanoSetEncoder`.
+#[pyfunction]
+#[text_signature = "(encoder, o)"]
+fn encoder_default(py: Python, encoder: &PyAny, o: &PyAny) -> PyResult<PyObject> {
+    if unwrap(py, o).is_none() {
+        let base = py.import("json")?.get("JSONEncoder")?;
+        return base.call_method1("default", (encoder, o)).map(|obj| obj.to_object(py));
+    }
+    default(py, o, encoder.getattr("tagged")?.is_true()?)
+}
+
+// --- MessagePack -----------------------------------------------------------
+
+/// Write the header of a MessagePack value of length `len`, using the fixed
+/// format `fix` if it is below `fixmax`, and the given 8, 16 and 32 bits
+/// formats otherwise (with 0 for formats not in the specification).
+fn pack_header(out: &mut Vec<u8>, len: usize, fix: (u8, usize), formats: [u8; 3]) -> PyResult<()> {
+    let (fix, fixmax) = (fix.0, fix.1);
+    if len < fixmax {
+        out.push(fix | len as u8);
+    } else if len <= u8::MAX as usize && formats[0] != 0 {
+        out.push(formats[0]);
+        out.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(formats[1]);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else if len <= u32::MAX as usize {
+        out.push(formats[2]);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        return ValueError::into("object too large for MessagePack");
+    }
+    Ok(())
+}
+
+/// Write an integer with the smallest MessagePack format able to store it.
+fn pack_int(out: &mut Vec<u8>, obj: &PyAny) -> PyResult<()> {
+    if let Ok(v) = obj.extract::<i64>() {
+        if v >= 0 {
+            pack_uint(out, v as u64);
+        } else if v >= -32 {
+            out.push(v as u8);
+        } else if v >= i8::MIN as i64 {
+            out.push(0xd0);
+            out.push(v as u8);
+        } else if v >= i16::MIN as i64 {
+            out.push(0xd1);
+            out.extend_from_slice(&(v as i16).to_be_bytes());
+        } else if v >= i32::MIN as i64 {
+            out.push(0xd2);
+            out.extend_from_slice(&(v as i32).to_be_bytes());
+        } else {
+            out.push(0xd3);
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        Ok(())
+    } else {
+        // raises `OverflowError` for integers not fitting in 64 bits
+        pack_uint(out, obj.extract::<u64>()?);
+        Ok(())
+    }
+}
+
+/// Write an unsigned integer with the smallest MessagePack format.
+fn pack_uint(out: &mut Vec<u8>, v: u64) {
+    if v < 0x80 {
+        out.push(v as u8);
+    } else if v <= u8::MAX as u64 {
+        out.push(0xcc);
+        out.push(v as u8);
+    } else if v <= u16::MAX as u64 {
+        out.push(0xcd);
+        out.extend_from_slice(&(v as u16).to_be_bytes());
+    } else if v <= u32::MAX as u64 {
+        out.push(0xce);
+        out.extend_from_slice(&(v as u32).to_be_bytes());
+    } else {
+        out.push(0xcf);
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+}
+
+/// Write `obj` as a MessagePack value.
+fn pack(out: &mut Vec<u8>, obj: &PyAny, depth: usize) -> PyResult<()> {
+    if depth > MAX_DEPTH {
+        return ValueError::into("nanoset items are nested too deeply");
+    }
+    let ptr = obj.as_ptr();
+    if obj.is_none() {
+        out.push(0xc0);
+    } else if unsafe { ffi::PyBool_Check(ptr) } != 0 {
+        out.push(if obj.is_true()? { 0xc3 } else { 0xc2 });
+    } else if unsafe { ffi::PyLong_Check(ptr) } != 0 {
+        pack_int(out, obj)?;
+    } else if let Ok(f) = obj.downcast::<PyFloat>() {
+        out.push(0xcb);
+        out.extend_from_slice(&f.value().to_be_bytes());
+    } else if let Ok(s) = obj.downcast::<PyString>() {
+        let s = s.to_string()?;
+        pack_header(out, s.len(), (0xa0, 32), [0xd9, 0xda, 0xdb])?;
+        out.extend_from_slice(s.as_bytes());
+    } else if let Ok(b) = obj.downcast::<PyBytes>() {
+        let b = b.as_bytes();
+        pack_header(out, b.len(), (0, 0), [0xc4, 0xc5, 0xc6])?;
+        out.extend_from_slice(b);
+    } else if let Ok(t) = obj.downcast::<PyTuple>() {
+        pack_header(out, t.len(), (0x90, 16), [0, 0xdc, 0xdd])?;
+        for item in t.iter() {
+            pack(out, item, depth + 1)?;
+        }
+    } else {
+        let ty = obj.get_type().name();
+        return TypeError::into(format!("can not serialize {:?} object", ty));
+    }
+    Ok(())
+}
+
+/// A reader of MessagePack values.
+struct Unpacker<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Unpacker<'a> {
+    fn take(&mut self, n: usize) -> PyResult<&'a [u8]> {
+        if self.data.len() < n {
+            return ValueError::into("truncated MessagePack data");
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> PyResult<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> PyResult<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    /// Read a length stored on `n` bytes.
+    fn read_len(&mut self, n: usize) -> PyResult<usize> {
+        match n {
+            1 => self.byte().map(usize::from),
+            2 => self.array().map(u16::from_be_bytes).map(usize::from),
+            _ => self.array().map(u32::from_be_bytes).map(|n| n as usize),
+        }
+    }
+
+    fn str(&mut self, py: Python, len: usize) -> PyResult<PyObject> {
+        match std::str::from_utf8(self.take(len)?) {
+            Ok(s) => Ok(s.to_object(py)),
+            Err(_) => ValueError::into("invalid UTF-8 in MessagePack string"),
+        }
+    }
+
+    fn tuple(&mut self, py: Python, len: usize, depth: usize) -> PyResult<PyObject> {
+        // do not trust the length to preallocate the items
+        let mut items = Vec::new();
+        for _ in 0..len {
+            items.push(self.read(py, depth + 1)?);
+        }
+        Ok(PyTuple::new(py, items).to_object(py))
+    }
+
+    /// Read a single value, decoding arrays as tuples.
+    fn read(&mut self, py: Python, depth: usize) -> PyResult<PyObject> {
+        if depth > MAX_DEPTH {
+            return ValueError::into("nanoset items are nested too deeply");
+        }
+        let marker = self.byte()?;
+        match marker {
+            0x00..=0x7f => Ok(marker.to_object(py)),
+            0xe0..=0xff => Ok((marker as i8).to_object(py)),
+            0x80..=0x8f => ValueError::into("unsupported MessagePack map in nanoset items"),
+            0x90..=0x9f => self.tuple(py, (marker & 0x0f) as usize, depth),
+            0xa0..=0xbf => self.str(py, (marker & 0x1f) as usize),
+            0xc0 => Ok(py.None()),
+            0xc2 => Ok(false.to_object(py)),
+            0xc3 => Ok(true.to_object(py)),
+            0xc4..=0xc6 => {
+                let len = self.read_len(1 << (marker - 0xc4))?;
+                Ok(PyBytes::new(py, self.take(len)?).to_object(py))
+            }
+            0xca => Ok((f32::from_be_bytes(self.array()?) as f64).to_object(py)),
+            0xcb => Ok(f64::from_be_bytes(self.array()?).to_object(py)),
+            0xcc => Ok(self.byte()?.to_object(py)),
+            0xcd => Ok(u16::from_be_bytes(self.array()?).to_object(py)),
+            0xce => Ok(u32::from_be_bytes(self.array()?).to_object(py)),
+            0xcf => Ok(u64::from_be_bytes(self.array()?).to_object(py)),
+            0xd0 => Ok(i8::from_be_bytes(self.array()?).to_object(py)),
+            0xd1 => Ok(i16::from_be_bytes(self.array()?).to_object(py)),
+            0xd2 => Ok(i32::from_be_bytes(self.array()?).to_object(py)),
+            0xd3 => Ok(i64::from_be_bytes(self.array()?).to_object(py)),
+            0xd9..=0xdb => {
+                let len = self.read_len(1 << (marker - 0xd9))?;
+                self.str(py, len)
+            }
+            0xdc | 0xdd => {
+                let len = self.read_len(2 << (marker - 0xdc))?;
+                self.tuple(py, len, depth)
+            }
+            _ => ValueError::into(format!("unsupported MessagePack type: 0x{:02x}", marker)),
+        }
+    }
+}
+
+/// Convert a nanoset to a `msgpack.ExtType`.
+///
+/// Use as the `default` argument of `msgpack.packb`. The elements must be
+/// `None`, `bool`, `int`, `float`, `str`, `bytes` or tuples of these types.
+#[pyfunction]
+#[text_signature = "(obj)"]
+fn msgpack_default(py: Python, obj: &PyAny) -> PyResult<PyObject> {
+    let (kind, set) = match unwrap(py, obj) {
+        Some(unwrapped) => unwrapped,
+        None => {
+            let ty = obj.get_type().name();
+            return TypeError::into(format!("can not serialize {:?} object", ty));
+        }
+    };
+    let items = PyTuple::new(py, items(py, set)?.iter().collect::<Vec<_>>());
+    let mut data = vec![kind as u8];
+    pack(&mut data, items, 0)?;
+    let ext = py.import("msgpack")?.get("ExtType")?;
+    ext.call1((EXT_CODE, PyBytes::new(py, &data))).map(|e| e.to_object(py))
+}
+
+/// Decode the extension types created by `msgpack_default` into nanosets.
+///
+/// Use as the `ext_hook` argument of `msgpack.unpackb`. Other extension
+/// types are returned as `msgpack.ExtType` instances, like `msgpack` does.
+#[pyfunction]
+#[text_signature = "(code, data)"]
+fn msgpack_ext_hook(py: Python, code: i8, data: &PyBytes) -> PyResult<PyObject> {
+    if code != EXT_CODE {
+        let ext = py.import("msgpack")?.get("ExtType")?;
+        return ext.call1((code, data)).map(|e| e.to_object(py));
+    }
+    let mut unpacker = Unpacker { data: data.as_bytes() };
+    let kind = Kind::from_byte(unpacker.byte()?)?;
+    let items = unpacker.read(py, 0)?;
+    if !unpacker.data.is_empty() {
+        return ValueError::into("trailing data after nanoset items");
+    }
+    if items.cast_as::<PyTuple>(py).is_err() {
+        return TypeError::into("expected an array of nanoset items");
+    }
+    kind.build(py, items.as_ref(py))
+}
+
+// ---------------------------------------------------------------------------
+
+/// Add the encoding helpers to the `nanoset.json` module.
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+    let builtins = py.import("builtins")?;
+    let partialmethod = py.import("functools")?.get("partialmethod")?;
+
+    // create `NanoSetEncoder` as a subclass of `json.JSONEncoder`, with the
+    // functions wrapped so that they are bound to the encoder instances
+    let ns = PyDict::new(py);
+    ns.set_item("__module__", "nanoset.json")?;
+    ns.set_item("__doc__", "A JSON encoder serializing nanosets as arrays, or as tagged objects.")?;
+    ns.set_item("tagged", false)?;
+    ns.set_item("__init__", partialmethod.call1((pyo3::wrap_pyfunction!(encoder_init)(py),))?)?;
+    ns.set_item("default", partialmethod.call1((pyo3::wrap_pyfunction!(encoder_default)(py),))?)?;
+    let bases = (py.import("json")?.get("JSONEncoder")?,);
+    let encoder = builtins.getattr("type")?.call1(("NanoSetEncoder", bases, ns))?;
+    let encoder = ENCODER.get_or_init(|| encoder.to_object(py));
+
+    m.add("TAG", TAG)?;
+    m.add("EXT_CODE", EXT_CODE)?;
+    m.add("NanoSetEncoder", encoder.clone_ref(py))?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(default))?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(object_hook))?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(dumps))?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(loads))?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(msgpack_default))?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(msgpack_ext_hook))?;
+    Ok(())
+}
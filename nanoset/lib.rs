@@ -4,7 +4,23 @@
 extern crate pyo3;
 extern crate pyo3_built;
 
+mod array;
+#[cfg(feature = "serde")]
+mod bridge;
 mod built;
+mod bulk;
+mod concurrent;
+mod debug;
+mod factory;
+mod hamt;
+mod iter;
+mod json;
+mod order;
+mod persistent;
+mod slot;
+mod stats;
+mod util;
+mod view;
 
 use pyo3::class::basic::CompareOp;
 use pyo3::class::PyGCProtocol;
@@ -12,18 +28,77 @@ use pyo3::class::PyIterProtocol;
 use pyo3::class::PyNumberProtocol;
 use pyo3::class::PyObjectProtocol;
 use pyo3::class::PySequenceProtocol;
+use pyo3::exceptions::ImportError;
 use pyo3::exceptions::KeyError;
+use pyo3::exceptions::TypeError;
 use pyo3::gc::PyTraverseError;
 use pyo3::gc::PyVisit;
 use pyo3::prelude::*;
+use pyo3::types::IntoPyDict;
 use pyo3::types::PyAny;
 use pyo3::types::PyDict;
 use pyo3::types::PyFrozenSet;
 use pyo3::types::PyIterator;
 use pyo3::types::PySet;
 use pyo3::types::PyTuple;
+use pyo3::types::PyType;
 use pyo3::AsPyPointer;
 use pyo3::PyNativeType;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::OnceLock;
+
+pub use self::array::Int64Array;
+pub use self::concurrent::ConcurrentNanoSet;
+pub use self::concurrent::ConcurrentNanoSetIterator;
+pub use self::concurrent::Key;
+pub use self::concurrent::KeySet;
+#[cfg(feature = "serde")]
+pub use self::bridge::Bridge;
+#[cfg(feature = "serde")]
+pub use self::bridge::Primitives;
+pub use self::iter::NanoSetIterator;
+pub use self::persistent::PersistentNanoSet;
+pub use self::persistent::PersistentNanoSetEvolver;
+pub use self::view::NanoSetView;
+use self::slot::Slot;
+
+/// Whether instances should be formatted like builtin sets by `__repr__`.
+static LEGACY_REPR: AtomicBool = AtomicBool::new(false);
+
+/// Whether `copy` shares the wrapped set with the copy until either changes.
+static COPY_ON_WRITE: AtomicBool = AtomicBool::new(false);
+
+/// The methods of `set` which intentionally behave differently on nanosets,
+/// with the reason why, exported as `nanoset.__divergences__`.
+const DIVERGENCES: &[(&str, &str)] = &[
+    ("__getstate__", "returns a copy of the wrapped set, or None if it is not allocated"),
+    ("__new__", "initializes the instance with the iterable, instead of __init__"),
+    ("__reduce__", "pickles the elements as a tuple, and empty instances without arguments"),
+    ("__repr__", "includes the class name, unless set_legacy_repr(True) was called"),
+    ("__sizeof__", "does not count the wrapped set when it is not allocated"),
+];
+
+/// Create a new `set` with the elements of `iterable`, or an empty `set`.
+///
+/// Unlike calling `copy` or `set`, this never calls a method overridden by
+/// a subclass of `set`, so the result is always an exact `set` instance.
+pub(crate) fn new_set(py: Python, iterable: Option<&PyAny>) -> PyResult<Py<PySet>> {
+    let ptr = iterable.map_or(std::ptr::null_mut(), AsPyPointer::as_ptr);
+    unsafe { Py::from_owned_ptr_or_err(py, pyo3::ffi::PySet_New(ptr)) }
+}
+
+/// The empty `frozenset` shared by all frozen empty instances.
+static EMPTY: OnceLock<PyObject> = OnceLock::new();
+
+/// Get the shared empty `frozenset`, creating it on first use.
+fn empty(py: Python) -> PyResult<PyObject> {
+    if let Some(empty) = EMPTY.get() {
+        return Ok(empty.clone_ref(py));
+    }
+    let new = PyFrozenSet::empty(py)?.to_object(py);
+    Ok(EMPTY.get_or_init(|| new).clone_ref(py))
+}
 
 // --- Common implementation -------------------------------------------------
 
@@ -31,11 +106,20 @@ macro_rules! common_impl {
     ($cls:ty) => {
         impl $cls {
             pub fn new() -> Self {
-                Self::default()
+                stats::created(stringify!($cls));
+                Self { inner: Self::slot(None) }
+            }
+
+            /// Wrap `set`, which must not be empty nor used anywhere else.
+            pub fn from_set(set: Py<PySet>) -> Self {
+                stats::created(stringify!($cls));
+                Self { inner: Self::slot(Some(set)) }
             }
 
-            pub fn from_set(obj: PyObject) -> Self {
-                Self { inner: Some(obj) }
+            /// Wrap a set shared with another instance.
+            fn from_shared(obj: Py<PySet>) -> Self {
+                stats::created(stringify!($cls));
+                Self { inner: Self::slot(Some(obj)).into_shared() }
             }
 
             pub fn try_from_any(py: Python, any: &PyAny) -> PyResult<Self> {
@@ -43,48 +127,187 @@ macro_rules! common_impl {
             }
 
             pub fn try_from_obj(py: Python, obj: PyObject) -> PyResult<Self> {
+                match Self::set_from_obj(py, obj)? {
+                    None => Ok(Self::new()),
+                    Some(set) => Ok(Self::from_set(set)),
+                }
+            }
+
+            pub fn try_from_iterator(py: Python, it: PyIterator) -> PyResult<Self> {
+                match Self::set_from_iterator(py, it)? {
+                    None => Ok(Self::new()),
+                    Some(set) => Ok(Self::from_set(set)),
+                }
+            }
+
+            /// Build a new set from `obj`, or `None` if it would be empty.
+            fn set_from_obj(py: Python, obj: PyObject) -> PyResult<Option<Py<PySet>>> {
                 if let Ok(s) = obj.cast_as::<PySet>(py) {
                     if s.is_empty() {
-                        Ok(Self::new())
+                        Ok(None)
                     } else {
-                        s.to_object(py).call_method0(py, "copy").map(Self::from_set)
+                        new_set(py, Some(s)).map(Some)
                     }
                 } else if let Ok(d) = obj.cast_as::<PyDict>(py) {
                     if d.is_empty() {
-                        Ok(Self::new())
+                        Ok(None)
                     } else {
-                        unsafe {
-                            let set = pyo3::ffi::PySet_New(d.as_ptr());
-                            Ok(Self::from_set(PyObject::from_owned_ptr(py, set)))
-                        }
+                        new_set(py, Some(d)).map(Some)
                     }
                 } else {
                     let iterator = PyIterator::from_object(py, &obj)?;
-                    Self::try_from_iterator(py, iterator)
+                    Self::set_from_iterator(py, iterator)
                 }
             }
 
-            pub fn try_from_iterator(py: Python, it: PyIterator) -> PyResult<Self> {
+            /// Build a new set from an iterator, or `None` if it would be empty.
+            fn set_from_iterator(py: Python, it: PyIterator) -> PyResult<Option<Py<PySet>>> {
                 let items: PyResult<Vec<&PyAny>> = it.collect();
                 let res = items?;
 
                 if res.is_empty() {
-                    Ok(Self::default())
+                    Ok(None)
                 } else {
                     let set = PySet::new(py, res.as_slice())?;
-                    Ok(Self::from_set(set.to_object(py)))
+                    Ok(Some(set.into()))
+                }
+            }
+
+            /// Call `f` with the slot of `obj` if it is an instance of this type.
+            fn with_slot(obj: &PyAny, f: &mut dyn FnMut(&Slot)) {
+                if let Ok(cell) = obj.downcast::<PyCell<Self>>() {
+                    if let Ok(this) = cell.try_borrow() {
+                        f(&this.inner)
+                    }
                 }
             }
+
+            /// Get the inner set, or a new empty set not stored in the slot.
+            ///
+            /// Elements added to an empty instance by `__hash__` or `__eq__`
+            /// are not seen by the methods using the empty set, which give
+            /// the result the instance had when they were called.
+            fn inner_or_empty(&self, py: Python) -> PyResult<Py<PySet>> {
+                match self.inner.get(py) {
+                    None => new_set(py, None),
+                    Some(obj) => Ok(obj),
+                }
+            }
+
+            /// Get the inner set to modify it, or a new empty set not stored
+            /// in the slot.
+            fn unique_or_empty(&self, py: Python) -> PyResult<Py<PySet>> {
+                match self.inner.get_unique(py)? {
+                    None => new_set(py, None),
+                    Some(obj) => Ok(obj),
+                }
+            }
+
+            /// Get the inner set to modify it, storing a new empty set in the
+            /// slot if needed, so that the methods called by `__hash__` or
+            /// `__eq__` while it is being modified see the same set.
+            ///
+            /// The set must be given to `commit` once the mutation is over.
+            fn unique_or_attach(&self, py: Python) -> PyResult<Py<PySet>> {
+                if let Some(inner) = self.inner.get_unique(py)? {
+                    return Ok(inner);
+                }
+                let set = new_set(py, None)?;
+                match self.inner.insert(py, &set) {
+                    None => Ok(set),
+                    // the slot holds an empty set still used by another method
+                    Some(current) => Ok(self.inner.get_unique(py)?.unwrap_or(current)),
+                }
+            }
+
+            /// Release the inner set `set` if a mutation left it empty.
+            ///
+            /// Methods only reading the set must call it as well, since the
+            /// `__hash__` or `__eq__` methods of the elements they look up
+            /// may have emptied the instance.
+            fn commit(&self, set: Py<PySet>) {
+                self.inner.release_if_empty(&set);
+            }
+
+            /// Store a set created by a mutation of an empty instance.
+            ///
+            /// If another thread stored a set in the meantime, the content of
+            /// `set` is applied to it with the `merge` method instead.
+            fn attach(&self, py: Python, set: Py<PySet>, merge: &str) -> PyResult<()> {
+                if set.as_ref(py).is_empty() {
+                    return Ok(());
+                }
+                match self.inner.insert(py, &set) {
+                    None => Ok(()),
+                    Some(current) => {
+                        // make sure not to modify a set shared with a copy
+                        let current = self.inner.get_unique(py)?.unwrap_or(current);
+                        current.as_ref(py).call_method1(merge, (set,))?;
+                        self.commit(current);
+                        Ok(())
+                    }
+                }
+            }
+        }
+
+        impl $cls {
+            /// Check whether `obj` can be an operand of a set operator.
+            fn is_operand(obj: &PyAny) -> PyResult<bool> {
+                Ok(obj.cast_as::<PySet>().is_ok()
+                    || obj.cast_as::<PyFrozenSet>().is_ok()
+                    || obj.downcast::<PyCell<NanoSet>>().is_ok()
+                    || obj.downcast::<PyCell<PicoSet>>().is_ok())
+            }
+
+            /// Check that `item` could be looked up in the inner set, so that
+            /// an unallocated instance raises the same errors.
+            fn check_hashable(item: &PyAny) -> PyResult<()> {
+                if Self::is_operand(item)? {
+                    Ok(())
+                } else {
+                    item.hash().map(|_| ())
+                }
+            }
+
+            /// Borrow `obj` if it is an instance of this type, failing with a
+            /// `RuntimeError` if it is being modified by an in-place operator.
+            fn try_borrow(obj: &PyAny) -> PyResult<Option<PyRef<'_, Self>>> {
+                match obj.downcast::<PyCell<Self>>() {
+                    Err(_) => Ok(None),
+                    Ok(cell) => Ok(Some(cell.try_borrow()?)),
+                }
+            }
+
+            /// Replace this instance with its inner set `inner` in `others`,
+            /// so that builtin sets handle operations with themselves.
+            fn substitute<'p>(&self, others: &'p PyTuple, inner: &Py<PySet>) -> &'p PyTuple {
+                let py = others.py();
+                if !others.iter().any(|obj| util::is_self(self, obj)) {
+                    return others;
+                }
+                let args: Vec<&PyAny> = others
+                    .iter()
+                    .map(|obj| if util::is_self(self, obj) { inner.as_ref(py).as_ref() } else { obj })
+                    .collect();
+                PyTuple::new(py, args)
+            }
         }
 
         impl FromPy<PySet> for $cls {
-            fn from_py(set: PySet, py: Python) -> Self {
-                Self::from_set(set.to_object(py))
+            fn from_py(set: PySet, _py: Python) -> Self {
+                Self::from_set(Py::from(&set))
+            }
+        }
+
+        impl Default for $cls {
+            fn default() -> Self {
+                Self::new()
             }
         }
 
         impl Drop for $cls {
             fn drop(&mut self) {
+                stats::dropped(stringify!($cls));
                 if let Some(set) = self.inner.take() {
                     Python::acquire_gil().python().release(set)
                 }
@@ -98,113 +321,283 @@ macro_rules! common_impl {
             fn __new__(iterable: Option<&PyAny>) -> PyResult<Self> {
                 let gil = Python::acquire_gil();
                 let py = gil.python();
-                let cell = PyCell::new(py,  Self::new())?;
-                Self::__init__(cell, iterable)?;
-                Ok(cell.replace(Self::new()))
+                // the instance cannot be reached by the elements yet, so the
+                // set can be built before being stored
+                let inner = match iterable {
+                    None => None,
+                    Some(it) => match Self::try_borrow(it)? {
+                        Some(set) => match set.inner.get(py) {
+                            Some(inner) => Some(new_set(py, Some(inner.as_ref(py)))?),
+                            None => None,
+                        },
+                        None => Self::set_from_obj(py, it.to_object(py))?,
+                    },
+                };
+                match inner {
+                    None => Ok(Self::new()),
+                    Some(set) => Ok(Self::from_set(set)),
+                }
             }
 
-            fn __init__(slf: &PyCell<Self>, iterable: Option<&PyAny>) -> PyResult<()> {
-                if let Some(it) = iterable {
-                    let gil = Python::acquire_gil();
-                    let py = gil.python();
-                    if let Ok(set) = it.extract::<PyRef<Self>>() {
-                        slf.replace(set.copy()?);
-                    } else {
-                        slf.replace(Self::try_from_any(py, it)?);
-                    }
-                } else {
-                    slf.replace(Self::new());
-                }
-                Ok(())
+            #[classmethod]
+            #[text_signature = "($cls, item)"]
+            fn __class_getitem__(cls: &PyType, item: &PyAny) -> PyResult<PyObject> {
+                util::class_getitem(cls, item)
             }
 
+            #[text_signature = "($self, iterable=None)"]
+            fn __init__(&self, iterable: Option<&PyAny>) -> PyResult<()> {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                // builtin sets are cleared before being updated, so they
+                // end up empty when initialized with themselves
+                let it = match iterable {
+                    Some(it) if !util::is_self(self, it) => it,
+                    _ => return self.clear(),
+                };
+                let other = match Self::try_borrow(it)? {
+                    Some(set) => match set.inner.get(py) {
+                        Some(inner) => inner.into(),
+                        None => return self.clear(),
+                    },
+                    None => it.to_object(py),
+                };
+
+                // clear and update the set in place like builtin sets, so
+                // that the elements see the instance being initialized
+                let inner = self.unique_or_attach(py)?;
+                inner.as_ref(py).clear();
+                let result = inner.as_ref(py).call_method1("update", (other,)).map(drop);
+                self.commit(inner);
+                result
+            }
+
+            #[text_signature = "($self)"]
             fn __getstate__(&self) -> PyResult<PyObject> {
                 let gil = Python::acquire_gil();
                 let py = gil.python();
-                match self.inner {
+                match self.inner.get(py) {
                     None => Ok(py.None()),
-                    Some(ref set) => Ok(set.clone_ref(py)),
+                    Some(set) => new_set(py, Some(set.as_ref(py))).map(PyObject::from),
                 }
             }
 
-            fn __setstate__(slf: &PyCell<Self>, state: PyObject) -> PyResult<()> {
+            #[text_signature = "($self, state)"]
+            fn __setstate__(&self, state: PyObject) -> PyResult<()> {
                 let gil = Python::acquire_gil();
                 let py = gil.python();
 
-                // check that we got either `None`, or a set
-                let inner = if state.is_none(py) {
-                    None
-                } else if state.cast_as::<PySet>(py)?.is_empty() {
-                    None
-                } else {
-                    Some(state)
+                // check that we got either `None`, or a set, and copy its
+                // elements so that the state is never shared with the caller
+                let state = match state.as_ref(py) {
+                    state if state.is_none() => return self.clear(),
+                    state => match state.downcast::<PySet>() {
+                        Ok(set) if set.is_empty() => return self.clear(),
+                        Ok(set) => set,
+                        Err(_) => {
+                            return TypeError::into(format!(
+                                "state must be a set or None, not '{}'",
+                                state.get_type().name(),
+                            ))
+                        }
+                    },
                 };
 
-                slf.borrow_mut().inner = inner;
-                Ok(())
+                // replace the elements in place, so that the set is never
+                // swapped while another method is modifying it
+                let inner = self.unique_or_attach(py)?;
+                inner.as_ref(py).clear();
+                let result = inner.as_ref(py).call_method1("update", (state,)).map(drop);
+                self.commit(inner);
+                result
             }
 
+            #[text_signature = "($self)"]
             fn __reduce__(&self) -> PyResult<PyObject> {
                 let gil = Python::acquire_gil();
                 let py = gil.python();
                 let ty = <$cls as pyo3::type_object::PyTypeObject>::type_object(py);
 
-                match self.inner {
+                // empty sets are pickled as a bare call to the constructor, and
+                // other sets with a tuple of their elements, which is smaller
+                // than a pickled `set` and loadable by any `nanoset` version
+                match self.inner.get(py) {
                     None => Ok((ty, PyTuple::empty(py)).to_object(py)),
-                    Some(ref set) => Ok((ty, (set.call_method0(py, "copy")?,)).to_object(py)),
+                    Some(set) => {
+                        let items = unsafe {
+                            let tuple = if order::is_deterministic() {
+                                pyo3::ffi::PyList_AsTuple(order::sorted(py, set.as_ref(py))?.as_ptr())
+                            } else {
+                                pyo3::ffi::PySequence_Tuple(set.as_ptr())
+                            };
+                            PyObject::from_owned_ptr_or_err(py, tuple)?
+                        };
+                        Ok((ty, (items,)).to_object(py))
+                    }
                 }
             }
 
-            fn add(slf: &PyCell<Self>, item: &PyAny) -> PyResult<()> {
+            #[text_signature = "($self, item)"]
+            fn add(&self, item: &PyAny) -> PyResult<()> {
                 let py = item.py();
-                let inner = match slf.borrow().inner.as_ref() {
-                    None => PySet::empty(py)?.to_object(py),
-                    Some(obj) => obj.clone_ref(py),
-                };
-
-                inner.cast_as::<PySet>(py)?.add(item)?;
-                slf.borrow_mut().inner = Some(inner);
-                Ok(())
+                match self.inner.get_unique(py)? {
+                    Some(inner) => {
+                        // `__eq__` may empty the set before failing
+                        let result = inner.as_ref(py).add(item);
+                        self.commit(inner);
+                        result
+                    }
+                    None => {
+                        let set = PySet::new(py, &[item])?.into();
+                        self.attach(py, set, "update")
+                    }
+                }
             }
 
-            fn clear(slf: &PyCell<Self>) -> PyResult<()> {
-                slf.borrow_mut().inner = None;
+            #[text_signature = "($self)"]
+            fn clear(&self) -> PyResult<()> {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                // clear in place so that running iterators see the set shrink,
+                // the set is then released unless an iterator still uses it
+                if let Some(inner) = self.inner.get_unique(py)? {
+                    inner.as_ref(py).clear();
+                    self.commit(inner);
+                }
                 Ok(())
             }
 
+            #[text_signature = "($self)"]
             fn copy(&self) -> PyResult<Self> {
-                match self.inner {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                // share the set with the copy in copy-on-write mode, it will
+                // be copied by whichever instance is modified first
+                if COPY_ON_WRITE.load(Ordering::Relaxed) {
+                    if let Some(inner) = self.inner.share(py) {
+                        return Ok(Self::from_shared(inner));
+                    }
+                }
+                match self.inner.get(py) {
+                    None => Ok(Self::new()),
+                    Some(inner) => new_set(py, Some(inner.as_ref(py))).map(Self::from_set),
+                }
+            }
+
+            /// Iterate over the elements in a stable order.
+            ///
+            /// Integers, floats, strings, bytes and tuples of them are sorted,
+            /// and the other elements are grouped by type and ordered by a
+            /// key built from their `repr`, so that the order does not depend
+            /// on hash randomization.
+            #[text_signature = "($self)"]
+            fn sorted_iter(slf: PyRef<Self>) -> PyResult<NanoSetIterator> {
+                let py = slf.py();
+                let owner = unsafe { py.from_borrowed_ptr::<PyAny>(slf.as_ptr()) };
+                NanoSetIterator::sorted(py, owner, Self::with_slot)
+            }
+
+            #[text_signature = "($self)"]
+            fn freeze(&self) -> PyResult<PyObject> {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                match self.inner.get(py) {
+                    None => empty(py),
+                    Some(inner) => unsafe {
+                        let frozen = pyo3::ffi::PyFrozenSet_New(inner.as_ptr());
+                        PyObject::from_owned_ptr_or_err(py, frozen)
+                    },
+                }
+            }
+
+            #[text_signature = "($self)"]
+            fn __sizeof__(&self) -> PyResult<usize> {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                // count the wrapped set, but not the elements, like `set` does,
+                // including an empty set kept allocated while being iterated
+                let ty = <$cls as pyo3::type_object::PyTypeObject>::type_object(py);
+                let size = ty.getattr("__basicsize__")?.extract::<usize>()?;
+                match self.inner.get_raw(py) {
+                    None => Ok(size),
+                    Some(inner) => Ok(size + inner.as_ref(py).call_method0("__sizeof__")?.extract::<usize>()?),
+                }
+            }
+
+            #[classmethod]
+            #[text_signature = "($cls, buffer)"]
+            fn from_array(_cls: &PyType, buffer: &PyAny) -> PyResult<Self> {
+                let py = buffer.py();
+                match array::set_from_buffer(py, buffer)? {
                     None => Ok(Self::new()),
-                    Some(ref inner) => {
-                        let gil = Python::acquire_gil();
-                        let py = gil.python();
-                        inner.call_method0(py, "copy").map(Self::from_set)
+                    Some(set) => Ok(Self::from_set(set)),
+                }
+            }
+
+            #[text_signature = "($self)"]
+            fn to_array(&self) -> PyResult<Int64Array> {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                array::to_array(py, self.inner.get(py))
+            }
+
+            #[text_signature = "($self)"]
+            fn __copy__(&self) -> PyResult<Self> {
+                self.copy()
+            }
+
+            #[text_signature = "($self, memo)"]
+            fn __deepcopy__(slf: &PyCell<Self>, memo: &PyDict) -> PyResult<PyObject> {
+                // return the copy already made if we were copied before
+                let py = memo.py();
+                let id = slf.as_ptr() as usize;
+                if let Some(copy) = memo.get_item(id) {
+                    return Ok(copy.to_object(py));
+                }
+
+                // record the copy in the memo before copying the elements,
+                // so that the elements referencing this set see the copy
+                let copy = Py::new(py, Self::new())?;
+                memo.set_item(id, &copy)?;
+
+                // deep copy all the elements into a new set
+                if let Some(inner) = slf.try_borrow()?.inner.get(py) {
+                    let deepcopy = py.import("copy")?.get("deepcopy")?;
+                    let set = PySet::empty(py)?;
+                    for item in PyIterator::from_object(py, &inner)? {
+                        set.add(deepcopy.call1((item?, memo))?)?;
+                    }
+                    if !set.is_empty() {
+                        copy.try_borrow(py)?.inner.replace(Some(set.into()));
                     }
                 }
+
+                Ok(copy.into())
             }
 
             #[args(others = "*")]
-            fn difference(slf: &PyCell<Self>, others: &PyTuple) -> PyResult<Self> {
+            #[text_signature = "($self, *others)"]
+            fn difference(&self, others: &PyTuple) -> PyResult<Self> {
                 // check if we got an argument, otherwise just copy the current
                 // set as the result
                 if others.is_empty() {
-                    return slf.borrow().copy();
+                    return self.copy();
                 }
 
                 // get the inner set object or return an empty one
                 let py = others.py();
-                let inner = match slf.borrow_mut().inner.as_ref() {
-                    None => PySet::empty(py)?.to_object(py),
-                    Some(obj) => obj.clone_ref(py),
-                };
+                let inner = self.inner_or_empty(py)?;
 
                 // create the union and wrap it in a new NanoSet
-                inner.call_method1(py, "difference", others)
-                    .and_then(|obj| Self::try_from_obj(py, obj))
+                let result = inner.as_ref(py).call_method1("difference", others)
+                    .and_then(|obj| Self::try_from_any(py, obj));
+                self.commit(inner);
+                result
             }
 
             #[args(others = "*")]
-            fn difference_update(slf: &PyCell<Self>, others: &PyTuple) -> PyResult<()> {
+            #[text_signature = "($self, *others)"]
+            fn difference_update(&self, others: &PyTuple) -> PyResult<()> {
                 // bail out early if we are not given any argument
                 if others.is_empty() {
                     return Ok(());
@@ -212,57 +605,53 @@ macro_rules! common_impl {
 
                 // get the inner set object if the set is not empty
                 let py = others.py();
-                let inner = match slf.borrow_mut().inner.as_ref() {
-                    None => PySet::empty(py)?.to_object(py),
-                    Some(obj) => obj.clone_ref(py),
-                };
-
-                // update with the given arguments
-                inner.call_method1(py, "difference_update", others)?;
-                if inner.cast_as::<PySet>(py)?.is_empty() {
-                    slf.borrow_mut().inner = None;
-                }
-
-                Ok(())
+                let inner = self.unique_or_empty(py)?;
+
+                // update with the given arguments, which may fail after some
+                // elements were already removed
+                let others = self.substitute(others, &inner);
+                let result = inner.as_ref(py).call_method1("difference_update", others).map(drop);
+                self.commit(inner);
+                result
             }
 
-            fn discard(slf: &PyCell<Self>, elem: &PyAny) -> PyResult<()> {
+            #[text_signature = "($self, elem)"]
+            fn discard(&self, elem: &PyAny) -> PyResult<()> {
                 let py = elem.py();
-                let inner = match slf.borrow().inner {
-                    None => return Ok(()),
-                    Some(ref obj) => obj.clone_ref(py),
+                let inner = match self.inner.get_unique(py)? {
+                    None => return Self::check_hashable(elem),
+                    Some(obj) => obj,
                 };
 
-                inner.call_method1(py, "discard", (elem,))?;
-                if inner.cast_as::<PySet>(py)?.is_empty() {
-                    slf.borrow_mut().inner = None;
-                }
-                Ok(())
+                let result = inner.as_ref(py).call_method1("discard", (elem,)).map(drop);
+                self.commit(inner);
+                result
             }
 
             #[args(others = "*")]
-            fn intersection(slf: &PyCell<Self>, others: &PyTuple) -> PyResult<Self> {
+            #[text_signature = "($self, *others)"]
+            fn intersection(&self, others: &PyTuple) -> PyResult<Self> {
                 // check if we got an argument, otherwise just copy the current
                 // set as the result
                 if others.is_empty() {
-                    return slf.borrow().copy();
+                    return self.copy();
                 }
 
                 // get the inner set object or return an empty one since
                 // intersection with an empty set is always empty
                 let py = others.py();
-                let inner = match slf.borrow().inner {
-                    Some(ref obj) => obj.clone_ref(py),
-                    None => PySet::empty(py)?.to_object(py),
-                };
+                let inner = self.inner_or_empty(py)?;
 
                 // create the union and wrap it in a new NanoSet
-                inner.call_method1(py, "intersection", others)
-                    .and_then(|obj| Self::try_from_obj(py, obj))
+                let result = inner.as_ref(py).call_method1("intersection", others)
+                    .and_then(|obj| Self::try_from_any(py, obj));
+                self.commit(inner);
+                result
             }
 
             #[args(others = "*")]
-            fn intersection_update(slf: &PyCell<Self>, others: &PyTuple) -> PyResult<()> {
+            #[text_signature = "($self, *others)"]
+            fn intersection_update(&self, others: &PyTuple) -> PyResult<()> {
                 // bail out early if we are not given any argument
                 if others.is_empty() {
                     return Ok(());
@@ -270,173 +659,153 @@ macro_rules! common_impl {
 
                 // get the inner set object if the set is not empty
                 let py = others.py();
-                let inner = match slf.borrow_mut().inner.as_ref() {
-                    None => PySet::empty(py)?.to_object(py),
-                    Some(obj) => obj.clone_ref(py),
-                };
+                let inner = self.unique_or_empty(py)?;
 
                 // update with the given arguments
-                inner.call_method1(py, "intersection_update", others)?;
-                if inner.cast_as::<PySet>(py)?.is_empty() {
-                    slf.borrow_mut().inner = None;
-                }
-
-                Ok(())
+                let others = self.substitute(others, &inner);
+                let result = inner.as_ref(py).call_method1("intersection_update", others).map(drop);
+                self.commit(inner);
+                result
             }
 
-            fn isdisjoint(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+            #[text_signature = "($self, other)"]
+            fn isdisjoint(&self, other: &PyAny) -> PyResult<PyObject> {
                 let py = other.py();
-
-                let inner = match slf.borrow().inner.as_ref() {
-                    None => PySet::empty(py)?.to_object(py),
-                    Some(obj) => obj.clone_ref(py),
-                };
-
-                inner.call_method1(py, "isdisjoint", (other,))
+                let inner = self.inner_or_empty(py)?;
+                let result = inner.as_ref(py).call_method1("isdisjoint", (other,)).map(|r| r.to_object(py));
+                self.commit(inner);
+                result
             }
 
-            fn issubset(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+            #[text_signature = "($self, other)"]
+            fn issubset(&self, other: &PyAny) -> PyResult<PyObject> {
                 let py = other.py();
-
-                let inner = match slf.borrow().inner.as_ref() {
-                    None => PySet::empty(py)?.to_object(py),
-                    Some(obj) => obj.clone_ref(py),
-                };
-
-                inner.call_method1(py, "issubset", (other,))
+                let inner = self.inner_or_empty(py)?;
+                let result = inner.as_ref(py).call_method1("issubset", (other,)).map(|r| r.to_object(py));
+                self.commit(inner);
+                result
             }
 
-            fn issuperset(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+            #[text_signature = "($self, other)"]
+            fn issuperset(&self, other: &PyAny) -> PyResult<PyObject> {
                 let py = other.py();
-
-                let inner = match slf.borrow().inner.as_ref() {
-                    None => PySet::empty(py)?.to_object(py),
-                    Some(obj) => obj.clone_ref(py),
-                };
-
-                inner.call_method1(py, "issuperset", (other,))
+                let inner = self.inner_or_empty(py)?;
+                let result = inner.as_ref(py).call_method1("issuperset", (other,)).map(|r| r.to_object(py));
+                self.commit(inner);
+                result
             }
 
-            fn pop(slf: &PyCell<Self>) -> PyResult<PyObject> {
+            #[text_signature = "($self)"]
+            fn pop(&self) -> PyResult<PyObject> {
                 // get the inner set if it is not empty
                 let gil = Python::acquire_gil();
                 let py = gil.python();
-                let inner = match slf.borrow().inner {
+                let inner = match self.inner.get_unique(py)? {
                     None => return KeyError::into("pop from an empty set"),
-                    Some(ref inner) => inner.clone_ref(py),
+                    Some(inner) => inner,
                 };
 
-                // pop from the set (which is not empty)
-                let set = inner.cast_as::<PySet>(py)
-                    .expect("inner set is always a `PySet`");
-                let item = set.pop().expect("inner set is never empty");
+                // pop from the set, which may have been emptied by another
+                // thread since we got it from the slot
+                let item = inner.as_ref(py).call_method0("pop")?.to_object(py);
 
                 // take care to clear the inner set if we exhausted it
-                if set.is_empty() {
-                    slf.borrow_mut().inner = None;
-                }
-
+                self.commit(inner);
                 Ok(item)
             }
 
-            fn remove(slf: &PyCell<Self>, item: &PyAny) -> PyResult<()> {
+            #[text_signature = "($self, item)"]
+            fn remove(&self, item: &PyAny) -> PyResult<()> {
                 let py = item.py();
-
-                let inner = match slf.borrow().inner {
-                    None => return KeyError::into(item.to_object(py)),
-                    Some(ref obj) => obj.clone_ref(py),
+                let inner = match self.inner.get_unique(py)? {
+                    None => {
+                        Self::check_hashable(item)?;
+                        return KeyError::into(item.to_object(py));
+                    }
+                    Some(obj) => obj,
                 };
 
                 // `set2.remove(set1)` actually does for
                 // `set2.remove(frozenset(set1))`, so we have to check if
                 // `set1` is `NanoSet` to reproduce that behaviour.
-                if let Ok(ref other) = item.extract::<PyRef<$cls>>() {
-                    if let Some(ref obj) = other.inner {
-                        inner.call_method1(py, "remove", (obj.clone_ref(py),))?
-                    } else {
-                        inner.call_method1(py, "remove", (PyFrozenSet::empty(py)?,))?
-                    };
-                } else {
-                    inner.call_method1(py, "remove", (item,))?;
-                }
+                let result = match Self::try_borrow(item)? {
+                    Some(ref other) => match other.inner.get(py) {
+                        Some(obj) => inner.as_ref(py).call_method1("remove", (obj,)),
+                        None => inner.as_ref(py).call_method1("remove", (PyFrozenSet::empty(py)?,)),
+                    },
+                    None => inner.as_ref(py).call_method1("remove", (item,)),
+                }.map(drop);
 
                 // after removing the item we check if the set is empty
-                // to maintain the invariant
-                if inner.cast_as::<PySet>(py).unwrap().is_empty() {
-                    slf.borrow_mut().inner = None
-                }
-
-                Ok(())
+                // to maintain the invariant, even if `__eq__` emptied the
+                // set before failing
+                self.commit(inner);
+                result
             }
 
-            fn symmetric_difference(slf: &PyCell<Self>, other: &PyAny) -> PyResult<Self> {
+            #[text_signature = "($self, other)"]
+            fn symmetric_difference(&self, other: &PyAny) -> PyResult<Self> {
                 // get the inner set or create a new one
                 let py = other.py();
-                let inner = match slf.borrow().inner.as_ref() {
-                    None => PySet::empty(py)?.to_object(py),
-                    Some(s) => s.clone_ref(py),
-                };
+                let inner = self.inner_or_empty(py)?;
 
                 // compute the symmetric difference
-                inner.call_method1(py, "symmetric_difference", (other,))
-                    .and_then(|obj| Self::try_from_obj(py, obj))
+                let result = inner.as_ref(py).call_method1("symmetric_difference", (other,))
+                    .and_then(|obj| Self::try_from_any(py, obj));
+                self.commit(inner);
+                result
             }
 
-            fn symmetric_difference_update(slf: &PyCell<Self>, other: &PyAny) -> PyResult<()> {
+            #[text_signature = "($self, other)"]
+            fn symmetric_difference_update(&self, other: &PyAny) -> PyResult<()> {
                 // get the inner set object or create a new one
                 let py = other.py();
-                let inner = match slf.borrow_mut().inner.as_ref() {
-                    None => PySet::empty(py)?.to_object(py),
-                    Some(obj) => obj.clone_ref(py),
-                };
-
-                // update with the given arguments and update the wrapped object
-                inner.call_method1(py, "symmetric_difference_update", (other,))?;
-                if !inner.cast_as::<PySet>(py)?.is_empty() {
-                    slf.borrow_mut().inner = Some(inner);
+                if util::is_self(self, other) {
+                    return self.clear();
                 }
-
-                Ok(())
+                let inner = self.unique_or_attach(py)?;
+                let result = inner.as_ref(py).call_method1("symmetric_difference_update", (other,)).map(drop);
+                self.commit(inner);
+                result
             }
 
             #[args(others = "*")]
-            fn union(slf: &PyCell<Self>, others: &PyTuple) -> PyResult<Self> {
+            #[text_signature = "($self, *others)"]
+            fn union(&self, others: &PyTuple) -> PyResult<Self> {
                 // check if we got an argument, otherwise just copy the current
                 // set as the result
                 if others.is_empty() {
-                    return slf.borrow().copy();
+                    return self.copy();
                 }
 
                 // get the inner set object or create a new one
                 let py = others.py();
-                let inner = match slf.borrow_mut().inner.as_ref() {
-                    None => PySet::empty(py)?.to_object(py),
-                    Some(obj) => obj.clone_ref(py),
-                };
+                let inner = self.inner_or_empty(py)?;
 
                 // create the union and wrap it in a new NanoSet
-                inner.call_method1(py, "union", others)
-                    .and_then(|obj| Self::try_from_obj(py, obj))
+                let result = inner.as_ref(py).call_method1("union", others)
+                    .and_then(|obj| Self::try_from_any(py, obj));
+                self.commit(inner);
+                result
             }
 
             #[args(others = "*")]
-            fn update(slf: &PyCell<Self>, others: &PyTuple) -> PyResult<()> {
-                // only attempt to borrow self if we are actually given some
-                // arguments to process
+            #[text_signature = "($self, *others)"]
+            fn update(&self, others: &PyTuple) -> PyResult<()> {
+                // only attempt to get the inner set if we are actually given
+                // some arguments to process
                 if !others.is_empty() {
                     // get the inner set object or create a new one
                     let py = others.py();
-                    let inner = match slf.borrow_mut().inner.take() {
-                        None => PySet::empty(py)?.to_object(py),
-                        Some(obj) => obj.clone_ref(py),
-                    };
-
-                    // update with the given arguments and update the wrapped
-                    // or set it to the new set only if it is not empty
-                    inner.call_method1(py, "update", others)?;
-                    if !inner.cast_as::<PySet>(py)?.is_empty() {
-                        slf.borrow_mut().inner = Some(inner);
-                    }
+                    // update the set stored in the slot, so that elements
+                    // modifying the instance see the elements added before
+                    // them, and keep these elements on errors like builtin
+                    // sets do
+                    let inner = self.unique_or_attach(py)?;
+                    let others = self.substitute(others, &inner);
+                    let result = inner.as_ref(py).call_method1("update", others).map(drop);
+                    self.commit(inner);
+                    result?;
                 }
 
                 Ok(())
@@ -445,82 +814,99 @@ macro_rules! common_impl {
 
         #[pyproto]
         impl PyIterProtocol for $cls {
-            fn __iter__(slf: PyRefMut<Self>) -> PyResult<PyObject> {
-                let gil = Python::acquire_gil();
-                let py = gil.python();
-                match slf.inner.as_ref() {
-                    None => PyTuple::empty(py)
-                        .to_object(py)
-                        .call_method0(py, "__iter__"),
-                    Some(inner) => inner
-                        .call_method0(py, "__iter__"),
+            fn __iter__(slf: PyRef<Self>) -> PyResult<NanoSetIterator> {
+                let py = slf.py();
+                let owner = unsafe { py.from_borrowed_ptr::<PyAny>(slf.as_ptr()) };
+                if order::is_deterministic() {
+                    NanoSetIterator::sorted(py, owner, Self::with_slot)
+                } else {
+                    NanoSetIterator::new(py, owner, Self::with_slot)
                 }
             }
         }
 
         #[pyproto]
         impl PyNumberProtocol for $cls {
-            fn __and__(lhs: &PyCell<Self>, rhs: &PyAny) -> PyResult<PyObject> {
+            fn __and__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
                 let py = rhs.py();
-
-                if rhs.cast_as::<PySet>().is_err()
-                    && rhs.cast_as::<PyFrozenSet>().is_err()
-                    && rhs.extract::<PyRef<$cls>>().is_err()
-                {
-                    return Ok(py.NotImplemented());
-                }
-
-                let args: Py<PyTuple> = (rhs,).into_py(py);
-                Self::intersection(lhs, &args.as_ref(py))
+                let (this, other, _) = match util::operands::<Self>(lhs, rhs, Self::is_operand)? {
+                    None => return Ok(py.NotImplemented()),
+                    Some(operands) => operands,
+                };
+                let args: Py<PyTuple> = (other,).into_py(py);
+                this.intersection(&args.as_ref(py))
                     .and_then(|s| Py::new(py, s))
                     .map(PyObject::from)
             }
 
-            fn __sub__(lhs: &PyCell<Self>, rhs: &PyAny) -> PyResult<PyObject> {
+            fn __sub__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
                 let py = rhs.py();
+                let (this, other, reflected) = match util::operands::<Self>(lhs, rhs, Self::is_operand)? {
+                    None => return Ok(py.NotImplemented()),
+                    Some(operands) => operands,
+                };
+                let result = if reflected {
+                    // `other` is on the left of the operator, and `rhs` is us
+                    let result = Self::try_from_any(py, other)?;
+                    let args: Py<PyTuple> = (rhs,).into_py(py);
+                    result.difference_update(&args.as_ref(py))?;
+                    result
+                } else {
+                    let args: Py<PyTuple> = (other,).into_py(py);
+                    this.difference(&args.as_ref(py))?
+                };
+                Py::new(py, result).map(PyObject::from)
+            }
 
-                if rhs.cast_as::<PySet>().is_err()
-                    && rhs.cast_as::<PyFrozenSet>().is_err()
-                    && rhs.extract::<PyRef<$cls>>().is_err()
-                {
-                    return Ok(py.NotImplemented());
-                }
-
-                let args: Py<PyTuple> = (rhs,).into_py(py);
-                Self::difference(lhs, &args.as_ref(py))
+            fn __or__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+                let py = rhs.py();
+                let (this, other, _) = match util::operands::<Self>(lhs, rhs, Self::is_operand)? {
+                    None => return Ok(py.NotImplemented()),
+                    Some(operands) => operands,
+                };
+                let args: Py<PyTuple> = (other,).into_py(py);
+                this.union(&args.as_ref(py))
                     .and_then(|s| Py::new(py, s))
                     .map(PyObject::from)
             }
 
-            fn __or__(lhs: &PyCell<Self>, rhs: &PyAny) -> PyResult<PyObject> {
+            fn __xor__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
                 let py = rhs.py();
-
-                if rhs.cast_as::<PySet>().is_err()
-                    && rhs.cast_as::<PyFrozenSet>().is_err()
-                    && rhs.extract::<PyRef<$cls>>().is_err()
-                {
-                    return Ok(py.NotImplemented());
-                }
-
-                let args: Py<PyTuple> = (rhs,).into_py(py);
-                Self::union(lhs, &args.as_ref(py))
+                let (this, other, _) = match util::operands::<Self>(lhs, rhs, Self::is_operand)? {
+                    None => return Ok(py.NotImplemented()),
+                    Some(operands) => operands,
+                };
+                this.symmetric_difference(other)
                     .and_then(|s| Py::new(py, s))
                     .map(PyObject::from)
             }
 
-            fn __xor__(lhs: &PyCell<Self>, rhs: &PyAny) -> PyResult<PyObject> {
-                let py = rhs.py();
+            fn __iand__(&mut self, other: &PyAny) -> PyResult<()> {
+                match util::inplace_operand(self, other, "&=", Self::is_operand)? {
+                    None => Ok(()),
+                    Some(other) => self.intersection_update(PyTuple::new(other.py(), &[other])),
+                }
+            }
 
-                if rhs.cast_as::<PySet>().is_err()
-                    && rhs.cast_as::<PyFrozenSet>().is_err()
-                    && rhs.extract::<PyRef<$cls>>().is_err()
-                {
-                    return Ok(py.NotImplemented());
+            fn __isub__(&mut self, other: &PyAny) -> PyResult<()> {
+                match util::inplace_operand(self, other, "-=", Self::is_operand)? {
+                    None => self.clear(),
+                    Some(other) => self.difference_update(PyTuple::new(other.py(), &[other])),
                 }
+            }
 
-                Self::symmetric_difference(lhs, rhs)
-                    .and_then(|s| Py::new(py, s))
-                    .map(PyObject::from)
+            fn __ior__(&mut self, other: &PyAny) -> PyResult<()> {
+                match util::inplace_operand(self, other, "|=", Self::is_operand)? {
+                    None => Ok(()),
+                    Some(other) => self.update(PyTuple::new(other.py(), &[other])),
+                }
+            }
+
+            fn __ixor__(&mut self, other: &PyAny) -> PyResult<()> {
+                match util::inplace_operand(self, other, "^=", Self::is_operand)? {
+                    None => self.clear(),
+                    Some(other) => self.symmetric_difference_update(other),
+                }
             }
         }
 
@@ -529,30 +915,70 @@ macro_rules! common_impl {
             fn __repr__(&self) -> PyResult<PyObject> {
                 let gil = Python::acquire_gil();
                 let py = gil.python();
-                match self.inner {
-                    None => {
-                        // let s = concat!(stringify!($cls), "()");
-                        // Ok(s.to_object(py))
-                        Ok("set()".to_object(py))
-                    }
-                    Some(ref inner) => {
-                        // let s = PyString::new(py, concat!(stringify!($cls), "({})"));
-                        // s.to_object(py).call_method1(py, "format", (inner,))
-                        inner.call_method0(py, "__repr__")
-                    }
+                let legacy = LEGACY_REPR.load(Ordering::Relaxed);
+                let deterministic = order::is_deterministic();
+                let inner = match self.inner.get(py) {
+                    Some(inner) => inner,
+                    None if legacy => return Ok("set()".to_object(py)),
+                    None => return Ok(concat!(stringify!($cls), "()").to_object(py)),
+                };
+                if legacy && !deterministic {
+                    return inner.as_ref(py).repr().map(|r| r.to_object(py));
+                }
+
+                // the wrapped set is never exposed, so it can stand for the
+                // wrapper when detecting recursive calls
+                match unsafe { pyo3::ffi::Py_ReprEnter(inner.as_ptr()) } {
+                    0 => (),
+                    n if n > 0 && legacy => return Ok("set(...)".to_object(py)),
+                    n if n > 0 => return Ok(concat!(stringify!($cls), "(...)").to_object(py)),
+                    _ => return Err(PyErr::fetch(py)),
                 }
+                // format a list copy of the elements, like builtin sets do,
+                // so that mutations from an item `__repr__` are harmless
+                let items = if deterministic {
+                    order::sorted(py, inner.as_ref(py)).map(|list| list.to_object(py))
+                } else {
+                    unsafe { PyObject::from_owned_ptr_or_err(py, pyo3::ffi::PySequence_List(inner.as_ptr())) }
+                };
+                let result = items
+                    .and_then(|items| items.as_ref(py).repr().map(|r| r.to_string_lossy().into_owned()))
+                    .map(|items| {
+                        let elements = &items[1..items.len() - 1];
+                        if legacy {
+                            format!("{{{}}}", elements).to_object(py)
+                        } else {
+                            format!(concat!(stringify!($cls), "({{{}}})"), elements).to_object(py)
+                        }
+                    });
+                unsafe { pyo3::ffi::Py_ReprLeave(inner.as_ptr()) };
+                result
             }
 
             fn __bool__(&self) -> PyResult<bool> {
-                Ok(self.inner.is_some())
+                let gil = Python::acquire_gil();
+                Ok(self.inner.get(gil.python()).is_some())
             }
 
             fn __richcmp__(&self, obj: &PyAny, op: CompareOp) -> PyResult<PyObject> {
                 use self::CompareOp::*;
 
                 let py = obj.py();
-                if let Ok(other) = obj.extract::<PyRef<Self>>() {
-                    match (&self.inner, &other.inner) {
+
+                // compare with the other nanoset type like with a frozenset
+                let obj: &PyAny = if obj.downcast::<PyCell<Self>>().is_err() && Self::is_operand(obj)? {
+                    unsafe { py.from_owned_ptr_or_err(pyo3::ffi::PyFrozenSet_New(obj.as_ptr()))? }
+                } else {
+                    obj
+                };
+
+                let inner = self.inner.get(py);
+                let result = if let Some(other) = Self::try_borrow(obj)? {
+                    let theirs = other.inner.get(py);
+                    let result = match (
+                        inner.as_ref().map(|set| set.to_object(py)),
+                        theirs.as_ref().map(|set| set.clone_ref(py)),
+                    ) {
                         (None, None) => match op {
                             Eq | Le | Ge => Ok(true.to_object(py)),
                             Ne | Lt | Gt => Ok(false.to_object(py)),
@@ -573,9 +999,13 @@ macro_rules! common_impl {
                             Gt => l.call_method1(py, "__gt__", (r,)),
                             Ge => l.call_method1(py, "__ge__", (r,)),
                         },
+                    };
+                    if let Some(theirs) = theirs {
+                        other.commit(theirs);
                     }
+                    result
                 } else if let Ok(other) = obj.cast_as::<PySet>() {
-                    match (&self.inner, other.is_empty()) {
+                    match (inner.as_ref().map(|set| set.to_object(py)), other.is_empty()) {
                         (None, true) => match op {
                             Eq | Le | Ge => Ok(true.to_object(py)),
                             Ne | Lt | Gt => Ok(false.to_object(py)),
@@ -594,7 +1024,7 @@ macro_rules! common_impl {
                         },
                     }
                 } else if let Ok(other) = obj.cast_as::<PyFrozenSet>() {
-                    match (&self.inner, other.is_empty()) {
+                    match (inner.as_ref().map(|set| set.to_object(py)), other.is_empty()) {
                         (None, true) => match op {
                             Eq | Le | Ge => Ok(true.to_object(py)),
                             Ne | Lt | Gt => Ok(false.to_object(py)),
@@ -613,46 +1043,50 @@ macro_rules! common_impl {
                         },
                     }
                 } else {
-                    match op {
-                        CompareOp::Eq => Ok(false.to_object(py)),
-                        CompareOp::Ne => Ok(true.to_object(py)),
-                        _ => Ok(py.NotImplemented()),
-                    }
+                    // like builtin sets, let the other operand decide, so
+                    // that set views can be compared in either order
+                    Ok(py.NotImplemented())
+                };
+
+                // the elements compared may have emptied either instance
+                if let Some(inner) = inner {
+                    self.commit(inner);
                 }
+                result
             }
         }
 
         #[pyproto]
         impl PySequenceProtocol for $cls {
             fn __len__(&self) -> PyResult<usize> {
-                match self.inner {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                match self.inner.get(py) {
                     None => Ok(0usize),
-                    Some(ref inner) => {
-                        let gil = Python::acquire_gil();
-                        Ok(inner.cast_as::<PySet>(gil.python())?.len())
-                    }
+                    Some(inner) => Ok(inner.as_ref(py).len()),
                 }
             }
 
             fn __contains__(&self, item: &PyAny) -> PyResult<bool> {
                 let py = item.py();
-                if let Some(ref obj) = self.inner {
+                if let Some(obj) = self.inner.get(py) {
                     // `set1 in set2` actually checks for
-                    // `frozenset(set1) in set2`, so we have to check if
-                    // `set1` is `NanoSet` to reproduce that behaviour.
-                    if let Ok(ref other) = item.extract::<PyRef<$cls>>() {
-                        let set = obj.cast_as::<PySet>(py).unwrap();
-                        match other.inner {
-                            Some(ref obj) => set.contains(obj),
-                            None => set.contains(PyFrozenSet::empty(py)?),
-                        }
-                    } else {
-                        obj.call_method1(py, "__contains__", (item,))
-                            .and_then(|val| val.extract(py))
-                    }
+                    // `frozenset(set1) in set2`, which `sq_contains` does
+                    // for builtin sets, so we have to check if `set1` is
+                    // `NanoSet` to reproduce that behaviour.
+                    let set = obj.as_ref(py);
+                    let result = match Self::try_borrow(item)? {
+                        Some(ref other) => match other.inner.get(py) {
+                            Some(obj) => util::contains(set, obj.as_ref(py)),
+                            None => util::contains(set, PyFrozenSet::empty(py)?),
+                        },
+                        None => util::contains(set, item),
+                    };
+                    self.commit(obj);
+                    result
                 } else {
                     // an empty set never contains anything.
-                    Ok(false)
+                    Self::check_hashable(item).map(|_| false)
                 }
             }
         }
@@ -663,21 +1097,39 @@ macro_rules! common_impl {
 // ---------------------------------------------------------------------------
 
 #[pyclass(gc, module = "nanoset")]
-#[derive(Debug, Default)]
+#[text_signature = "(iterable=None)"]
+#[derive(Debug)]
 /// A set that has lower memory footprint if it is empty.
 pub struct NanoSet {
-    inner: Option<PyObject>,
+    inner: Slot,
+}
+
+impl NanoSet {
+    fn slot(value: Option<Py<PySet>>) -> Slot {
+        Slot::new(value)
+    }
 }
 
 common_impl!(NanoSet);
 
+#[pymethods]
+impl NanoSet {
+    /// Create a set view over the keys of `mapping`, without copying them.
+    ///
+    /// The view reflects the changes of the mapping until it is modified
+    /// itself, at which point the keys are copied to a `NanoSet` wrapped by
+    /// the view, and the mapping is left untouched.
+    #[classmethod]
+    #[text_signature = "($cls, mapping)"]
+    fn view(_cls: &PyType, mapping: &PyAny) -> PyResult<NanoSetView> {
+        NanoSetView::from_mapping(mapping)
+    }
+}
+
 #[pyproto]
 impl PyGCProtocol for NanoSet {
     fn __traverse__(&'p self, visit: PyVisit) -> Result<(), PyTraverseError> {
-        match self.inner {
-            Some(ref obj) => visit.call(obj),
-            None => Ok(()),
-        }
+        self.inner.traverse(&visit)
     }
 
     fn __clear__(&'p mut self) {
@@ -691,24 +1143,93 @@ impl PyGCProtocol for NanoSet {
 // ---------------------------------------------------------------------------
 
 #[pyclass(module = "nanoset")]
-#[derive(Debug, Default)]
+#[text_signature = "(iterable=None)"]
+#[derive(Debug)]
 /// A set that has lower memory footprint if it is empty.
 pub struct PicoSet {
-    inner: Option<PyObject>,
+    inner: Slot,
+}
+
+impl PicoSet {
+    // the wrapped set is invisible to the GC, so track it in the registry
+    fn slot(value: Option<Py<PySet>>) -> Slot {
+        Slot::hidden(value)
+    }
 }
 
 common_impl!(PicoSet);
 
 // ---------------------------------------------------------------------------
 
+/// Set whether instances are formatted like builtin sets by `repr`.
+///
+/// This can be used to keep doctests written for a previous version of the
+/// library passing. Returns the previous value of the flag.
+#[pyfunction]
+#[text_signature = "(legacy)"]
+pub fn set_legacy_repr(legacy: bool) -> bool {
+    LEGACY_REPR.swap(legacy, Ordering::Relaxed)
+}
+
+/// Set whether `copy` uses copy-on-write.
+///
+/// In copy-on-write mode, the set wrapped by an instance is shared with its
+/// copies, and only copied when one of them is first modified, making
+/// `copy` a constant-time operation. Returns the previous value of the flag.
+#[pyfunction]
+#[text_signature = "(enabled)"]
+pub fn set_copy_on_write(enabled: bool) -> bool {
+    COPY_ON_WRITE.swap(enabled, Ordering::Relaxed)
+}
+
+// ---------------------------------------------------------------------------
+
 #[cfg_attr(feature = "extension-module", pymodule(nanoset))]
 pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+    // the slots rely on the GIL to serialize the accesses to the wrapped
+    // sets, so refuse to run on a free-threaded interpreter without it
+    if let Ok(is_gil_enabled) = py.import("sys")?.getattr("_is_gil_enabled") {
+        if !is_gil_enabled.call0()?.is_true()? {
+            return ImportError::into("nanoset cannot be used with the GIL disabled");
+        }
+    }
+
     m.add_class::<NanoSet>()?;
     m.add_class::<PicoSet>()?;
+    m.add_class::<NanoSetIterator>()?;
+    m.add_class::<Int64Array>()?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(set_legacy_repr))?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(set_copy_on_write))?;
+    persistent::init(py, m)?;
+    concurrent::init(py, m)?;
+    view::init(py, m)?;
+    bulk::init(m)?;
+    order::init(m)?;
+    factory::init(py, m)?;
+    m.add("EMPTY", empty(py)?)?;
+    #[cfg(feature = "stats")]
+    stats::init(m)?;
+
+    let debug = PyModule::new(py, "nanoset.debug")?;
+    debug::init(py, debug)?;
+    m.add("debug", debug)?;
+    py.import("sys")?.get("modules")?.set_item("nanoset.debug", debug)?;
+
+    let json = PyModule::new(py, "nanoset.json")?;
+    json::init(py, json)?;
+    m.add("json", json)?;
+    py.import("sys")?.get("modules")?.set_item("nanoset.json", json)?;
+    m.add("__divergences__", DIVERGENCES.iter().cloned().into_py_dict(py))?;
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;
     m.add("__author__", env!("CARGO_PKG_AUTHORS").replace(':', "\n"))?;
     m.add("__build__", pyo3_built::pyo3_built!(py, built))?;
 
+    let copyreg = py.import("copyreg")?;
+    let nanoset = <NanoSet as pyo3::type_object::PyTypeObject>::type_object(py);
+    copyreg.call1("pickle", (nanoset, nanoset.getattr("__reduce__")?, nanoset))?;
+    let picoset = <PicoSet as pyo3::type_object::PyTypeObject>::type_object(py);
+    copyreg.call1("pickle", (picoset, picoset.getattr("__reduce__")?, picoset))?;
+
     let cabc = py.import("collections.abc")?;
     let set = cabc.get("Set")?.to_object(py);
     set.call_method1(
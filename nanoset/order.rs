@@ -0,0 +1,173 @@
+//! Deterministic ordering of the elements of a set.
+//!
+//! The iteration order of a set depends on the hashes of its elements, which
+//! change between interpreter runs for `str` and `bytes` unless the
+//! `PYTHONHASHSEED` environment variable is set. The elements listed here
+//! are instead ordered by their values only: they are sorted with `<` when
+//! it is a total order on all of them, and otherwise grouped by type, then
+//! sorted with `<` within each type where it is a total order, or by a
+//! canonical key built from their `repr` otherwise.
+//!
+//! `<` is only trusted for the builtin types known to be totally ordered by
+//! it. Sorting other values with `<` could give an order depending on the
+//! order of the input, e.g. for sets and frozensets, for which `<` is the
+//! subset relation.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use pyo3::exceptions::TypeError;
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use pyo3::types::PyList;
+use pyo3::types::PyTuple;
+use pyo3::AsPyPointer;
+
+/// Whether nanosets iterate, format and pickle their elements in order.
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+/// The nesting depth after which the canonical key of a container falls back
+/// to its `repr`.
+const MAX_DEPTH: usize = 32;
+
+/// Check whether the deterministic mode is enabled.
+pub fn is_deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::Relaxed)
+}
+
+/// Set whether nanosets iterate over their elements in a stable order.
+///
+/// When enabled, `__iter__`, `repr` and pickling list the elements of a
+/// `NanoSet` or a `PicoSet` in the same order as `sorted_iter`, which does
+/// not depend on hash randomization. Builtin sets nested in the elements
+/// still list their own elements in iteration order. Returns the previous
+/// value of the flag.
+#[pyfunction]
+#[text_signature = "(enabled)"]
+fn set_deterministic(enabled: bool) -> bool {
+    DETERMINISTIC.swap(enabled, Ordering::Relaxed)
+}
+
+/// Collect the elements of `set` in a new list, in a stable order.
+pub fn sorted<'p>(py: Python<'p>, set: &PyAny) -> PyResult<&'p PyList> {
+    let list: &PyList = unsafe { py.from_owned_ptr_or_err(pyo3::ffi::PySequence_List(set.as_ptr()))? };
+    if list.iter().all(|item| is_totally_ordered(item, 0)) && sort(py, list)? {
+        return Ok(list);
+    }
+
+    // group the elements by type, so that the elements of each type can be
+    // compared with each other
+    let mut items = list
+        .iter()
+        .map(|item| (type_name(item), item))
+        .collect::<Vec<_>>();
+    items.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut position = 0;
+    for group in items.chunk_by(|(a, _), (b, _)| a == b) {
+        let group = PyList::new(py, group.iter().map(|(_, item)| item));
+        if !(group.iter().all(|item| is_totally_ordered(item, 0)) && sort(py, group)?) {
+            let mut keyed = group
+                .iter()
+                .map(|item| canonical(item).map(|key| (key, item)))
+                .collect::<PyResult<Vec<_>>>()?;
+            keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (i, (_, item)) in keyed.into_iter().enumerate() {
+                group.set_item(i as isize, item)?;
+            }
+        }
+        for item in group.iter() {
+            list.set_item(position, item)?;
+            position += 1;
+        }
+    }
+
+    Ok(list)
+}
+
+/// Check whether `item` is of a type totally ordered by `<`, so that sorting
+/// it with `<` does not depend on the order of the input.
+///
+/// Only the exact builtin types are checked, since subclasses may override
+/// `<`, and floats are only ordered when they are not NaN.
+fn is_totally_ordered(item: &PyAny, depth: usize) -> bool {
+    let ptr = item.as_ptr();
+    unsafe {
+        if pyo3::ffi::PyLong_CheckExact(ptr) != 0
+            || pyo3::ffi::PyBool_Check(ptr) != 0
+            || pyo3::ffi::PyUnicode_CheckExact(ptr) != 0
+            || pyo3::ffi::PyBytes_CheckExact(ptr) != 0
+        {
+            return true;
+        }
+        if pyo3::ffi::PyFloat_CheckExact(ptr) != 0 {
+            return !pyo3::ffi::PyFloat_AS_DOUBLE(ptr).is_nan();
+        }
+        if pyo3::ffi::PyTuple_CheckExact(ptr) == 0 || depth >= MAX_DEPTH {
+            return false;
+        }
+    }
+    item.downcast::<PyTuple>()
+        .is_ok_and(|tuple| tuple.iter().all(|item| is_totally_ordered(item, depth + 1)))
+}
+
+/// Sort `list` in place with `<`, returning `false` if the elements of
+/// `list` cannot be compared with each other.
+fn sort(py: Python, list: &PyList) -> PyResult<bool> {
+    if unsafe { pyo3::ffi::PyList_Sort(list.as_ptr()) } == 0 {
+        return Ok(true);
+    }
+    let err = PyErr::fetch(py);
+    if err.is_instance::<TypeError>(py) {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+/// Get the name of the type of `item`.
+fn type_name(item: &PyAny) -> String {
+    item.get_type().name().into_owned()
+}
+
+/// Build a key for `item` which does not depend on hash randomization.
+///
+/// The key of a `set` or `frozenset` lists the keys of its elements in
+/// order, and the key of a `tuple` the keys of its items, since their
+/// `repr` would list their elements in iteration order.
+fn canonical(item: &PyAny) -> PyResult<String> {
+    let mut key = String::new();
+    write_key(&mut key, item, 0)?;
+    Ok(key)
+}
+
+fn write_key(out: &mut String, item: &PyAny, depth: usize) -> PyResult<()> {
+    let is_set = unsafe { pyo3::ffi::PyAnySet_Check(item.as_ptr()) } != 0;
+    let is_tuple = item.downcast::<PyTuple>().is_ok();
+    out.push_str(&type_name(item));
+    if depth >= MAX_DEPTH || !(is_set || is_tuple) {
+        out.push(':');
+        out.push_str(&item.repr()?.to_string_lossy());
+        return Ok(());
+    }
+
+    let mut keys = item
+        .iter()?
+        .map(|item| {
+            let mut key = String::new();
+            write_key(&mut key, item?, depth + 1).map(|_| key)
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    if is_set {
+        keys.sort();
+    }
+    out.push('(');
+    out.push_str(&keys.join(", "));
+    out.push(')');
+    Ok(())
+}
+
+pub fn init(m: &PyModule) -> PyResult<()> {
+    m.add_wrapped(pyo3::wrap_pyfunction!(set_deterministic))?;
+    Ok(())
+}
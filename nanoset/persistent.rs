@@ -0,0 +1,516 @@
+//! Persistent sets, sharing structure between successive versions.
+//!
+//! A `PersistentNanoSet` is immutable: methods like `add` or `discard`
+//! return a new set, which shares all the nodes of the trie not on the path
+//! to the modified element with the original. An empty persistent set does
+//! not allocate anything besides the Python object itself.
+
+use std::cell::RefCell;
+use std::sync::atomic::AtomicIsize;
+use std::sync::atomic::Ordering;
+
+use pyo3::class::basic::CompareOp;
+use pyo3::class::PyIterProtocol;
+use pyo3::class::PyNumberProtocol;
+use pyo3::class::PyObjectProtocol;
+use pyo3::class::PySequenceProtocol;
+use pyo3::exceptions::KeyError;
+use pyo3::prelude::*;
+use pyo3::type_object::PyTypeObject;
+use pyo3::types::PyAny;
+use pyo3::types::PyTuple;
+use pyo3::types::PyType;
+use pyo3::AsPyPointer;
+use pyo3::PyNativeType;
+
+use super::hamt::Cursor;
+use super::hamt::Tree;
+use super::util;
+use super::util::as_set;
+use super::util::contains;
+use super::util::is_set;
+
+thread_local! {
+    /// The persistent sets being formatted by `__repr__` in this thread.
+    static REPR_STACK: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Get the elements of `obj` in a tree, sharing the tree of persistent sets.
+fn tree_of(obj: &PyAny) -> PyResult<Tree> {
+    match obj.extract::<PyRef<PersistentNanoSet>>() {
+        Ok(set) => Ok(set.tree.clone()),
+        Err(_) => Tree::from_iterable(obj),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+#[pyclass(module = "nanoset")]
+#[text_signature = "(iterable=None)"]
+#[derive(Debug)]
+/// An immutable set sharing structure with the sets derived from it.
+///
+/// Persistent sets are hashable, and can be modified efficiently in batches
+/// with an evolver. Like `PicoSet`, they are not tracked by the garbage
+/// collector, since their nodes can be shared by several instances.
+pub struct PersistentNanoSet {
+    tree: Tree,
+    /// The cached hash of the set, or `-1` if not computed yet.
+    hash: AtomicIsize,
+}
+
+impl PersistentNanoSet {
+    fn from_tree(tree: Tree) -> Self {
+        Self {
+            tree,
+            hash: AtomicIsize::new(-1),
+        }
+    }
+
+    /// Get `slf` if `tree` is its own tree, or a new set wrapping `tree`.
+    fn derive(slf: PyRef<Self>, tree: Tree) -> PyResult<PyObject> {
+        let py = slf.py();
+        if tree.same(&slf.tree) {
+            Ok(Py::from(slf).into())
+        } else {
+            Py::new(py, Self::from_tree(tree)).map(PyObject::from)
+        }
+    }
+
+    /// Check whether all the elements of the set are in `other`.
+    fn is_within(&self, other: &PyAny) -> PyResult<bool> {
+        let py = other.py();
+        for key in self.tree.keys() {
+            if !contains(other, key.as_ref(py))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Check whether all the elements of `other` are in the set.
+    fn covers(&self, other: &PyAny) -> PyResult<bool> {
+        for item in other.iter()? {
+            if !self.tree.contains(item?)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[pymethods]
+impl PersistentNanoSet {
+    #[new]
+    fn __new__(iterable: Option<&PyAny>) -> PyResult<Self> {
+        match iterable {
+            None => Ok(Self::from_tree(Tree::default())),
+            Some(it) => tree_of(it).map(Self::from_tree),
+        }
+    }
+
+    #[classmethod]
+    #[text_signature = "($cls, item)"]
+    fn __class_getitem__(cls: &PyType, item: &PyAny) -> PyResult<PyObject> {
+        util::class_getitem(cls, item)
+    }
+
+    #[text_signature = "($self)"]
+    fn __reduce__(&self) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let ty = Self::type_object(py);
+        if self.tree.is_empty() {
+            return Ok((ty, PyTuple::empty(py)).to_object(py));
+        }
+        let items = PyTuple::new(py, self.tree.keys().collect::<Vec<_>>());
+        Ok((ty, (items,)).to_object(py))
+    }
+
+    #[text_signature = "($self)"]
+    fn __copy__(slf: PyRef<Self>) -> Py<Self> {
+        slf.into()
+    }
+
+    #[text_signature = "($self)"]
+    fn copy(slf: PyRef<Self>) -> Py<Self> {
+        slf.into()
+    }
+
+    #[text_signature = "($self, item)"]
+    fn add(slf: PyRef<Self>, item: &PyAny) -> PyResult<PyObject> {
+        let mut tree = slf.tree.clone();
+        tree.add(item)?;
+        Self::derive(slf, tree)
+    }
+
+    #[text_signature = "($self, item)"]
+    fn discard(slf: PyRef<Self>, item: &PyAny) -> PyResult<PyObject> {
+        let mut tree = slf.tree.clone();
+        tree.discard(item)?;
+        Self::derive(slf, tree)
+    }
+
+    #[text_signature = "($self, item)"]
+    fn remove(slf: PyRef<Self>, item: &PyAny) -> PyResult<PyObject> {
+        let mut tree = slf.tree.clone();
+        if !tree.discard(item)? {
+            return KeyError::into(item.to_object(item.py()));
+        }
+        Self::derive(slf, tree)
+    }
+
+    #[text_signature = "($self)"]
+    fn evolver(&self) -> PersistentNanoSetEvolver {
+        PersistentNanoSetEvolver {
+            tree: self.tree.clone(),
+            original: self.tree.clone(),
+        }
+    }
+
+    #[args(others = "*")]
+    #[text_signature = "($self, *others)"]
+    fn difference(&self, others: &PyTuple) -> PyResult<Self> {
+        let mut tree = self.tree.clone();
+        for other in others.iter() {
+            if tree.is_empty() {
+                break;
+            }
+            for item in other.iter()? {
+                tree.discard(item?)?;
+            }
+        }
+        Ok(Self::from_tree(tree))
+    }
+
+    #[args(others = "*")]
+    #[text_signature = "($self, *others)"]
+    fn intersection(&self, others: &PyTuple) -> PyResult<Self> {
+        // check if we got an argument, otherwise just share the current tree
+        if others.is_empty() {
+            return Ok(Self::from_tree(self.tree.clone()));
+        }
+
+        // convert the arguments to sets, so that membership tests are cheap
+        let py = others.py();
+        let others = others.iter().map(as_set).collect::<PyResult<Vec<_>>>()?;
+        let mut tree = Tree::default();
+        'keys: for key in self.tree.keys() {
+            let key = key.as_ref(py);
+            for other in others.iter() {
+                if !contains(other, key)? {
+                    continue 'keys;
+                }
+            }
+            tree.add(key)?;
+        }
+        Ok(Self::from_tree(tree))
+    }
+
+    #[text_signature = "($self, other)"]
+    fn isdisjoint(&self, other: &PyAny) -> PyResult<bool> {
+        for item in other.iter()? {
+            if self.tree.contains(item?)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    #[text_signature = "($self, other)"]
+    fn issubset(&self, other: &PyAny) -> PyResult<bool> {
+        let other = as_set(other)?;
+        Ok(self.tree.len() <= other.len()? && self.is_within(other)?)
+    }
+
+    #[text_signature = "($self, other)"]
+    fn issuperset(&self, other: &PyAny) -> PyResult<bool> {
+        self.covers(other)
+    }
+
+    #[text_signature = "($self, other)"]
+    fn symmetric_difference(&self, other: &PyAny) -> PyResult<Self> {
+        // deduplicate the elements of `other` first, so that an element
+        // given twice is not toggled twice
+        let py = other.py();
+        let mut tree = self.tree.clone();
+        for key in tree_of(other)?.keys() {
+            let key = key.as_ref(py);
+            if !tree.discard(key)? {
+                tree.add(key)?;
+            }
+        }
+        Ok(Self::from_tree(tree))
+    }
+
+    #[args(others = "*")]
+    #[text_signature = "($self, *others)"]
+    fn union(&self, others: &PyTuple) -> PyResult<Self> {
+        let mut tree = self.tree.clone();
+        for other in others.iter() {
+            match other.extract::<PyRef<Self>>() {
+                Ok(ref set) if tree.is_empty() => tree = set.tree.clone(),
+                _ => {
+                    for item in other.iter()? {
+                        tree.add(item?)?;
+                    }
+                }
+            }
+        }
+        Ok(Self::from_tree(tree))
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for PersistentNanoSet {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<PersistentNanoSetIterator> {
+        Ok(PersistentNanoSetIterator {
+            keys: slf.tree.keys(),
+        })
+    }
+}
+
+#[pyproto]
+impl PyNumberProtocol for PersistentNanoSet {
+    fn __and__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, _) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        let args: Py<PyTuple> = (other,).into_py(py);
+        this.intersection(args.as_ref(py))
+            .and_then(|s| Py::new(py, s))
+            .map(PyObject::from)
+    }
+
+    fn __sub__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, reflected) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        let result = if reflected {
+            // `other` is on the left of the operator, and `rhs` is us
+            let mut tree = tree_of(other)?;
+            for key in this.tree.keys() {
+                if tree.is_empty() {
+                    break;
+                }
+                tree.discard(key.as_ref(py))?;
+            }
+            Self::from_tree(tree)
+        } else {
+            let args: Py<PyTuple> = (other,).into_py(py);
+            this.difference(args.as_ref(py))?
+        };
+        Py::new(py, result).map(PyObject::from)
+    }
+
+    fn __or__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, _) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        let args: Py<PyTuple> = (other,).into_py(py);
+        this.union(args.as_ref(py))
+            .and_then(|s| Py::new(py, s))
+            .map(PyObject::from)
+    }
+
+    fn __xor__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, _) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        this.symmetric_difference(other)
+            .and_then(|s| Py::new(py, s))
+            .map(PyObject::from)
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for PersistentNanoSet {
+    fn __repr__(&self) -> PyResult<String> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        if self.tree.is_empty() {
+            return Ok("PersistentNanoSet()".to_string());
+        }
+
+        // the set cannot be used with `Py_ReprEnter` since we only have a
+        // reference to its contents, so recursion is detected by address
+        let id = self as *const Self as usize;
+        if REPR_STACK.with(|stack| stack.borrow().contains(&id)) {
+            return Ok("PersistentNanoSet(...)".to_string());
+        }
+        REPR_STACK.with(|stack| stack.borrow_mut().push(id));
+        let result = self
+            .tree
+            .keys()
+            .map(|key| key.as_ref(py).repr().map(|r| r.to_string_lossy().into_owned()))
+            .collect::<PyResult<Vec<_>>>();
+        REPR_STACK.with(|stack| stack.borrow_mut().retain(|&x| x != id));
+        Ok(format!("PersistentNanoSet({{{}}})", result?.join(", ")))
+    }
+
+    fn __hash__(&self) -> PyResult<isize> {
+        let cached = self.hash.load(Ordering::Relaxed);
+        if cached != -1 {
+            return Ok(cached);
+        }
+
+        // hash like a `frozenset` with the same elements, since both
+        // compare equal
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let items = PyTuple::new(py, self.tree.keys().collect::<Vec<_>>());
+        let frozen: &PyAny =
+            unsafe { py.from_owned_ptr_or_err(pyo3::ffi::PyFrozenSet_New(items.as_ptr()))? };
+        let hash = frozen.hash()?;
+        self.hash.store(hash, Ordering::Relaxed);
+        Ok(hash)
+    }
+
+    fn __bool__(&self) -> bool {
+        !self.tree.is_empty()
+    }
+
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp) -> PyResult<PyObject> {
+        use self::CompareOp::*;
+
+        let py = other.py();
+        if let Ok(set) = other.extract::<PyRef<Self>>() {
+            if self.tree.same(&set.tree) {
+                return Ok(matches!(op, Eq | Le | Ge).to_object(py));
+            }
+        }
+        if !is_set(other)? {
+            return Ok(py.NotImplemented());
+        }
+
+        let (len, other_len) = (self.tree.len(), other.len()?);
+        let result = match op {
+            Eq => len == other_len && self.is_within(other)?,
+            Ne => len != other_len || !self.is_within(other)?,
+            Le => len <= other_len && self.is_within(other)?,
+            Lt => len < other_len && self.is_within(other)?,
+            Ge => len >= other_len && self.covers(other)?,
+            Gt => len > other_len && self.covers(other)?,
+        };
+        Ok(result.to_object(py))
+    }
+}
+
+#[pyproto]
+impl PySequenceProtocol for PersistentNanoSet {
+    fn __len__(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn __contains__(&self, item: &PyAny) -> PyResult<bool> {
+        self.tree.contains(item)
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+#[pyclass(module = "nanoset")]
+#[derive(Debug)]
+/// A mutable copy of a persistent set, to apply several changes in batch.
+///
+/// Nodes shared with the original set are copied on first modification,
+/// and modified in place afterwards.
+pub struct PersistentNanoSetEvolver {
+    tree: Tree,
+    /// The tree of the last persistent set created from this evolver.
+    original: Tree,
+}
+
+#[pymethods]
+impl PersistentNanoSetEvolver {
+    #[text_signature = "($self, item)"]
+    fn add(&mut self, item: &PyAny) -> PyResult<()> {
+        self.tree.add(item).map(drop)
+    }
+
+    #[text_signature = "($self, item)"]
+    fn discard(&mut self, item: &PyAny) -> PyResult<()> {
+        self.tree.discard(item).map(drop)
+    }
+
+    #[text_signature = "($self, item)"]
+    fn remove(&mut self, item: &PyAny) -> PyResult<()> {
+        if !self.tree.discard(item)? {
+            return KeyError::into(item.to_object(item.py()));
+        }
+        Ok(())
+    }
+
+    #[args(others = "*")]
+    #[text_signature = "($self, *others)"]
+    fn update(&mut self, others: &PyTuple) -> PyResult<()> {
+        for other in others.iter() {
+            for item in other.iter()? {
+                self.tree.add(item?)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[text_signature = "($self)"]
+    fn is_dirty(&self) -> bool {
+        !self.tree.same(&self.original)
+    }
+
+    #[text_signature = "($self)"]
+    fn persistent(&mut self) -> PersistentNanoSet {
+        self.original = self.tree.clone();
+        PersistentNanoSet::from_tree(self.tree.clone())
+    }
+}
+
+#[pyproto]
+impl PySequenceProtocol for PersistentNanoSetEvolver {
+    fn __len__(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn __contains__(&self, item: &PyAny) -> PyResult<bool> {
+        self.tree.contains(item)
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+#[pyclass(module = "nanoset")]
+#[derive(Debug)]
+/// An iterator over the elements of a `PersistentNanoSet`.
+pub struct PersistentNanoSetIterator {
+    keys: Cursor,
+}
+
+#[pyproto]
+impl PyIterProtocol for PersistentNanoSetIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<Py<Self>> {
+        Ok(slf.into())
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> Option<PyObject> {
+        slf.keys.next()
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Add the persistent set types to the `nanoset` module.
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PersistentNanoSet>()?;
+    m.add_class::<PersistentNanoSetEvolver>()?;
+    m.add_class::<PersistentNanoSetIterator>()?;
+    let set = py.import("collections.abc")?.get("Set")?;
+    set.call_method1("register", (PersistentNanoSet::type_object(py),))?;
+    Ok(())
+}
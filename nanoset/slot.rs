@@ -0,0 +1,323 @@
+//! Storage slot for the set wrapped by a nanoset.
+//!
+//! All accesses to the wrapped set go through a [`Slot`], whose critical
+//! sections only perform a handful of pointer operations: no Python code is
+//! ever executed, and no object is ever allocated or released, inside them.
+//! Sets are only stored in the slot once, and released once they are empty
+//! and no other thread is using them, so that the wrappers stay consistent
+//! when several threads mutate the same instance because of the GIL being
+//! released in a `__hash__` / `__eq__` implementation.
+//!
+//! A set can also be shared between several slots by copy-on-write copies.
+//! A shared set is never modified: methods modifying the set must get it
+//! with [`Slot::get_unique`], which replaces it with a private copy first.
+
+use std::cell::UnsafeCell;
+
+use pyo3::gc::PyTraverseError;
+use pyo3::gc::PyVisit;
+use pyo3::prelude::*;
+use pyo3::types::PySet;
+use pyo3::AsPyPointer;
+
+#[cfg(feature = "registry")]
+use super::debug;
+use super::new_set;
+use super::stats;
+
+/// The content of a slot.
+#[derive(Debug, Default)]
+struct State {
+    /// The wrapped set, if any.
+    set: Option<Py<PySet>>,
+    /// Whether the set may be shared with another slot.
+    shared: bool,
+}
+
+/// A storage slot for an optional Python set.
+///
+/// The sets stored in a slot are created with [`new_set`], and never by a
+/// method of an object given by the user, so they are always exact `set`
+/// instances, whose methods cannot be overridden.
+pub struct Slot {
+    cell: UnsafeCell<State>,
+    #[cfg(feature = "registry")]
+    hidden: bool,
+}
+
+// SAFETY: the slot is only ever accessed with the GIL held, and the critical
+// sections never release it, so plain interior mutability is enough. This
+// does not hold on free-threaded interpreters, which is why `init` refuses
+// to load the module when the GIL is disabled.
+unsafe impl Sync for Slot {}
+
+impl Slot {
+    /// Create a new slot containing the given object.
+    pub fn new(value: Option<Py<PySet>>) -> Self {
+        Self::build(value, false)
+    }
+
+    /// Create a new slot for a set hidden from the garbage collector.
+    ///
+    /// The sets stored in such a slot are tracked by the debug registry
+    /// when the `registry` feature is enabled.
+    pub fn hidden(value: Option<Py<PySet>>) -> Self {
+        Self::build(value, true)
+    }
+
+    fn build(value: Option<Py<PySet>>, _hidden: bool) -> Self {
+        let ptr = value.as_ref().map(AsPyPointer::as_ptr);
+        let state = State {
+            set: value,
+            shared: false,
+        };
+        let slot = Self {
+            cell: UnsafeCell::new(state),
+            #[cfg(feature = "registry")]
+            hidden: _hidden,
+        };
+        if let Some(ptr) = ptr {
+            slot.stored(ptr);
+        }
+        slot
+    }
+
+    /// Mark the set of a new slot as shared with another slot.
+    pub fn into_shared(mut self) -> Self {
+        self.cell.get_mut().shared = true;
+        self
+    }
+
+    /// Record a set being stored in the slot.
+    #[inline(always)]
+    fn stored(&self, _ptr: *mut pyo3::ffi::PyObject) {
+        stats::allocated();
+        #[cfg(feature = "registry")]
+        {
+            if self.hidden {
+                debug::track(_ptr);
+            }
+        }
+    }
+
+    /// Record a set left in the slot after it was modified.
+    #[inline(always)]
+    fn modified(&self, _ptr: *mut pyo3::ffi::PyObject) {
+        #[cfg(feature = "registry")]
+        {
+            // the set may have been resized, so update its traced size
+            if self.hidden {
+                debug::retrack(_ptr);
+            }
+        }
+    }
+
+    /// Record a set being removed from the slot.
+    #[inline(always)]
+    fn removed(&self, _ptr: *mut pyo3::ffi::PyObject) {
+        stats::released();
+        #[cfg(feature = "registry")]
+        {
+            if self.hidden {
+                debug::untrack(_ptr);
+            }
+        }
+    }
+
+    fn with<R, F: FnOnce(&mut State) -> R>(&self, f: F) -> R {
+        // SAFETY: the GIL is held by the caller and `f` never runs Python code.
+        f(unsafe { &mut *self.cell.get() })
+    }
+
+    /// Get a new reference to the set in the slot, even if it is empty.
+    pub fn get_raw(&self, py: Python) -> Option<Py<PySet>> {
+        self.with(|state| state.set.as_ref().map(|obj| obj.clone_ref(py)))
+    }
+
+    /// Get a pointer to the set in the slot, or a null pointer.
+    ///
+    /// The pointer is only meant to be compared to the pointer of a set
+    /// the caller holds a reference to, and must never be dereferenced.
+    pub fn as_ptr(&self) -> *mut pyo3::ffi::PyObject {
+        self.with(|state| match state.set {
+            Some(ref obj) => obj.as_ptr(),
+            None => std::ptr::null_mut(),
+        })
+    }
+
+    /// Take the object out of the slot, without locking it.
+    pub fn take(&mut self) -> Option<Py<PySet>> {
+        let state = self.cell.get_mut();
+        state.shared = false;
+        let value = state.set.take();
+        if let Some(ref obj) = value {
+            self.removed(obj.as_ptr());
+        }
+        value
+    }
+
+    /// Get a new reference to the set in the slot, if it is not empty.
+    ///
+    /// A set may only be found empty in the slot while another thread is
+    /// still using it, in which case the slot is considered empty as well.
+    /// The set may be shared with another slot, so it must not be modified.
+    pub fn get(&self, py: Python) -> Option<Py<PySet>> {
+        self.with(|state| match state.set {
+            Some(ref obj) if unsafe { pyo3::ffi::PySet_Size(obj.as_ptr()) } != 0 => {
+                Some(obj.clone_ref(py))
+            }
+            _ => None,
+        })
+    }
+
+    /// Get a new reference to the set in the slot, if it is not empty,
+    /// making sure it is not shared with another slot so it can be modified.
+    pub fn get_unique(&self, py: Python) -> PyResult<Option<Py<PySet>>> {
+        loop {
+            let shared = self.with(|state| match state.set {
+                Some(ref obj) if state.shared => Some(obj.clone_ref(py)),
+                _ => None,
+            });
+            let set = match shared {
+                Some(set) => set,
+                None => return Ok(self.get(py)),
+            };
+
+            // copy the set outside of the critical section, and only store
+            // the copy if the slot was not modified in the meantime
+            let copy = new_set(py, Some(set.as_ref(py)))?;
+            let copy_ptr = copy.as_ptr();
+            let mut copy = Some(copy);
+            let previous = self.with(|state| match state.set {
+                Some(ref mut current) if state.shared && current.as_ptr() == set.as_ptr() => {
+                    state.shared = false;
+                    // the copy is not needed if the set is only referenced
+                    // by this slot and by us anymore
+                    if unsafe { pyo3::ffi::Py_REFCNT(set.as_ptr()) } == 2 {
+                        None
+                    } else {
+                        copy.take().map(|copy| std::mem::replace(current, copy))
+                    }
+                }
+                _ => None,
+            });
+            if let Some(obj) = previous {
+                self.removed(obj.as_ptr());
+                self.stored(copy_ptr);
+            }
+        }
+    }
+
+    /// Get a new reference to the set in the slot, if it is not empty, and
+    /// mark it as shared so that it is never modified in this slot anymore.
+    ///
+    /// A set referenced outside of the slots, e.g. by a method modifying it
+    /// while calling the `__eq__` implementation requesting the copy, is
+    /// never shared: `None` is returned, and the caller must copy it instead.
+    pub fn share(&self, py: Python) -> Option<Py<PySet>> {
+        self.with(|state| match state.set {
+            Some(ref obj) if unsafe { pyo3::ffi::PySet_Size(obj.as_ptr()) } != 0 => {
+                let refcount = unsafe { pyo3::ffi::Py_REFCNT(obj.as_ptr()) };
+                if refcount == 1 || state.shared {
+                    state.shared = true;
+                    Some(obj.clone_ref(py))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+    }
+
+    /// Replace the object in the slot, returning the previous one.
+    ///
+    /// The returned object must be dropped by the caller, outside of any
+    /// critical section, since releasing it may run arbitrary Python code.
+    pub fn replace(&self, value: Option<Py<PySet>>) -> Option<Py<PySet>> {
+        let ptr = value.as_ref().map(AsPyPointer::as_ptr);
+        let previous = self.with(|state| {
+            state.shared = false;
+            std::mem::replace(&mut state.set, value)
+        });
+        if let Some(ref obj) = previous {
+            self.removed(obj.as_ptr());
+        }
+        if let Some(ptr) = ptr {
+            self.stored(ptr);
+        }
+        previous
+    }
+
+    /// Visit the object in the slot, if any, for the garbage collector.
+    pub fn traverse(&self, visit: &PyVisit) -> Result<(), PyTraverseError> {
+        self.with(|state| match state.set {
+            Some(ref obj) => visit.call(obj),
+            None => Ok(()),
+        })
+    }
+
+    /// Store `obj` in the slot if the slot is empty.
+    ///
+    /// Returns `None` if `obj` was stored, or a new reference to the object
+    /// already in the slot otherwise.
+    pub fn insert(&self, py: Python, obj: &Py<PySet>) -> Option<Py<PySet>> {
+        let current = self.with(|state| match state.set {
+            Some(ref current) => Some(current.clone_ref(py)),
+            None => {
+                state.set = Some(obj.clone_ref(py));
+                state.shared = false;
+                None
+            }
+        });
+        if current.is_none() {
+            self.stored(obj.as_ptr());
+            stats::promoted();
+        }
+        current
+    }
+
+    /// Empty the slot if it holds `obj`, and `obj` is an empty set only
+    /// referenced by the slot and the caller.
+    ///
+    /// Called after every modification of the set in the slot, so that the
+    /// debug registry can update the size traced for a set it keeps.
+    ///
+    /// A set still referenced elsewhere, e.g. by a method running in another
+    /// thread, is left in place, so that no mutation is ever performed on a
+    /// set after it was released. The released object is returned so that
+    /// it can be dropped by the caller outside of the critical section.
+    pub fn release_if_empty(&self, obj: &Py<PySet>) -> Option<Py<PySet>> {
+        let released = self.with(|state| match state.set {
+            Some(ref current) if current.as_ptr() == obj.as_ptr() => unsafe {
+                let ptr = obj.as_ptr();
+                if pyo3::ffi::Py_REFCNT(ptr) == 2 && pyo3::ffi::PySet_Size(ptr) == 0 {
+                    state.shared = false;
+                    state.set.take()
+                } else {
+                    None
+                }
+            },
+            _ => None,
+        });
+        match released {
+            Some(ref obj) => {
+                self.removed(obj.as_ptr());
+                stats::demoted();
+            }
+            None => self.modified(obj.as_ptr()),
+        }
+        released
+    }
+}
+
+impl Default for Slot {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl std::fmt::Debug for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.with(|state| f.debug_tuple("Slot").field(&state.set).finish())
+    }
+}
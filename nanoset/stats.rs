@@ -0,0 +1,161 @@
+//! Allocation statistics, only collected with the `stats` feature.
+//!
+//! All the recording functions are no-ops when the feature is disabled, so
+//! that the counters are compiled out of the default build entirely.
+
+#[cfg(feature = "stats")]
+use std::sync::atomic::AtomicUsize;
+#[cfg(feature = "stats")]
+use std::sync::atomic::Ordering;
+
+#[cfg(feature = "stats")]
+use pyo3::prelude::*;
+#[cfg(feature = "stats")]
+use pyo3::types::PyDict;
+
+/// Instance counters for a single class.
+#[cfg(feature = "stats")]
+struct ClassStats {
+    created: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+#[cfg(feature = "stats")]
+impl ClassStats {
+    const fn new() -> Self {
+        Self {
+            created: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    fn get(name: &str) -> Option<&'static Self> {
+        match name {
+            "NanoSet" => Some(&NANOSET),
+            "PicoSet" => Some(&PICOSET),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+static NANOSET: ClassStats = ClassStats::new();
+#[cfg(feature = "stats")]
+static PICOSET: ClassStats = ClassStats::new();
+
+#[cfg(feature = "stats")]
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "stats")]
+static RELEASED: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "stats")]
+static PROMOTIONS: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "stats")]
+static DEMOTIONS: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "stats")]
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "stats")]
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Record the creation of an instance of the class `name`.
+#[inline(always)]
+pub fn created(_name: &str) {
+    #[cfg(feature = "stats")]
+    {
+        if let Some(stats) = ClassStats::get(_name) {
+            stats.created.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Record the destruction of an instance of the class `name`.
+#[inline(always)]
+pub fn dropped(_name: &str) {
+    #[cfg(feature = "stats")]
+    {
+        if let Some(stats) = ClassStats::get(_name) {
+            stats.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Record a set being stored in the slot of an instance.
+#[inline(always)]
+pub fn allocated() {
+    #[cfg(feature = "stats")]
+    {
+        ALLOCATED.fetch_add(1, Ordering::Relaxed);
+        let live = LIVE.fetch_add(1, Ordering::Relaxed) + 1;
+        PEAK.fetch_max(live, Ordering::Relaxed);
+    }
+}
+
+/// Record a set being removed from the slot of an instance.
+#[inline(always)]
+pub fn released() {
+    #[cfg(feature = "stats")]
+    {
+        RELEASED.fetch_add(1, Ordering::Relaxed);
+        LIVE.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Record an empty instance allocating a set to store new elements.
+#[inline(always)]
+pub fn promoted() {
+    #[cfg(feature = "stats")]
+    PROMOTIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record an instance releasing its set after it was emptied.
+#[inline(always)]
+pub fn demoted() {
+    #[cfg(feature = "stats")]
+    DEMOTIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Get a dictionary with the current value of all counters.
+#[cfg(feature = "stats")]
+#[pyfunction]
+#[text_signature = "()"]
+pub fn stats(py: Python) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    for &(name, stats) in [("NanoSet", &NANOSET), ("PicoSet", &PICOSET)].iter() {
+        let counters = PyDict::new(py);
+        counters.set_item("created", stats.created.load(Ordering::Relaxed))?;
+        counters.set_item("dropped", stats.dropped.load(Ordering::Relaxed))?;
+        dict.set_item(name, counters)?;
+    }
+    dict.set_item("sets_allocated", ALLOCATED.load(Ordering::Relaxed))?;
+    dict.set_item("sets_released", RELEASED.load(Ordering::Relaxed))?;
+    dict.set_item("promotions", PROMOTIONS.load(Ordering::Relaxed))?;
+    dict.set_item("demotions", DEMOTIONS.load(Ordering::Relaxed))?;
+    dict.set_item("live_sets", LIVE.load(Ordering::Relaxed))?;
+    dict.set_item("peak_sets", PEAK.load(Ordering::Relaxed))?;
+    Ok(dict.to_object(py))
+}
+
+/// Reset all counters, except for the number of live sets.
+///
+/// The peak number of sets is reset to the number of sets currently alive.
+#[cfg(feature = "stats")]
+#[pyfunction]
+#[text_signature = "()"]
+pub fn reset_stats() {
+    for stats in [&NANOSET, &PICOSET].iter() {
+        stats.created.store(0, Ordering::Relaxed);
+        stats.dropped.store(0, Ordering::Relaxed);
+    }
+    ALLOCATED.store(0, Ordering::Relaxed);
+    RELEASED.store(0, Ordering::Relaxed);
+    PROMOTIONS.store(0, Ordering::Relaxed);
+    DEMOTIONS.store(0, Ordering::Relaxed);
+    PEAK.store(LIVE.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// Add the statistics functions to the `nanoset` module.
+#[cfg(feature = "stats")]
+pub fn init(m: &PyModule) -> PyResult<()> {
+    m.add_wrapped(pyo3::wrap_pyfunction!(stats))?;
+    m.add_wrapped(pyo3::wrap_pyfunction!(reset_stats))?;
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Helpers for the set protocol and the operators shared by the set types
+//! of the crate.
+
+use pyo3::exceptions::TypeError;
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use pyo3::types::PyFrozenSet;
+use pyo3::types::PySet;
+use pyo3::types::PyType;
+use pyo3::AsPyPointer;
+use pyo3::PyClass;
+use pyo3::PyNativeType;
+
+use super::persistent::PersistentNanoSet;
+
+/// Check whether `obj` is a set, i.e. an instance of `collections.abc.Set`.
+pub(crate) fn is_set(obj: &PyAny) -> PyResult<bool> {
+    if obj.cast_as::<PySet>().is_ok()
+        || obj.cast_as::<PyFrozenSet>().is_ok()
+        || obj.extract::<PyRef<PersistentNanoSet>>().is_ok()
+    {
+        return Ok(true);
+    }
+    let abc = obj.py().import("collections.abc")?.get("Set")?;
+    match unsafe { pyo3::ffi::PyObject_IsInstance(obj.as_ptr(), abc.as_ptr()) } {
+        -1 => Err(PyErr::fetch(obj.py())),
+        n => Ok(n == 1),
+    }
+}
+
+/// Get `obj` if it is a set, or a `frozenset` with its elements otherwise.
+pub(crate) fn as_set(obj: &PyAny) -> PyResult<&PyAny> {
+    let py = obj.py();
+    if is_set(obj)? {
+        Ok(obj)
+    } else {
+        unsafe { py.from_owned_ptr_or_err(pyo3::ffi::PyFrozenSet_New(obj.as_ptr())) }
+    }
+}
+
+/// Check whether `container` contains `key`.
+pub(crate) fn contains(container: &PyAny, key: &PyAny) -> PyResult<bool> {
+    match unsafe { pyo3::ffi::PySequence_Contains(container.as_ptr(), key.as_ptr()) } {
+        -1 => Err(PyErr::fetch(key.py())),
+        n => Ok(n == 1),
+    }
+}
+
+/// Check whether `obj` is the instance `this`, even while it is borrowed
+/// mutably.
+pub(crate) fn is_self<T: PyClass + 'static>(this: &T, obj: &PyAny) -> bool {
+    match obj.downcast::<PyCell<T>>() {
+        Err(_) => false,
+        Ok(cell) => {
+            // an instance is stored inside the memory of its cell
+            let start = cell.as_ptr() as usize;
+            let this = this as *const T as usize;
+            let end = start + std::mem::size_of::<PyCell<T>>();
+            (start..end).contains(&this)
+        }
+    }
+}
+
+/// Get the operands of a binary set operator of `T`, as the instance, the
+/// other operand, and whether the operator is reflected.
+///
+/// Returns `None` if neither operand is an instance of `T`, or if the other
+/// operand is not accepted by `is_operand`.
+pub(crate) fn operands<'p, T: PyClass + 'static>(
+    lhs: &'p PyAny,
+    rhs: &'p PyAny,
+    is_operand: fn(&PyAny) -> PyResult<bool>,
+) -> PyResult<Option<(PyRef<'p, T>, &'p PyAny, bool)>> {
+    let (cell, other, reflected) = match (lhs.downcast::<PyCell<T>>(), rhs.downcast::<PyCell<T>>()) {
+        (Ok(cell), _) => (cell, rhs, false),
+        (_, Ok(cell)) => (cell, lhs, true),
+        _ => return Ok(None),
+    };
+    if !is_operand(other)? {
+        return Ok(None);
+    }
+    Ok(Some((cell.try_borrow()?, other, reflected)))
+}
+
+/// Check the operand of an in-place operator `op` of `this`, returning
+/// `None` if it is `this` itself, which is already borrowed mutably.
+pub(crate) fn inplace_operand<'p, T: PyClass + 'static>(
+    this: &T,
+    other: &'p PyAny,
+    op: &str,
+    is_operand: fn(&PyAny) -> PyResult<bool>,
+) -> PyResult<Option<&'p PyAny>> {
+    if is_self(this, other) {
+        return Ok(None);
+    }
+    if is_operand(other)? {
+        return Ok(Some(other));
+    }
+    TypeError::into(format!(
+        "unsupported operand type(s) for {}: '{}' and '{}'",
+        op,
+        T::NAME,
+        other.get_type().name(),
+    ))
+}
+
+/// Get the generic alias `cls[item]`, for the `__class_getitem__` methods.
+pub(crate) fn class_getitem(cls: &PyType, item: &PyAny) -> PyResult<PyObject> {
+    let py = cls.py();
+    // `types.GenericAlias` was only added in Python 3.9, so fall back to the
+    // bare class with older interpreters
+    match py.import("types")?.getattr("GenericAlias") {
+        Ok(alias) => alias.call1((cls, item)).map(|a| a.to_object(py)),
+        Err(_) => Ok(cls.to_object(py)),
+    }
+}
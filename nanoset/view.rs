@@ -0,0 +1,422 @@
+//! Lazy set views over the keys of a mapping.
+//!
+//! A view looks up and iterates over the keys of a mapping without copying
+//! them into a set. It is only a wrapper: when it is first modified, the keys
+//! are copied to a `NanoSet` which the view delegates to from then on, but
+//! the view itself stays a `NanoSetView`. The results of the set operators
+//! are always new nanosets.
+//!
+//! The target of a view is never borrowed while calling Python code, so that
+//! the `__hash__` and `__eq__` methods of the keys can use the view while a
+//! method is running. In-place operators are the exception, since `pyo3`
+//! borrows the view mutably while they run: like with `NanoSet`, the methods
+//! called in the meantime raise a `RuntimeError`.
+
+use std::cell::RefCell;
+
+use pyo3::class::basic::CompareOp;
+use pyo3::class::PyGCProtocol;
+use pyo3::class::PyIterProtocol;
+use pyo3::class::PyNumberProtocol;
+use pyo3::class::PyObjectProtocol;
+use pyo3::class::PySequenceProtocol;
+use pyo3::exceptions::TypeError;
+use pyo3::gc::PyTraverseError;
+use pyo3::gc::PyVisit;
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use pyo3::types::PyDict;
+use pyo3::types::PyFrozenSet;
+use pyo3::type_object::PyTypeObject;
+use pyo3::AsPyPointer;
+use pyo3::PyNativeType;
+
+use super::new_set;
+use super::util;
+use super::util::contains;
+use super::util::is_set;
+use super::NanoSet;
+
+/// The set a view delegates to.
+enum Target {
+    /// The keys of a mapping, until the view is first modified.
+    Keys { mapping: PyObject, keys: PyObject },
+    /// The set wrapped by the view since it was first modified.
+    Owned(Py<NanoSet>),
+    /// Nothing, once the view was cleared by the garbage collector.
+    Cleared,
+}
+
+#[pyclass(gc, module = "nanoset")]
+/// A set view over the keys of a mapping, returned by `NanoSet.view`.
+pub struct NanoSetView {
+    /// Only borrowed mutably to replace it, without running Python code.
+    target: RefCell<Target>,
+}
+
+/// Check whether `obj` is a mapping, i.e. an instance of
+/// `collections.abc.Mapping`.
+fn is_mapping(obj: &PyAny) -> PyResult<bool> {
+    if obj.cast_as::<PyDict>().is_ok() {
+        return Ok(true);
+    }
+    let abc = obj.py().import("collections.abc")?.get("Mapping")?;
+    match unsafe { pyo3::ffi::PyObject_IsInstance(obj.as_ptr(), abc.as_ptr()) } {
+        -1 => Err(PyErr::fetch(obj.py())),
+        n => Ok(n == 1),
+    }
+}
+
+/// Build a new `NanoSet` with the items of `items` accepted by `keep`.
+fn collect<'p>(
+    py: Python<'p>,
+    items: &'p PyAny,
+    keep: &mut dyn FnMut(&'p PyAny) -> PyResult<bool>,
+) -> PyResult<NanoSet> {
+    let set = new_set(py, None)?;
+    for item in items.iter()? {
+        let item = item?;
+        if keep(item)? {
+            set.as_ref(py).add(item)?;
+        }
+    }
+    if set.as_ref(py).is_empty() {
+        Ok(NanoSet::new())
+    } else {
+        Ok(NanoSet::from_set(set))
+    }
+}
+
+impl NanoSetView {
+    /// Create a view over the keys of `mapping`, for `NanoSet.view`.
+    pub(crate) fn from_mapping(mapping: &PyAny) -> PyResult<Self> {
+        if !is_mapping(mapping)? {
+            return TypeError::into(format!(
+                "view() argument must be a mapping, not '{}'",
+                mapping.get_type().name(),
+            ));
+        }
+        let py = mapping.py();
+        Ok(NanoSetView {
+            target: RefCell::new(Target::Keys {
+                mapping: mapping.to_object(py),
+                keys: mapping.call_method0("keys")?.to_object(py),
+            }),
+        })
+    }
+
+    /// Get a new reference to the object the view delegates to, which stays
+    /// valid if the target is replaced while it is used.
+    fn target<'p>(&self, py: Python<'p>) -> PyResult<&'p PyAny> {
+        let ptr = match *self.target.borrow() {
+            Target::Keys { ref keys, .. } => keys.as_ptr(),
+            Target::Owned(ref set) => set.as_ptr(),
+            Target::Cleared => return Ok(PyFrozenSet::empty(py)?),
+        };
+        Ok(unsafe { py.from_borrowed_ptr(ptr) })
+    }
+
+    /// Get the set wrapped by the view, copying the keys of the mapping to a
+    /// new `NanoSet` the first time.
+    fn owned(&self, py: Python) -> PyResult<Py<NanoSet>> {
+        let keys = match *self.target.borrow() {
+            Target::Owned(ref set) => return Ok(set.clone_ref(py)),
+            Target::Keys { ref keys, .. } => Some(keys.clone_ref(py)),
+            Target::Cleared => None,
+        };
+        let set = match keys {
+            Some(keys) => NanoSet::try_from_any(py, keys.as_ref(py))?,
+            None => NanoSet::new(),
+        };
+        Ok(self.wrap(py, Py::new(py, set)?))
+    }
+
+    /// Make the view delegate to `set`, unless the keys of the view hashed
+    /// while creating it already made the view wrap another set, which is
+    /// returned instead so that their changes are kept.
+    fn wrap(&self, py: Python, set: Py<NanoSet>) -> Py<NanoSet> {
+        // release the previous target or the unused set once the target is
+        // not borrowed anymore, since it may run arbitrary code
+        let (current, _released) = {
+            let mut target = self.target.borrow_mut();
+            match *target {
+                Target::Owned(ref current) => (current.clone_ref(py), Target::Owned(set)),
+                _ => (set.clone_ref(py), std::mem::replace(&mut *target, Target::Owned(set))),
+            }
+        };
+        current
+    }
+
+    /// Call the method `name` of the wrapped set with `args`.
+    fn mutate(&self, name: &str, args: impl IntoPy<Py<pyo3::types::PyTuple>>) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let set = self.owned(py)?;
+        set.as_ref(py).call_method1(name, args).map(|r| r.to_object(py))
+    }
+
+    /// Check whether all the items of `items` are in `container`.
+    fn all_in(items: &PyAny, container: &PyAny) -> PyResult<bool> {
+        for item in items.iter()? {
+            if !contains(container, item?)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[pymethods]
+impl NanoSetView {
+    /// The mapping the view delegates to, or `None` once it was modified.
+    #[getter]
+    fn mapping(&self) -> Option<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        match *self.target.borrow() {
+            Target::Keys { ref mapping, .. } => Some(mapping.clone_ref(py)),
+            _ => None,
+        }
+    }
+
+    #[text_signature = "($self)"]
+    fn copy(&self) -> PyResult<NanoSet> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        NanoSet::try_from_any(py, self.target(py)?)
+    }
+
+    #[text_signature = "($self, other)"]
+    fn isdisjoint(&self, other: &PyAny) -> PyResult<bool> {
+        let py = other.py();
+        let target = self.target(py)?;
+        for item in other.iter()? {
+            if contains(target, item?)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    #[text_signature = "($self, item)"]
+    fn add(&self, item: &PyAny) -> PyResult<()> {
+        self.mutate("add", (item,)).map(|_| ())
+    }
+
+    #[text_signature = "($self)"]
+    fn clear(&self) -> PyResult<()> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let owned = match *self.target.borrow() {
+            Target::Owned(ref set) => Some(set.clone_ref(py)),
+            _ => None,
+        };
+        match owned {
+            // clear the wrapped set in place, since it may be being modified
+            Some(set) => set.as_ref(py).call_method0("clear").map(drop),
+            // there is no need to copy the keys only to remove them
+            None => Py::new(py, NanoSet::new()).map(|set| drop(self.wrap(py, set))),
+        }
+    }
+
+    #[text_signature = "($self, item)"]
+    fn discard(&self, item: &PyAny) -> PyResult<()> {
+        self.mutate("discard", (item,)).map(|_| ())
+    }
+
+    #[text_signature = "($self)"]
+    fn pop(&self) -> PyResult<PyObject> {
+        self.mutate("pop", ())
+    }
+
+    #[text_signature = "($self, item)"]
+    fn remove(&self, item: &PyAny) -> PyResult<()> {
+        self.mutate("remove", (item,)).map(|_| ())
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for NanoSetView {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<PyObject> {
+        let py = slf.py();
+        let target = slf.target(py)?;
+        unsafe { PyObject::from_owned_ptr_or_err(py, pyo3::ffi::PyObject_GetIter(target.as_ptr())) }
+    }
+}
+
+#[pyproto]
+impl PyNumberProtocol for NanoSetView {
+    fn __and__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, _) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        let target = this.target(py)?;
+        let result = collect(py, other, &mut |item| contains(target, item))?;
+        Py::new(py, result).map(PyObject::from)
+    }
+
+    fn __sub__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, reflected) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        let target = this.target(py)?;
+        let result = if reflected {
+            // `other` is on the left of the operator, and `rhs` is us
+            collect(py, other, &mut |item| contains(target, item).map(|c| !c))?
+        } else {
+            collect(py, target, &mut |item| contains(other, item).map(|c| !c))?
+        };
+        Py::new(py, result).map(PyObject::from)
+    }
+
+    fn __or__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, _) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        let result = NanoSet::try_from_any(py, this.target(py)?)?;
+        let result = Py::new(py, result)?;
+        result.as_ref(py).call_method1("update", (other,))?;
+        Ok(result.into())
+    }
+
+    fn __xor__(lhs: &PyAny, rhs: &PyAny) -> PyResult<PyObject> {
+        let py = rhs.py();
+        let (this, other, _) = match util::operands::<Self>(lhs, rhs, is_set)? {
+            None => return Ok(py.NotImplemented()),
+            Some(operands) => operands,
+        };
+        let target = this.target(py)?;
+        let result = collect(py, target, &mut |item| contains(other, item).map(|c| !c))?;
+        let result = Py::new(py, result)?;
+        let added = collect(py, other, &mut |item| contains(target, item).map(|c| !c))?;
+        result.as_ref(py).call_method1("update", (Py::new(py, added)?,))?;
+        Ok(result.into())
+    }
+
+    fn __iand__(&mut self, other: &PyAny) -> PyResult<()> {
+        match util::inplace_operand(self, other, "&=", is_set)? {
+            None => Ok(()),
+            Some(other) => self.mutate("intersection_update", (other,)).map(|_| ()),
+        }
+    }
+
+    fn __isub__(&mut self, other: &PyAny) -> PyResult<()> {
+        match util::inplace_operand(self, other, "-=", is_set)? {
+            None => self.clear(),
+            Some(other) => self.mutate("difference_update", (other,)).map(|_| ()),
+        }
+    }
+
+    fn __ior__(&mut self, other: &PyAny) -> PyResult<()> {
+        match util::inplace_operand(self, other, "|=", is_set)? {
+            None => Ok(()),
+            Some(other) => self.mutate("update", (other,)).map(|_| ()),
+        }
+    }
+
+    fn __ixor__(&mut self, other: &PyAny) -> PyResult<()> {
+        match util::inplace_operand(self, other, "^=", is_set)? {
+            None => self.clear(),
+            Some(other) => self.mutate("symmetric_difference_update", (other,)).map(|_| ()),
+        }
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for NanoSetView {
+    fn __repr__(&self) -> PyResult<String> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let inner = match *self.target.borrow() {
+            Target::Keys { ref mapping, .. } => Some(mapping.clone_ref(py)),
+            Target::Owned(ref set) => Some(set.to_object(py)),
+            Target::Cleared => None,
+        };
+        let inner = match inner {
+            Some(obj) => obj.as_ref(py).repr()?.to_string_lossy().into_owned(),
+            None => String::new(),
+        };
+        Ok(format!("NanoSetView({})", inner))
+    }
+
+    fn __bool__(&self) -> PyResult<bool> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        Ok(self.target(py)?.len()? > 0)
+    }
+
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp) -> PyResult<PyObject> {
+        use self::CompareOp::*;
+
+        let py = other.py();
+        if !is_set(other)? {
+            return Ok(py.NotImplemented());
+        }
+
+        let target = self.target(py)?;
+        let (len, other_len) = (target.len()?, other.len()?);
+        let result = match op {
+            Eq => len == other_len && Self::all_in(target, other)?,
+            Ne => len != other_len || !Self::all_in(target, other)?,
+            Le => len <= other_len && Self::all_in(target, other)?,
+            Lt => len < other_len && Self::all_in(target, other)?,
+            Ge => len >= other_len && Self::all_in(other, target)?,
+            Gt => len > other_len && Self::all_in(other, target)?,
+        };
+        Ok(result.to_object(py))
+    }
+}
+
+#[pyproto]
+impl PySequenceProtocol for NanoSetView {
+    fn __len__(&self) -> PyResult<usize> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        self.target(py)?.len()
+    }
+
+    fn __contains__(&self, item: &PyAny) -> PyResult<bool> {
+        contains(self.target(item.py())?, item)
+    }
+}
+
+#[pyproto]
+impl PyGCProtocol for NanoSetView {
+    fn __traverse__(&'p self, visit: PyVisit) -> Result<(), PyTraverseError> {
+        match *self.target.borrow() {
+            Target::Keys { ref mapping, ref keys } => {
+                visit.call(mapping)?;
+                visit.call(keys)
+            }
+            Target::Owned(ref set) => visit.call(set),
+            Target::Cleared => Ok(()),
+        }
+    }
+
+    fn __clear__(&'p mut self) {
+        let target = std::mem::replace(self.target.get_mut(), Target::Cleared);
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        match target {
+            Target::Keys { mapping, keys } => {
+                py.release(mapping);
+                py.release(keys);
+            }
+            Target::Owned(set) => py.release(set),
+            Target::Cleared => (),
+        }
+    }
+}
+
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<NanoSetView>()?;
+    let set = py.import("collections.abc")?.get("Set")?;
+    set.call_method1("register", (NanoSetView::type_object(py),))?;
+    Ok(())
+}
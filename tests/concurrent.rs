@@ -0,0 +1,96 @@
+extern crate nanoset_py;
+extern crate pyo3;
+
+use std::sync::Arc;
+
+use nanoset_py::ConcurrentNanoSet;
+use nanoset_py::Key;
+use nanoset_py::KeySet;
+use pyo3::prelude::*;
+use pyo3::types::IntoPyDict;
+
+/// Evaluate `code` with `s` bound to `set`, and extract the result.
+fn eval<'p, T: FromPyObject<'p>>(py: Python<'p>, set: &Py<ConcurrentNanoSet>, code: &str) -> T {
+    let globals = [("s", set.clone_ref(py))].into_py_dict(py);
+    py.eval(code, Some(globals), None).unwrap().extract().unwrap()
+}
+
+#[test]
+fn extract_keys() {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    let key: Key = py.eval("(1, 'a', b'b', True)", None, None).unwrap().extract().unwrap();
+    let expected = vec![Key::Int(1), Key::Str("a".into()), Key::Bytes(b"b".to_vec()), Key::Int(1)];
+    assert_eq!(key, Key::Tuple(expected));
+    assert!(py.eval("1.5", None, None).unwrap().extract::<Key>().is_err());
+    assert!(py.eval("2 ** 64", None, None).unwrap().extract::<Key>().is_err());
+    assert!(py.eval("(frozenset(),)", None, None).unwrap().extract::<Key>().is_err());
+}
+
+#[test]
+fn insert_without_gil() {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    let set = Py::new(py, ConcurrentNanoSet::default()).unwrap();
+    let keys = set.as_ref(py).borrow().key_set();
+
+    py.allow_threads(|| {
+        std::thread::scope(|scope| {
+            for t in 0..4 {
+                let keys = &keys;
+                scope.spawn(move || {
+                    for i in 0..1000 {
+                        keys.insert(Key::Int(i * 4 + t));
+                    }
+                });
+            }
+        })
+    });
+
+    assert_eq!(keys.len(), 4000);
+    assert!(eval::<bool>(py, &set, "len(s) == 4000 and 3999 in s and s == set(range(4000))"));
+}
+
+#[test]
+fn python_and_rust_threads() {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    let keys = Arc::new(KeySet::new());
+    let set = Py::new(py, ConcurrentNanoSet::from_key_set(keys.clone())).unwrap();
+
+    py.allow_threads(|| {
+        std::thread::scope(|scope| {
+            // a thread modifying the set from Python, with the GIL held
+            scope.spawn(|| {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                eval::<PyObject>(py, &set, "[s.add(str(i)) for i in range(1000)]");
+                eval::<PyObject>(py, &set, "s.update(range(1000, 2000))");
+            });
+            // a thread modifying the same set from Rust, without the GIL
+            scope.spawn(|| {
+                for i in 0..1000 {
+                    keys.insert(Key::Int(i));
+                    if i % 2 == 0 {
+                        keys.remove(&Key::Int(i));
+                    }
+                }
+            });
+        })
+    });
+
+    assert_eq!(keys.len(), 2500);
+    assert!(eval::<bool>(py, &set, "s == {*map(str, range(1000)), *range(1, 1000, 2), *range(1000, 2000)}"));
+}
+
+#[test]
+fn rust_keys_in_python() {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    let keys = Arc::new(KeySet::new());
+    let set = Py::new(py, ConcurrentNanoSet::from_key_set(keys.clone())).unwrap();
+    keys.insert(Key::Tuple(vec![Key::Str("a".into()), Key::Bytes(b"b".to_vec())]));
+    assert!(eval::<bool>(py, &set, "('a', b'b') in s and ('a', 'b') not in s"));
+    assert!(eval::<bool>(py, &set, "s.pop() == ('a', b'b') and not s"));
+    assert!(keys.is_empty());
+}
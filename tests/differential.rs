@@ -0,0 +1,338 @@
+extern crate nanoset_py;
+extern crate proptest;
+extern crate pyo3;
+
+use nanoset_py::NanoSet;
+use nanoset_py::PicoSet;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::select;
+use proptest::test_runner::TestCaseError;
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use pyo3::types::PyDict;
+use pyo3::types::PyFrozenSet;
+use pyo3::types::PyList;
+use pyo3::types::PyModule;
+use pyo3::types::PySet;
+use pyo3::types::PyTuple;
+use pyo3::AsPyPointer;
+
+/// An element given to the sets, which may not be hashable.
+#[derive(Clone, Debug)]
+enum Elem {
+    Int(i64),
+    Str(&'static str),
+    Unhashable,
+}
+
+/// The type of an operand passed to a set method or operator.
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+    Set,
+    FrozenSet,
+    List,
+    NanoSet,
+    PicoSet,
+}
+
+/// An operand passed to a set method or operator.
+#[derive(Clone, Debug)]
+struct Operand {
+    kind: Kind,
+    items: Vec<Elem>,
+}
+
+/// The nanoset removing an element in a `pop` step.
+#[derive(Clone, Copy, Debug)]
+enum Target {
+    NanoSet,
+    PicoSet,
+}
+
+/// A step applied to all the sets in lockstep.
+#[derive(Clone, Debug)]
+enum Op {
+    Add(Elem),
+    Discard(Elem),
+    Remove(Elem),
+    Contains(Elem),
+    Pop(Target),
+    Clear,
+    Copy,
+    Pickle,
+    Method(&'static str, Vec<Operand>),
+    Operator(&'static str, Operand, bool),
+}
+
+fn hashable() -> impl Strategy<Value = Elem> {
+    prop_oneof![
+        (0..8i64).prop_map(Elem::Int),
+        select(vec!["a", "b", "c", "d"]).prop_map(Elem::Str),
+    ]
+}
+
+fn elem() -> impl Strategy<Value = Elem> {
+    prop_oneof![
+        8 => hashable(),
+        1 => Just(Elem::Unhashable),
+    ]
+}
+
+fn operand() -> impl Strategy<Value = Operand> {
+    let set = (
+        select(vec![Kind::Set, Kind::FrozenSet, Kind::NanoSet, Kind::PicoSet]),
+        vec(hashable(), 0..6),
+    );
+    let list = (Just(Kind::List), vec(elem(), 0..6));
+    prop_oneof![set, list].prop_map(|(kind, items)| Operand { kind, items })
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let variadic = select(vec![
+        "update",
+        "difference_update",
+        "intersection_update",
+        "union",
+        "intersection",
+        "difference",
+    ]);
+    let unary = select(vec![
+        "symmetric_difference_update",
+        "symmetric_difference",
+        "isdisjoint",
+        "issubset",
+        "issuperset",
+    ]);
+    let binary = select(vec![
+        "and_", "or_", "sub", "xor", "eq", "ne", "lt", "le", "gt", "ge",
+    ]);
+    let inplace = select(vec!["iand", "ior", "isub", "ixor"]);
+    prop_oneof![
+        4 => elem().prop_map(Op::Add),
+        2 => elem().prop_map(Op::Discard),
+        2 => elem().prop_map(Op::Remove),
+        1 => elem().prop_map(Op::Contains),
+        2 => select(vec![Target::NanoSet, Target::PicoSet]).prop_map(Op::Pop),
+        1 => Just(Op::Clear),
+        1 => Just(Op::Copy),
+        1 => Just(Op::Pickle),
+        2 => (variadic, vec(operand(), 0..3)).prop_map(|(m, args)| Op::Method(m, args)),
+        2 => (unary, operand()).prop_map(|(m, arg)| Op::Method(m, vec![arg])),
+        2 => (binary, operand(), any::<bool>()).prop_map(|(o, arg, r)| Op::Operator(o, arg, r)),
+        2 => (inplace, operand()).prop_map(|(o, arg)| Op::Operator(o, arg, false)),
+    ]
+}
+
+// ---------------------------------------------------------------------------
+
+/// The outcome of a step: a description of the returned value, or the name
+/// of the exception type which was raised.
+type Outcome = Result<String, String>;
+
+/// Get the `nanoset` module, registering it in `sys.modules` on first use so
+/// that instances can be pickled.
+fn nanoset(py: Python<'_>) -> &PyModule {
+    let modules = py.import("sys").unwrap().get("modules").unwrap();
+    let modules = modules.downcast::<PyDict>().unwrap();
+    if let Some(module) = modules.get_item("nanoset") {
+        return module.downcast::<PyModule>().unwrap();
+    }
+    let module = PyModule::new(py, "nanoset").unwrap();
+    nanoset_py::init(py, module).unwrap();
+    modules.set_item("nanoset", module).unwrap();
+    module
+}
+
+fn is_set(obj: &PyAny) -> bool {
+    obj.downcast::<PySet>().is_ok()
+        || obj.downcast::<PyFrozenSet>().is_ok()
+        || obj.downcast::<PyCell<NanoSet>>().is_ok()
+        || obj.downcast::<PyCell<PicoSet>>().is_ok()
+}
+
+/// Get the sorted `repr` of the elements of a set.
+fn contents(obj: &PyAny) -> PyResult<Vec<String>> {
+    let mut items = obj
+        .iter()?
+        .map(|item| Ok(item?.repr()?.to_string_lossy().into_owned()))
+        .collect::<PyResult<Vec<String>>>()?;
+    items.sort();
+    Ok(items)
+}
+
+/// Check whether a set is allocated in `obj`, using `__sizeof__` since it
+/// also counts an empty set which the other methods would hide.
+fn is_allocated(obj: &PyAny) -> bool {
+    let size: usize = obj.call_method0("__sizeof__").unwrap().extract().unwrap();
+    let basic: usize = obj.get_type().getattr("__basicsize__").unwrap().extract().unwrap();
+    size > basic
+}
+
+/// Describe the value returned by a step applied to `obj`.
+fn outcome(py: Python, obj: &PyObject, result: PyResult<&PyAny>) -> Outcome {
+    let describe = |value: &PyAny| -> PyResult<String> {
+        if value.as_ptr() == obj.as_ptr() {
+            Ok(String::from("self"))
+        } else if is_set(value) {
+            Ok(format!("{{{}}}", contents(value)?.join(", ")))
+        } else {
+            Ok(value.repr()?.to_string_lossy().into_owned())
+        }
+    };
+    result
+        .and_then(describe)
+        .map_err(|err| err.ptype.as_ref(py).name().into_owned())
+}
+
+impl Elem {
+    fn to_object(&self, py: Python) -> PyObject {
+        match self {
+            Elem::Int(n) => n.to_object(py),
+            Elem::Str(s) => s.to_object(py),
+            Elem::Unhashable => PyList::empty(py).to_object(py),
+        }
+    }
+}
+
+impl Operand {
+    /// Build the operand, using builtin sets in place of nanosets for the
+    /// reference so that it only ever sees builtin semantics.
+    fn build<'p>(&self, py: Python<'p>, reference: bool) -> &'p PyAny {
+        let items: Vec<PyObject> = self.items.iter().map(|e| e.to_object(py)).collect();
+        let list = PyList::new(py, items);
+        let module = nanoset(py);
+        let builtins = py.import("builtins").unwrap();
+        let ty = match (self.kind, reference) {
+            (Kind::List, _) => return list,
+            (Kind::Set, _) | (Kind::NanoSet, true) | (Kind::PicoSet, true) => builtins.get("set"),
+            (Kind::FrozenSet, _) => builtins.get("frozenset"),
+            (Kind::NanoSet, false) => module.get("NanoSet"),
+            (Kind::PicoSet, false) => module.get("PicoSet"),
+        };
+        ty.unwrap().call1((list,)).unwrap()
+    }
+}
+
+/// A builtin set and nanosets to which the same steps are applied.
+struct Lockstep {
+    reference: PyObject,
+    nanoset: PyObject,
+    picoset: PyObject,
+}
+
+impl Lockstep {
+    fn new(py: Python) -> Self {
+        let module = nanoset(py);
+        Self {
+            reference: PySet::empty(py).unwrap().to_object(py),
+            nanoset: module.call0("NanoSet").unwrap().to_object(py),
+            picoset: module.call0("PicoSet").unwrap().to_object(py),
+        }
+    }
+
+    /// Apply `op` to the set `obj`, possibly replacing it with a new object.
+    fn apply(py: Python, obj: &mut PyObject, op: &Op, reference: bool) -> Outcome {
+        let this = obj.clone_ref(py);
+        let this = this.as_ref(py);
+        let result = match op {
+            Op::Add(e) => this.call_method1("add", (e.to_object(py),)),
+            Op::Discard(e) => this.call_method1("discard", (e.to_object(py),)),
+            Op::Remove(e) => this.call_method1("remove", (e.to_object(py),)),
+            Op::Contains(e) => this.call_method1("__contains__", (e.to_object(py),)),
+            Op::Clear => this.call_method0("clear"),
+            Op::Method(name, args) => {
+                let args: Vec<&PyAny> = args.iter().map(|a| a.build(py, reference)).collect();
+                this.call_method1(name, PyTuple::new(py, args))
+            }
+            Op::Operator(name, arg, reflected) => {
+                let arg = arg.build(py, reference);
+                let args = if *reflected { (arg, this) } else { (this, arg) };
+                py.import("operator").unwrap().call1(name, args)
+            }
+            Op::Copy | Op::Pickle => {
+                let new = if let Op::Copy = op {
+                    this.call_method0("copy")
+                } else {
+                    let pickle = py.import("pickle").unwrap();
+                    pickle
+                        .call1("dumps", (this,))
+                        .and_then(|data| pickle.call1("loads", (data,)))
+                };
+                new.inspect(|new| *obj = new.to_object(py))
+            }
+            Op::Pop(_) => unreachable!("pop is applied by `Lockstep::pop`"),
+        };
+        outcome(py, obj, result)
+    }
+
+    /// Pop an element from the target nanoset, and remove the same element
+    /// from the other sets, since the element popped from sets is arbitrary.
+    fn pop(&mut self, py: Python, target: Target) -> Result<(), TestCaseError> {
+        let (popped, others) = match target {
+            Target::NanoSet => (&self.nanoset, [&self.picoset, &self.reference]),
+            Target::PicoSet => (&self.picoset, [&self.nanoset, &self.reference]),
+        };
+        match popped.call_method0(py, "pop") {
+            Ok(item) => {
+                for other in others.iter() {
+                    let result = other.call_method1(py, "remove", (item.clone_ref(py),));
+                    prop_assert!(result.is_ok(), "popped {:?} which was not in {:?}", item, other);
+                }
+            }
+            Err(err) => {
+                prop_assert!(err.is_instance::<pyo3::exceptions::KeyError>(py));
+                for other in others.iter() {
+                    prop_assert!(other.call_method0(py, "pop").is_err());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, py: Python, op: &Op) -> Result<(), TestCaseError> {
+        if let Op::Pop(target) = op {
+            return self.pop(py, *target);
+        }
+        let expected = Self::apply(py, &mut self.reference, op, true);
+        let nanoset = Self::apply(py, &mut self.nanoset, op, false);
+        let picoset = Self::apply(py, &mut self.picoset, op, false);
+        prop_assert_eq!(&nanoset, &expected, "NanoSet");
+        prop_assert_eq!(&picoset, &expected, "PicoSet");
+        Ok(())
+    }
+
+    /// Check the nanosets have the same elements as the reference, and only
+    /// allocate a set when they are not empty.
+    fn check(&self, py: Python) -> Result<(), TestCaseError> {
+        let expected = contents(self.reference.as_ref(py)).unwrap();
+        for obj in [&self.nanoset, &self.picoset].iter() {
+            let obj = obj.as_ref(py);
+            // check the allocation before iterating, since an exhausted
+            // iterator releases an empty set
+            let allocated = is_allocated(obj);
+            prop_assert_eq!(allocated, !expected.is_empty(), "allocation of {:?}", obj);
+            prop_assert_eq!(&contents(obj).unwrap(), &expected, "{}", obj.get_type().name());
+            prop_assert_eq!(obj.len().unwrap(), expected.len());
+        }
+        Ok(())
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig {
+        cases: 128,
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn lockstep(ops in vec(op(), 1..48)) {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let mut sets = Lockstep::new(py);
+        for op in ops.iter() {
+            sets.step(py, op)?;
+            sets.check(py)?;
+        }
+    }
+}
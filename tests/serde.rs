@@ -0,0 +1,197 @@
+#![cfg(feature = "serde")]
+
+extern crate nanoset_py;
+extern crate pyo3;
+extern crate serde;
+
+use nanoset_py::NanoSet;
+use nanoset_py::PicoSet;
+use pyo3::prelude::*;
+use pyo3::types::PyFrozenSet;
+use pyo3::types::PySet;
+use pyo3::types::PyTuple;
+use serde::de::value::Error;
+use serde::de::value::SeqDeserializer;
+use serde::de::value::UnitDeserializer;
+use serde::de::IntoDeserializer;
+use serde::ser::Impossible;
+use serde::ser::SerializeSeq;
+use serde::Deserialize;
+use serde::Serialize;
+use serde::Serializer;
+
+/// A serializer writing values in a compact, JSON-like text format.
+struct Text;
+
+/// A sequence being written by `Text`.
+struct Seq(Vec<String>);
+
+macro_rules! unsupported {
+    ($($method:ident($($arg:ty),*) -> $ok:ty;)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<$ok, Error> {
+                Err(serde::ser::Error::custom(stringify!($method)))
+            }
+        )*
+    }
+}
+
+impl Serializer for Text {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Seq;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> {
+        Ok(format!("{:?}", v))
+    }
+
+    fn serialize_none(self) -> Result<String, Error> {
+        Ok(String::from("null"))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String, Error> {
+        Ok(String::from("null"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Seq, Error> {
+        Ok(Seq(Vec::new()))
+    }
+
+    unsupported! {
+        serialize_i8(i8) -> String;
+        serialize_i16(i16) -> String;
+        serialize_i32(i32) -> String;
+        serialize_u8(u8) -> String;
+        serialize_u16(u16) -> String;
+        serialize_u32(u32) -> String;
+        serialize_f32(f32) -> String;
+        serialize_f64(f64) -> String;
+        serialize_char(char) -> String;
+        serialize_bytes(&[u8]) -> String;
+        serialize_unit_struct(&'static str) -> String;
+        serialize_unit_variant(&'static str, u32, &'static str) -> String;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, _: &T) -> Result<String, Error> {
+        Err(serde::ser::Error::custom("serialize_newtype_struct"))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<String, Error> {
+        Err(serde::ser::Error::custom("serialize_newtype_variant"))
+    }
+}
+
+impl SerializeSeq for Seq {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.0.push(value.serialize(Text)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<String, Error> {
+        Ok(format!("[{}]", self.0.join(", ")))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+macro_rules! serde_tests {
+    ($cls:ident, $module:ident) => {
+        mod $module {
+            use super::*;
+
+            #[test]
+            fn serialize_unallocated() {
+                assert_eq!($cls::new().serialize(Text).unwrap(), "null");
+            }
+
+            #[test]
+            fn serialize_elements() {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                let item = PyTuple::new(py, &[1.to_object(py), "a".to_object(py)]);
+                let set = PySet::new(py, &[item]).unwrap();
+                let nanoset = $cls::from_set(set.into());
+                assert_eq!(nanoset.serialize(Text).unwrap(), "[[1, \"a\"]]");
+            }
+
+            #[test]
+            fn serialize_unsupported() {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                let item = PyFrozenSet::empty(py).unwrap();
+                let set = PySet::new(py, &[item]).unwrap();
+                let nanoset = $cls::from_set(set.into());
+                assert!(nanoset.serialize(Text).is_err());
+            }
+
+            #[test]
+            fn deserialize_unit() {
+                let de: UnitDeserializer<Error> = ().into_deserializer();
+                let nanoset = $cls::deserialize(de).unwrap();
+                assert_eq!(nanoset.serialize(Text).unwrap(), "null");
+            }
+
+            #[test]
+            fn deserialize_empty() {
+                let items: Vec<i64> = Vec::new();
+                let de = SeqDeserializer::<_, Error>::new(items.into_iter());
+                let nanoset = $cls::deserialize(de).unwrap();
+                assert_eq!(nanoset.serialize(Text).unwrap(), "null");
+            }
+
+            #[test]
+            fn deserialize_elements() {
+                let de = SeqDeserializer::<_, Error>::new(vec!["x", "x"].into_iter());
+                let nanoset = $cls::deserialize(de).unwrap();
+                assert_eq!(nanoset.serialize(Text).unwrap(), "[\"x\"]");
+            }
+
+            #[test]
+            fn deserialize_invalid() {
+                let de = 1u64.into_deserializer();
+                let result: Result<$cls, Error> = $cls::deserialize(de);
+                assert!(result.is_err());
+            }
+        }
+    };
+}
+
+serde_tests!(NanoSet, nanoset);
+serde_tests!(PicoSet, picoset);
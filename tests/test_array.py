@@ -0,0 +1,76 @@
+# Tests for the conversion between nanosets and buffers of integers.
+
+import array
+import unittest
+
+import nanoset
+
+
+class TestArray:
+
+    def test_to_array(self):
+        s = self.thetype([3, -1, 2 ** 62, 0])
+        a = s.to_array()
+        self.assertIsInstance(a, nanoset.Int64Array)
+        self.assertEqual(len(a), 4)
+        self.assertEqual(sorted(a.tolist()), [-1, 0, 3, 2 ** 62])
+        view = memoryview(a)
+        self.assertEqual(view.format, "q")
+        self.assertEqual(view.itemsize, 8)
+        self.assertEqual(view.shape, (4,))
+        self.assertTrue(view.readonly)
+        self.assertEqual(view.tolist(), a.tolist())
+        self.assertEqual(array.array("q", view).tolist(), a.tolist())
+
+    def test_to_array_empty(self):
+        a = self.thetype().to_array()
+        self.assertEqual(len(a), 0)
+        self.assertEqual(memoryview(a).tolist(), [])
+
+    def test_to_array_errors(self):
+        self.assertRaises(TypeError, self.thetype(["a"]).to_array)
+        self.assertRaises(TypeError, self.thetype([1.5]).to_array)
+        self.assertRaises(OverflowError, self.thetype([2 ** 64]).to_array)
+
+    def test_to_array_readonly(self):
+        view = memoryview(self.thetype([1]).to_array())
+        with self.assertRaises(TypeError):
+            view[0] = 2
+
+    def test_from_array(self):
+        for code in "bBhHiIlLqQ":
+            with self.subTest(code=code):
+                s = self.thetype.from_array(array.array(code, [1, 2, 2, 3]))
+                self.assertIs(type(s), self.thetype)
+                self.assertEqual(s, {1, 2, 3})
+
+    def test_from_array_roundtrip(self):
+        s = self.thetype(range(-50, 50))
+        self.assertEqual(self.thetype.from_array(s.to_array()), s)
+
+    def test_from_array_strided(self):
+        view = memoryview(array.array("q", range(10)))[::2]
+        self.assertEqual(self.thetype.from_array(view), {0, 2, 4, 6, 8})
+
+    def test_from_array_empty(self):
+        s = self.thetype.from_array(array.array("q"))
+        self.assertEqual(s, self.thetype())
+        self.assertEqual(self.thetype.from_array(b""), self.thetype())
+
+    def test_from_array_errors(self):
+        self.assertRaises(TypeError, self.thetype.from_array, [1, 2])
+        self.assertRaises(TypeError, self.thetype.from_array, array.array("d", [1.0]))
+
+
+class TestNanoSetArray(TestArray, unittest.TestCase):
+    thetype = nanoset.NanoSet
+
+
+class TestPicoSetArray(TestArray, unittest.TestCase):
+    thetype = nanoset.PicoSet
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
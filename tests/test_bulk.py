@@ -0,0 +1,84 @@
+# Tests for the bulk operations over many sets.
+
+import unittest
+
+import nanoset
+from nanoset import NanoSet, PicoSet
+
+
+class TestUnionAll(unittest.TestCase):
+
+    def test_union_all(self):
+        sets = [NanoSet([1, 2]), PicoSet([2, 3]), {4}, frozenset([5]), [6, 6]]
+        result = nanoset.union_all(sets)
+        self.assertIs(type(result), NanoSet)
+        self.assertEqual(result, frozenset(range(1, 7)))
+        self.assertEqual(sets[0], frozenset([1, 2]))
+        self.assertEqual(sets[2], {4})
+
+    def test_union_all_empty(self):
+        self.assertEqual(nanoset.union_all([]), NanoSet())
+        self.assertEqual(nanoset.union_all([NanoSet(), PicoSet(), []]), NanoSet())
+        self.assertEqual(nanoset.union_all(iter([NanoSet(), NanoSet([1])])), {1})
+
+    def test_union_all_errors(self):
+        self.assertRaises(TypeError, nanoset.union_all, 1)
+        self.assertRaises(TypeError, nanoset.union_all, [[[]]])
+
+    @unittest.skipUnless(hasattr(nanoset, "stats"), "built without `stats` feature")
+    def test_union_all_unallocated(self):
+        sets = [NanoSet() for _ in range(10)]
+        nanoset.reset_stats()
+        nanoset.union_all(sets)
+        self.assertEqual(nanoset.stats()["sets_allocated"], 0)
+
+
+class TestIntersectionAll(unittest.TestCase):
+
+    def test_intersection_all(self):
+        sets = [NanoSet([1, 2, 3]), PicoSet([2, 3, 4]), {3, 2}, [2, 9]]
+        result = nanoset.intersection_all(sets)
+        self.assertIs(type(result), NanoSet)
+        self.assertEqual(result, {2})
+        self.assertEqual(sets[0], {1, 2, 3})
+
+    def test_intersection_all_empty(self):
+        self.assertEqual(nanoset.intersection_all([]), NanoSet())
+        self.assertEqual(nanoset.intersection_all([NanoSet([1]), {2}]), NanoSet())
+
+    def test_intersection_all_short_circuit(self):
+        def sets():
+            yield NanoSet([1])
+            yield NanoSet()
+            raise AssertionError("iterable consumed after an empty set")
+        self.assertEqual(nanoset.intersection_all(sets()), NanoSet())
+
+
+class TestAddToEach(unittest.TestCase):
+
+    def test_add_to_each(self):
+        sets = [NanoSet(), PicoSet([1]), set(), NanoSet([2])]
+        self.assertIsNone(nanoset.add_to_each(sets, 3))
+        self.assertEqual(sets, [{3}, {1, 3}, {3}, {2, 3}])
+
+    def test_add_to_each_unhashable(self):
+        sets = [NanoSet(), PicoSet()]
+        self.assertRaises(TypeError, nanoset.add_to_each, sets, [])
+        self.assertEqual(sets, [NanoSet(), PicoSet()])
+
+    def test_add_to_each_errors(self):
+        self.assertRaises(AttributeError, nanoset.add_to_each, [NanoSet(), 1], 1)
+        self.assertRaises(TypeError, nanoset.add_to_each, 1, 1)
+
+    @unittest.skipUnless(hasattr(nanoset, "stats"), "built without `stats` feature")
+    def test_add_to_each_unallocated(self):
+        sets = [NanoSet() for _ in range(10)]
+        nanoset.reset_stats()
+        self.assertRaises(TypeError, nanoset.add_to_each, sets, {})
+        self.assertEqual(nanoset.stats()["sets_allocated"], 0)
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,141 @@
+# Tests for `nanoset.ConcurrentNanoSet`.
+
+import builtins
+import collections.abc
+import copy
+import pickle
+import threading
+import unittest
+
+import nanoset
+from nanoset import ConcurrentNanoSet
+
+
+class TestConcurrentNanoSet(unittest.TestCase):
+
+    def test_empty(self):
+        s = ConcurrentNanoSet()
+        self.assertEqual(len(s), 0)
+        self.assertFalse(s)
+        self.assertEqual(s, builtins.set())
+        self.assertEqual(list(s), [])
+        self.assertEqual(repr(s), "ConcurrentNanoSet()")
+
+    def test_abc(self):
+        s = ConcurrentNanoSet([1, 2])
+        self.assertIsInstance(s, collections.abc.MutableSet)
+        self.assertNotIsInstance(s, collections.abc.Hashable)
+
+    def test_keys(self):
+        s = ConcurrentNanoSet([1, "a", b"a", (1, ("a", b"a"))])
+        self.assertEqual(s, {1, "a", b"a", (1, ("a", b"a"))})
+        self.assertIn(True, s)
+        self.assertNotIn("b", s)
+        self.assertNotIn(1.5, s)
+        self.assertNotIn(frozenset(), s)
+        self.assertRaises(TypeError, s.__contains__, [])
+        self.assertRaises(TypeError, s.add, 1.5)
+        self.assertRaises(TypeError, s.add, (1, frozenset()))
+        self.assertRaises(OverflowError, s.add, 2 ** 64)
+        self.assertEqual(len(s), 4)
+
+    def test_add_remove(self):
+        s = ConcurrentNanoSet()
+        s.add(1)
+        s.add(1)
+        self.assertEqual(s, {1})
+        s.discard(2)
+        s.remove(1)
+        self.assertRaises(KeyError, s.remove, 1)
+        self.assertRaises(KeyError, s.pop)
+        s.update("ab", [1])
+        self.assertIn(s.pop(), {"a", "b", 1})
+        self.assertEqual(len(s), 2)
+        s.clear()
+        self.assertFalse(s)
+
+    def test_operations(self):
+        s = ConcurrentNanoSet("abcd")
+        self.assertEqual(s | {"e"}, builtins.set("abcde"))
+        self.assertEqual(s & {"a", "e"}, {"a"})
+        self.assertEqual(s - {"a"}, builtins.set("bcd"))
+        self.assertEqual(s ^ {"a", "e"}, builtins.set("bcde"))
+        self.assertEqual({"e"} | s, builtins.set("abcde"))
+        self.assertEqual({"a", "e"} - s, {"e"})
+        self.assertIs(type(s | {"e"}), ConcurrentNanoSet)
+        self.assertEqual(s.union("e", "f"), builtins.set("abcdef"))
+        self.assertEqual(s.intersection("abc", "bcd"), builtins.set("bc"))
+        self.assertEqual(s.difference("a", "b"), builtins.set("cd"))
+        self.assertEqual(s.symmetric_difference("ae"), builtins.set("bcde"))
+        self.assertTrue(s.issubset("abcde"))
+        self.assertTrue(s.issuperset("ab"))
+        self.assertTrue(s.isdisjoint("xyz"))
+        self.assertEqual(s, builtins.set("abcd"))
+
+    def test_inplace(self):
+        s = ConcurrentNanoSet("abcd")
+        t = s
+        s |= {"e"}
+        s &= builtins.set("abce")
+        s -= {"a"}
+        s ^= {"b", "f"}
+        self.assertIs(s, t)
+        self.assertEqual(s, builtins.set("cef"))
+        s ^= s
+        self.assertFalse(s)
+
+    def test_comparison(self):
+        s = ConcurrentNanoSet([1, 2])
+        self.assertTrue(s == {1, 2})
+        self.assertTrue(s <= {1, 2})
+        self.assertTrue(s < {1, 2, 3})
+        self.assertTrue(s > {1})
+        self.assertFalse(s >= {3})
+        self.assertTrue(s != [1, 2])
+
+    def test_copy(self):
+        s = ConcurrentNanoSet("abc")
+        for t in (s.copy(), copy.copy(s), copy.deepcopy(s)):
+            self.assertIsNot(t, s)
+            self.assertEqual(t, s)
+            t.add("d")
+            self.assertNotIn("d", s)
+
+    def test_pickle(self):
+        for s in (ConcurrentNanoSet(), ConcurrentNanoSet([1, "a", (b"b",)])):
+            for proto in range(pickle.HIGHEST_PROTOCOL + 1):
+                t = pickle.loads(pickle.dumps(s, proto))
+                self.assertIs(type(t), ConcurrentNanoSet)
+                self.assertEqual(t, s)
+
+    def test_iter_snapshot(self):
+        s = ConcurrentNanoSet(range(10))
+        seen = builtins.set()
+        for x in s:
+            seen.add(x)
+            s.discard(x)
+        self.assertEqual(seen, builtins.set(range(10)))
+        self.assertFalse(s)
+
+    def test_deterministic(self):
+        s = ConcurrentNanoSet(["b", 2, "a", 1])
+        previous = nanoset.set_deterministic(True)
+        try:
+            self.assertEqual(list(s), [1, 2, "a", "b"])
+            self.assertEqual(repr(s), "ConcurrentNanoSet({1, 2, 'a', 'b'})")
+        finally:
+            nanoset.set_deterministic(previous)
+
+    def test_threads(self):
+        s = ConcurrentNanoSet()
+
+        def worker(n):
+            for i in range(n, 4000, 4):
+                s.add(i)
+
+        threads = [threading.Thread(target=worker, args=(n,)) for n in range(4)]
+        for thread in threads:
+            thread.start()
+        for thread in threads:
+            thread.join()
+        self.assertEqual(s, builtins.set(range(4000)))
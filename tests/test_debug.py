@@ -0,0 +1,66 @@
+# Tests for the `nanoset.debug` helpers.
+
+import gc
+import sys
+import tracemalloc
+import unittest
+
+import nanoset
+import nanoset.debug
+
+
+class TestDebug(unittest.TestCase):
+
+    def hidden_sets(self):
+        return [frozenset(s) for s in nanoset.debug.iter_hidden_sets()]
+
+    def test_module(self):
+        self.assertIs(sys.modules["nanoset.debug"], nanoset.debug)
+        self.assertIsInstance(nanoset.debug.ENABLED, bool)
+        self.assertIsInstance(nanoset.debug.TRACEMALLOC_DOMAIN, int)
+
+    @unittest.skipIf(nanoset.debug.ENABLED, "built with `registry` feature")
+    def test_disabled(self):
+        self.assertRaises(RuntimeError, nanoset.debug.iter_hidden_sets)
+
+    @unittest.skipUnless(nanoset.debug.ENABLED, "built without `registry` feature")
+    def test_iter_hidden_sets(self):
+        gc.collect()
+        s = nanoset.PicoSet([1, 2, 3])
+        n = nanoset.NanoSet([4, 5, 6])
+        self.assertIn(frozenset([1, 2, 3]), self.hidden_sets())
+        self.assertNotIn(frozenset([4, 5, 6]), self.hidden_sets())
+        s.clear()
+        self.assertNotIn(frozenset([1, 2, 3]), self.hidden_sets())
+        s.add(7)
+        self.assertIn(frozenset([7]), self.hidden_sets())
+        del s
+        self.assertNotIn(frozenset([7]), self.hidden_sets())
+
+    @unittest.skipUnless(nanoset.debug.ENABLED, "built without `registry` feature")
+    def test_tracemalloc(self):
+        domain = tracemalloc.DomainFilter(True, nanoset.debug.TRACEMALLOC_DOMAIN)
+        tracemalloc.start()
+        try:
+            s = nanoset.PicoSet(range(100))
+            traces = tracemalloc.take_snapshot().filter_traces([domain]).traces
+            self.assertEqual(len(traces), 1)
+            hidden = [h for h in nanoset.debug.iter_hidden_sets() if h == set(range(100))]
+            self.assertEqual(traces[0].size, hidden[0].__sizeof__())
+            # the traced size follows the set when it is resized
+            size = traces[0].size
+            s.update(range(100, 10000))
+            traces = tracemalloc.take_snapshot().filter_traces([domain]).traces
+            self.assertEqual(len(traces), 1)
+            self.assertGreater(traces[0].size, size)
+            del s
+            traces = tracemalloc.take_snapshot().filter_traces([domain]).traces
+            self.assertEqual(len(traces), 0)
+        finally:
+            tracemalloc.stop()
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,84 @@
+# Tests for the `nanoset.Set` factory.
+
+import collections.abc
+import inspect
+import os
+import sys
+import unittest
+
+import nanoset
+
+
+class TestFactory(unittest.TestCase):
+
+    def setUp(self):
+        self.env = os.environ.pop("NANOSET_IMPL", None)
+        nanoset.configure(None)
+
+    def tearDown(self):
+        os.environ.pop("NANOSET_IMPL", None)
+        if self.env is not None:
+            os.environ["NANOSET_IMPL"] = self.env
+        nanoset.configure(None)
+
+    def test_isinstance(self):
+        self.assertIsInstance(nanoset.NanoSet(), nanoset.Set)
+        self.assertIsInstance(nanoset.PicoSet(), nanoset.Set)
+        self.assertNotIsInstance(frozenset(), nanoset.Set)
+        self.assertTrue(issubclass(nanoset.Set, collections.abc.MutableSet))
+
+    def test_signature(self):
+        signature = inspect.signature(nanoset.Set.__new__)
+        self.assertEqual(list(signature.parameters), ["cls", "iterable"])
+
+    def test_default(self):
+        s = nanoset.Set([1, 2, 3])
+        expected = nanoset.PicoSet if sys.flags.optimize else nanoset.NanoSet
+        self.assertIs(type(s), expected)
+        self.assertEqual(s, frozenset([1, 2, 3]))
+        self.assertIsInstance(s, nanoset.Set)
+
+    def test_configure(self):
+        nanoset.configure(nanoset.PicoSet)
+        self.assertIs(type(nanoset.Set()), nanoset.PicoSet)
+        nanoset.configure(impl="nanoset")
+        self.assertIs(type(nanoset.Set()), nanoset.NanoSet)
+        nanoset.configure("PicoSet")
+        self.assertIs(type(nanoset.Set("abc")), nanoset.PicoSet)
+
+    def test_configure_error(self):
+        self.assertRaises(ValueError, nanoset.configure, "femtoset")
+        self.assertRaises(TypeError, nanoset.configure, frozenset)
+        self.assertRaises(TypeError, nanoset.configure)
+        self.assertRaises(TypeError, nanoset.configure, None, impl=None)
+        self.assertRaises(TypeError, nanoset.configure, impl=None, other=None)
+
+    def test_environment(self):
+        os.environ["NANOSET_IMPL"] = "picoset"
+        self.assertIs(type(nanoset.Set()), nanoset.PicoSet)
+        # the implementation is only detected once
+        os.environ["NANOSET_IMPL"] = "nanoset"
+        self.assertIs(type(nanoset.Set()), nanoset.PicoSet)
+        nanoset.configure(None)
+        self.assertIs(type(nanoset.Set()), nanoset.NanoSet)
+
+    def test_environment_error(self):
+        os.environ["NANOSET_IMPL"] = "femtoset"
+        self.assertRaises(ValueError, nanoset.Set)
+
+    def test_configure_overrides_environment(self):
+        os.environ["NANOSET_IMPL"] = "picoset"
+        nanoset.configure(nanoset.NanoSet)
+        self.assertIs(type(nanoset.Set()), nanoset.NanoSet)
+
+    @unittest.skipUnless(sys.version_info >= (3, 9), "requires Python 3.9")
+    def test_class_getitem(self):
+        alias = nanoset.Set[int]
+        self.assertIs(alias.__origin__, nanoset.Set)
+        self.assertEqual(alias.__args__, (int,))
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
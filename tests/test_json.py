@@ -0,0 +1,140 @@
+# Tests for the `nanoset.json` encoding helpers.
+
+import collections
+import json
+import sys
+import types
+import unittest
+
+import nanoset
+import nanoset.json
+from nanoset import NanoSet, PicoSet
+
+
+class TestJson(unittest.TestCase):
+
+    def test_module(self):
+        self.assertIs(sys.modules["nanoset.json"], nanoset.json)
+        self.assertIsInstance(nanoset.json.TAG, str)
+
+    def test_default(self):
+        s = NanoSet([1, 2, 3])
+        self.assertEqual(sorted(json.loads(json.dumps(s, default=nanoset.json.default))), [1, 2, 3])
+        self.assertEqual(json.dumps(PicoSet(), default=nanoset.json.default), "[]")
+        self.assertRaises(TypeError, json.dumps, object(), default=nanoset.json.default)
+
+    def test_default_tagged(self):
+        tagged = nanoset.json.default(PicoSet(["a"]), tagged=True)
+        self.assertEqual(tagged, {nanoset.json.TAG: "PicoSet", "items": ["a"]})
+
+    def test_encoder(self):
+        data = {"a": NanoSet([1]), "b": [PicoSet()]}
+        text = json.dumps(data, cls=nanoset.json.NanoSetEncoder)
+        self.assertEqual(json.loads(text), {"a": [1], "b": [[]]})
+        text = json.dumps(data, cls=nanoset.json.NanoSetEncoder, tagged=True, sort_keys=True)
+        self.assertEqual(json.loads(text)["a"], {nanoset.json.TAG: "NanoSet", "items": [1]})
+        self.assertRaises(TypeError, json.dumps, object(), cls=nanoset.json.NanoSetEncoder)
+
+    def test_object_hook(self):
+        for cls in (NanoSet, PicoSet):
+            with self.subTest(cls=cls.__name__):
+                s = cls([1, "x", (2, (3,)), None])
+                text = json.dumps({"s": s}, default=lambda o: nanoset.json.default(o, True))
+                decoded = json.loads(text, object_hook=nanoset.json.object_hook)["s"]
+                self.assertIs(type(decoded), cls)
+                self.assertEqual(decoded, s)
+
+    def test_object_hook_untagged(self):
+        obj = {"items": [1], "other": 2}
+        self.assertEqual(nanoset.json.object_hook(obj), obj)
+        self.assertRaises(ValueError, nanoset.json.object_hook, {nanoset.json.TAG: "set", "items": []})
+        self.assertRaises(TypeError, nanoset.json.object_hook, {nanoset.json.TAG: "NanoSet", "items": 1})
+
+    def test_dumps(self):
+        # the fast path must give the same output as `json.dumps`
+        items = [1, -2, 10**30, 2.5, float("inf"), float("nan"), "é\n\"\\\x7f", "\U0001f600", None, (True, ("a",))]
+        for cls in (NanoSet, PicoSet):
+            with self.subTest(cls=cls.__name__):
+                s = cls(items)
+                self.assertEqual(nanoset.json.dumps(s), json.dumps(list(s)))
+                tagged = {nanoset.json.TAG: cls.__name__, "items": list(s)}
+                self.assertEqual(nanoset.json.dumps(s, tagged=True), json.dumps(tagged))
+
+    def test_dumps_deterministic(self):
+        previous = nanoset.set_deterministic(True)
+        try:
+            s = NanoSet(["b", "c", "a", 2, 1])
+            self.assertEqual(nanoset.json.dumps(s), '[1, 2, "a", "b", "c"]')
+            self.assertEqual(json.dumps(s, default=nanoset.json.default), '[1, 2, "a", "b", "c"]')
+        finally:
+            nanoset.set_deterministic(previous)
+
+    def test_dumps_fallback(self):
+        class Int(int):
+            def __repr__(self):
+                return "Int()"
+        s = NanoSet([Int(3), "\ud800", frozenset()])
+        self.assertRaises(TypeError, nanoset.json.dumps, s)
+        self.assertEqual(nanoset.json.dumps(NanoSet([Int(3)])), "[3]")
+        self.assertEqual(nanoset.json.dumps(NanoSet(["\ud800"])), '["\\ud800"]')
+        self.assertEqual(nanoset.json.dumps({"a": NanoSet()}, indent=None), '{"a": []}')
+
+    def test_loads(self):
+        s = NanoSet([(1, 2), "x"])
+        self.assertEqual(nanoset.json.loads(nanoset.json.dumps(s, tagged=True)), s)
+        self.assertEqual(nanoset.json.loads("[1]"), [1])
+
+
+class TestMsgpack(unittest.TestCase):
+
+    def setUp(self):
+        # use a stand-in for `msgpack` if it is not installed, since the
+        # codec only needs `msgpack.ExtType`
+        self.msgpack = sys.modules.get("msgpack")
+        try:
+            import msgpack
+        except ImportError:
+            msgpack = types.ModuleType("msgpack")
+            msgpack.ExtType = collections.namedtuple("ExtType", "code data")
+            sys.modules["msgpack"] = msgpack
+        self.ExtType = msgpack.ExtType
+
+    def tearDown(self):
+        if self.msgpack is None:
+            sys.modules.pop("msgpack", None)
+        else:
+            sys.modules["msgpack"] = self.msgpack
+
+    def test_roundtrip(self):
+        items = [0, -1, -33, 200, -2**63, 2**64 - 1, 2.5, "é", "x" * 40, b"ab", None, False, (1, ("a",))]
+        for cls in (NanoSet, PicoSet):
+            with self.subTest(cls=cls.__name__):
+                s = cls(items)
+                ext = nanoset.json.msgpack_default(s)
+                self.assertIsInstance(ext, self.ExtType)
+                self.assertEqual(ext.code, nanoset.json.EXT_CODE)
+                decoded = nanoset.json.msgpack_ext_hook(ext.code, ext.data)
+                self.assertIs(type(decoded), cls)
+                self.assertEqual(decoded, s)
+
+    def test_empty(self):
+        ext = nanoset.json.msgpack_default(PicoSet())
+        self.assertEqual(nanoset.json.msgpack_ext_hook(ext.code, ext.data), PicoSet())
+
+    def test_errors(self):
+        self.assertRaises(TypeError, nanoset.json.msgpack_default, object())
+        self.assertRaises(TypeError, nanoset.json.msgpack_default, NanoSet([frozenset()]))
+        self.assertRaises(OverflowError, nanoset.json.msgpack_default, NanoSet([2**64]))
+        for data in (b"", b"\x05\x90", b"\x00\x91", b"\x00\x90\x00", b"\x00\x81\x01\x01", b"\x00\x01"):
+            with self.subTest(data=data):
+                with self.assertRaises((TypeError, ValueError)):
+                    nanoset.json.msgpack_ext_hook(nanoset.json.EXT_CODE, data)
+
+    def test_other_ext_type(self):
+        self.assertEqual(nanoset.json.msgpack_ext_hook(1, b"x"), self.ExtType(1, b"x"))
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,140 @@
+# Tests with crafted pickles and `set` subclasses trying to make an instance
+# wrap something else than a builtin set.
+#
+# The wrapped set must always be an exact `set` created by the wrapper, so
+# that every method keeps raising proper exceptions afterwards.
+
+import builtins
+import pickle
+import unittest
+
+import nanoset
+from nanoset import NanoSet, PicoSet
+
+
+class EvilSet(builtins.set):
+    'set subclass whose methods return or raise unexpected things'
+
+    def copy(self):
+        return ["not", "a", "set"]
+
+    def __iter__(self):
+        raise AssertionError("elements must be copied without iterating")
+
+    def __len__(self):
+        return -1
+
+    def __reduce__(self):
+        return (EvilSet, (list(builtins.set.__iter__(self)),))
+
+
+class Forge(object):
+    'object unpickled as the result of calling `func` with `args`'
+
+    def __init__(self, func, args, state=None):
+        self.func = func
+        self.args = args
+        self.state = state
+
+    def __reduce__(self):
+        if self.state is None:
+            return (self.func, self.args)
+        return (self.func, self.args, self.state)
+
+
+class TestMalicious:
+
+    # states which `__setstate__` must reject
+    INVALID_STATES = [
+        [1, 2],
+        (1, 2),
+        {1: 2},
+        frozenset([1]),
+        "ab",
+        1,
+        {"inner": [1]},
+    ]
+
+    def forge(self, *args):
+        return pickle.loads(pickle.dumps(Forge(*args)))
+
+    def assertWrapsSet(self, s, expected):
+        # the wrapped set is only exposed through a copy, which must be an
+        # exact `set` copied from it, and all methods must keep working
+        state = s.__getstate__()
+        if expected:
+            self.assertIs(type(state), builtins.set)
+        else:
+            self.assertIsNone(state)
+        self.assertEqual(len(s), len(expected))
+        self.assertEqual(builtins.set(s), expected)
+        self.assertEqual(s, expected)
+        self.assertEqual(repr(s).startswith(self.thetype.__name__), True)
+        for item in expected:
+            self.assertIn(item, s)
+        self.assertNotIn(object(), s)
+        self.assertRaises(TypeError, s.__contains__, [])
+        copy = s.copy()
+        while copy:
+            self.assertIn(copy.pop(), expected)
+        self.assertRaises(KeyError, copy.pop)
+
+    def test_setstate_invalid(self):
+        for state in self.INVALID_STATES:
+            with self.subTest(state=state):
+                s = self.thetype([1, 2])
+                self.assertRaises(TypeError, s.__setstate__, state)
+                self.assertWrapsSet(s, {1, 2})
+
+    def test_setstate_subclass(self):
+        s = self.thetype([1])
+        s.__setstate__(EvilSet([3, 4]))
+        self.assertWrapsSet(s, {3, 4})
+        s.__setstate__(EvilSet())
+        self.assertWrapsSet(s, builtins.set())
+
+    def test_constructor_subclass(self):
+        self.assertWrapsSet(self.thetype(EvilSet([1, 2])), {1, 2})
+        self.assertWrapsSet(self.thetype(EvilSet()), builtins.set())
+        s = self.thetype([5])
+        s.__init__(EvilSet([1, 2]))
+        self.assertWrapsSet(s, {1, 2})
+
+    def test_update_subclass(self):
+        s = self.thetype([1])
+        s.update(EvilSet([2]))
+        s |= EvilSet([3])
+        s.symmetric_difference_update(EvilSet([4]))
+        self.assertWrapsSet(s, {1, 2, 3, 4})
+
+    def test_pickle_state_invalid(self):
+        for state in self.INVALID_STATES:
+            with self.subTest(state=state):
+                with self.assertRaises(TypeError):
+                    self.forge(self.thetype, (), state)
+
+    def test_pickle_state_subclass(self):
+        s = self.forge(self.thetype, (), EvilSet([1, 2]))
+        self.assertWrapsSet(s, {1, 2})
+
+    def test_pickle_constructor_subclass(self):
+        s = self.forge(self.thetype, (EvilSet([1, 2]),))
+        self.assertWrapsSet(s, {1, 2})
+
+    def test_pickle_unhashable(self):
+        self.assertRaises(TypeError, self.forge, self.thetype, ([[]],))
+        self.assertRaises(TypeError, self.forge, self.thetype, (1,))
+
+
+class TestNanoSetMalicious(TestMalicious, unittest.TestCase):
+    thetype = NanoSet
+
+
+class TestPicoSetMalicious(TestMalicious, unittest.TestCase):
+    thetype = PicoSet
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,79 @@
+# Tests for the in-place and reflected set operators, and `__sizeof__`.
+
+import builtins
+import operator
+import unittest
+
+import nanoset
+from nanoset import NanoSet, PicoSet
+
+
+class TestOperators:
+
+    def operands(self):
+        return [builtins.set([2, 3]), frozenset([2, 3]), NanoSet([2, 3]), PicoSet([2, 3])]
+
+    def test_inplace(self):
+        results = [
+            (operator.iand, {2}),
+            (operator.ior, {1, 2, 3}),
+            (operator.isub, {1}),
+            (operator.ixor, {1, 3}),
+        ]
+        for op, expected in results:
+            for other in self.operands():
+                with self.subTest(op=op.__name__, other=type(other).__name__):
+                    s = t = self.thetype([1, 2])
+                    s = op(s, other)
+                    self.assertIs(s, t)
+                    self.assertEqual(s, expected)
+                    self.assertEqual(other, {2, 3})
+
+    def test_inplace_not_set(self):
+        for op in (operator.iand, operator.ior, operator.isub, operator.ixor):
+            with self.subTest(op=op.__name__):
+                s = self.thetype([1, 2])
+                self.assertRaises(TypeError, op, s, [2, 3])
+                self.assertEqual(s, {1, 2})
+
+    def test_reflected(self):
+        results = [
+            (operator.and_, {2}),
+            (operator.or_, {1, 2, 3}),
+            (operator.sub, {3}),
+            (operator.xor, {1, 3}),
+        ]
+        for op, expected in results:
+            for other in (builtins.set([2, 3]), frozenset([2, 3])):
+                with self.subTest(op=op.__name__, other=type(other).__name__):
+                    result = op(other, self.thetype([1, 2]))
+                    self.assertIs(type(result), self.thetype)
+                    self.assertEqual(result, expected)
+
+    def test_reflected_not_set(self):
+        for op in (operator.and_, operator.or_, operator.sub, operator.xor):
+            with self.subTest(op=op.__name__):
+                self.assertRaises(TypeError, op, [2, 3], self.thetype([1, 2]))
+
+    def test_sizeof(self):
+        # the wrapped set is counted, but not the elements, like `set` does
+        basicsize = self.thetype.__basicsize__
+        self.assertEqual(self.thetype().__sizeof__(), basicsize)
+        s = self.thetype(range(100))
+        self.assertEqual(s.__sizeof__(), basicsize + builtins.set(range(100)).__sizeof__())
+        s.clear()
+        self.assertEqual(s.__sizeof__(), basicsize)
+
+
+class TestNanoSetOperators(TestOperators, unittest.TestCase):
+    thetype = NanoSet
+
+
+class TestPicoSetOperators(TestOperators, unittest.TestCase):
+    thetype = PicoSet
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,300 @@
+# Check nanosets provide every method of builtin sets, with the same
+# signatures and the same results for random inputs.
+#
+# Intentional differences are listed in `nanoset.__divergences__`, and each
+# of them is checked to behave as described.
+#
+# The random inputs are generated from the seed in `NANOSET_PARITY_SEED`, or
+# from a new seed printed on `stderr`, so that failures can be reproduced.
+
+import builtins
+import inspect
+import operator
+import os
+import random
+import sys
+import typing
+import unittest
+
+import nanoset
+from nanoset import NanoSet, PicoSet
+
+# the generic object protocol, which has nothing specific to sets
+OBJECT_PROTOCOL = {
+    "__class__", "__delattr__", "__dir__", "__doc__", "__format__",
+    "__getattribute__", "__init_subclass__", "__reduce_ex__", "__setattr__",
+    "__str__", "__subclasshook__",
+}
+
+# the elements of the random sets, all hashable and comparable for equality
+POOL = list(range(8)) + list("abcd") + [None, 2.5, (1, 2), frozenset()]
+
+# a stand-in for the instance under test, to apply an operator to itself
+SELF = object()
+
+# the signatures of the methods of `set` which are not introspectable on
+# every Python version, or only have the generic signature of `__init__`
+SIGNATURES = {
+    "__init__": "(self, iterable=(), /)",
+    "__class_getitem__": "(object, /)",
+    "__contains__": "(self, object, /)",
+    "add": "(self, object, /)",
+    "clear": "(self, /)",
+    "copy": "(self, /)",
+    "difference": "(self, /, *others)",
+    "difference_update": "(self, /, *others)",
+    "discard": "(self, object, /)",
+    "intersection": "(self, /, *others)",
+    "intersection_update": "(self, /, *others)",
+    "isdisjoint": "(self, other, /)",
+    "issubset": "(self, other, /)",
+    "issuperset": "(self, other, /)",
+    "pop": "(self, /)",
+    "remove": "(self, object, /)",
+    "symmetric_difference": "(self, other, /)",
+    "symmetric_difference_update": "(self, other, /)",
+    "union": "(self, /, *others)",
+    "update": "(self, /, *others)",
+}
+
+
+def signature(text):
+    namespace = {}
+    exec("def f{}: pass".format(text), namespace)
+    return inspect.signature(namespace["f"])
+
+
+def reflected(op):
+    return lambda obj, other: op(other, obj)
+
+
+OPERATORS = {
+    "__and__": operator.and_,
+    "__or__": operator.or_,
+    "__sub__": operator.sub,
+    "__xor__": operator.xor,
+    "__rand__": reflected(operator.and_),
+    "__ror__": reflected(operator.or_),
+    "__rsub__": reflected(operator.sub),
+    "__rxor__": reflected(operator.xor),
+    "__iand__": operator.iand,
+    "__ior__": operator.ior,
+    "__isub__": operator.isub,
+    "__ixor__": operator.ixor,
+    "__eq__": operator.eq,
+    "__ne__": operator.ne,
+    "__lt__": operator.lt,
+    "__le__": operator.le,
+    "__gt__": operator.gt,
+    "__ge__": operator.ge,
+}
+
+# methods taking any number of iterables
+VARIADIC = {
+    "difference", "difference_update", "intersection", "intersection_update",
+    "union", "update",
+}
+
+# methods taking exactly one iterable
+UNARY = {
+    "isdisjoint", "issubset", "issuperset", "symmetric_difference",
+    "symmetric_difference_update", "__init__",
+}
+
+# methods taking a single element
+ELEMENT = {"add", "discard", "remove", "__contains__"}
+
+# methods taking no argument
+NULLARY = {"clear", "copy", "__len__", "__iter__"}
+
+
+class TestParity(unittest.TestCase):
+
+    TRIALS = 50
+
+    @classmethod
+    def setUpClass(cls):
+        seed = os.environ.get("NANOSET_PARITY_SEED")
+        cls.seed = int(seed) if seed else random.randrange(2 ** 32)
+        print("NANOSET_PARITY_SEED={}".format(cls.seed), file=sys.stderr)
+
+    def setUp(self):
+        self.rng = random.Random(self.seed)
+
+    def elements(self):
+        return self.rng.sample(POOL, self.rng.randint(0, 6))
+
+    def operand(self, iterable):
+        kinds = [builtins.set, frozenset, NanoSet, PicoSet, SELF]
+        if iterable:
+            kinds += [list, tuple, dict.fromkeys, iter]
+        else:
+            kinds += [list]
+        return (self.rng.choice(kinds), self.elements())
+
+    def arguments(self, name):
+        if name in OPERATORS:
+            return [self.operand(iterable=False)]
+        elif name in VARIADIC:
+            return [self.operand(iterable=True) for _ in range(self.rng.randint(0, 3))]
+        elif name in UNARY:
+            return [self.operand(iterable=True)]
+        elif name in ELEMENT:
+            # builtin sets are looked up as frozensets
+            if self.rng.random() < 0.25:
+                return [(builtins.set, self.elements())]
+            return [(None, self.rng.choice(POOL + [[]]))]
+        elif name in NULLARY:
+            return []
+        self.fail("no recipe to check set.{}".format(name))
+
+    def build(self, spec, receiver):
+        kind, elements = spec
+        if type(receiver) is builtins.set and kind in (NanoSet, PicoSet):
+            kind = builtins.set  # the reference for any other set
+        if kind is SELF:
+            return receiver
+        elif kind is None:
+            return elements
+        return kind(elements)
+
+    def outcome(self, name, receiver, args):
+        args = [self.build(spec, receiver) for spec in args]
+        if name in OPERATORS:
+            call = OPERATORS[name]
+        else:
+            call = lambda obj, *args: getattr(obj, name)(*args)
+        try:
+            result = call(receiver, *args)
+        except Exception as err:
+            outcome = ("raised", type(err))
+        else:
+            if result is receiver:
+                result = "self"
+            elif isinstance(result, (builtins.set, frozenset, NanoSet, PicoSet)):
+                result = frozenset(result)
+            elif name == "__iter__":
+                result = sorted(map(repr, result))
+            outcome = ("returned", result)
+        return outcome, sorted(map(repr, receiver))
+
+    def checked(self, cls):
+        divergences = builtins.set(nanoset.__divergences__) | OBJECT_PROTOCOL
+        for name in dir(builtins.set):
+            if name not in divergences and callable(getattr(builtins.set, name)):
+                yield name
+
+    def assertCompatible(self, actual, expected):
+        """Check a method accepts the same arguments as a method of `set`.
+
+        Positional-only parameters of `set` may also be accepted by keyword,
+        since `pyo3` can only make the receiver positional-only.
+        """
+        actual_params = list(actual.parameters.values())
+        expected_params = list(expected.parameters.values())
+        self.assertEqual(len(actual_params), len(expected_params), "{} != {}".format(actual, expected))
+        for param, reference in zip(actual_params, expected_params):
+            if reference.kind == reference.POSITIONAL_ONLY:
+                self.assertIn(param.kind, (param.POSITIONAL_ONLY, param.POSITIONAL_OR_KEYWORD))
+            else:
+                self.assertEqual((param.kind, param.name), (reference.kind, reference.name))
+            self.assertEqual(
+                param.default is param.empty,
+                reference.default is reference.empty,
+                "{} != {}".format(actual, expected),
+            )
+
+    def diverges_getstate(self, cls):
+        self.assertIsNone(builtins.set([1]).__getstate__())
+        self.assertIs(type(cls([1]).__getstate__()), builtins.set)
+        self.assertEqual(cls([1]).__getstate__(), {1})
+        self.assertIsNone(cls().__getstate__())
+
+    def diverges_new(self, cls):
+        self.assertEqual(builtins.set.__new__(builtins.set, [1]), builtins.set())
+        self.assertEqual(cls.__new__(cls, [1]), {1})
+
+    def diverges_reduce(self, cls):
+        self.assertEqual(builtins.set([1]).__reduce__(), (builtins.set, ([1],), None))
+        self.assertEqual(cls([1]).__reduce__(), (cls, ((1,),)))
+        self.assertEqual(cls().__reduce__(), (cls, ()))
+
+    def diverges_repr(self, cls):
+        self.assertEqual(repr(cls([1])), "{}({{1}})".format(cls.__name__))
+        legacy = nanoset.set_legacy_repr(True)
+        try:
+            self.assertEqual(repr(cls([1])), repr(builtins.set([1])))
+        finally:
+            nanoset.set_legacy_repr(legacy)
+
+    def diverges_sizeof(self, cls):
+        self.assertEqual(cls().__sizeof__(), cls.__basicsize__)
+        self.assertLess(cls().__sizeof__(), builtins.set().__sizeof__())
+        self.assertGreaterEqual(cls([1]).__sizeof__(), cls.__basicsize__ + builtins.set().__sizeof__())
+
+    def test_divergences(self):
+        self.assertIsInstance(nanoset.__divergences__, dict)
+        for name, reason in nanoset.__divergences__.items():
+            self.assertIn(name, dir(builtins.set))
+            self.assertIsInstance(reason, str)
+            self.assertTrue(reason)
+            check = getattr(self, "diverges_{}".format(name.strip("_")), None)
+            if check is None:
+                self.fail("no check for the divergence of {}".format(name))
+            for cls in (NanoSet, PicoSet):
+                with self.subTest(cls=cls.__name__, name=name):
+                    check(cls)
+
+    def test_attributes(self):
+        for cls in (NanoSet, PicoSet):
+            missing = [name for name in dir(builtins.set) if not hasattr(cls, name)]
+            self.assertEqual(missing, [], "missing from {}".format(cls.__name__))
+            self.assertIsNone(cls.__hash__)
+
+    def test_signatures(self):
+        for cls in (NanoSet, PicoSet):
+            for name in self.checked(cls):
+                with self.subTest(cls=cls.__name__, name=name):
+                    if name in SIGNATURES:
+                        expected = signature(SIGNATURES[name])
+                    else:
+                        expected = inspect.signature(getattr(builtins.set, name))
+                    self.assertCompatible(inspect.signature(getattr(cls, name)), expected)
+
+    def test_semantics(self):
+        for cls in (NanoSet, PicoSet):
+            for name in self.checked(cls):
+                if name in ("__class_getitem__", "pop"):
+                    continue
+                for _ in range(self.TRIALS):
+                    elements = self.elements()
+                    args = self.arguments(name)
+                    with self.subTest(cls=cls.__name__, name=name, seed=self.seed, elements=elements, args=args):
+                        expected = self.outcome(name, builtins.set(elements), args)
+                        actual = self.outcome(name, cls(elements), args)
+                        self.assertEqual(actual, expected)
+
+    def test_pop(self):
+        for cls in (NanoSet, PicoSet):
+            for _ in range(self.TRIALS):
+                elements = self.elements()
+                with self.subTest(cls=cls.__name__, seed=self.seed, elements=elements):
+                    s = cls(elements)
+                    if not elements:
+                        self.assertRaises(KeyError, s.pop)
+                    else:
+                        item = s.pop()
+                        self.assertIn(item, elements)
+                        self.assertEqual(s, builtins.set(elements) - {item})
+
+    def test_class_getitem(self):
+        for cls in (NanoSet, PicoSet):
+            alias = cls[int]
+            self.assertIs(typing.get_origin(alias), cls)
+            self.assertEqual(typing.get_args(alias), typing.get_args(builtins.set[int]))
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,221 @@
+# Tests for `nanoset.PersistentNanoSet` and its evolver.
+
+import collections.abc
+import copy
+import pickle
+import random
+import unittest
+
+import nanoset
+from nanoset import PersistentNanoSet
+
+
+class Key(object):
+    """A key with a custom hash, to create collisions in the trie."""
+
+    def __init__(self, value, hash):
+        self.value = value
+        self.hash = hash
+
+    def __hash__(self):
+        return self.hash
+
+    def __eq__(self, other):
+        return isinstance(other, Key) and other.value == self.value
+
+    def __repr__(self):
+        return "Key({!r}, {!r})".format(self.value, self.hash)
+
+
+class TestPersistentNanoSet(unittest.TestCase):
+
+    def test_empty(self):
+        s = PersistentNanoSet()
+        self.assertEqual(len(s), 0)
+        self.assertFalse(s)
+        self.assertEqual(s, frozenset())
+        self.assertEqual(list(s), [])
+        self.assertEqual(repr(s), "PersistentNanoSet()")
+
+    def test_abc(self):
+        s = PersistentNanoSet([1, 2])
+        self.assertIsInstance(s, collections.abc.Set)
+        self.assertIsInstance(s, collections.abc.Hashable)
+        self.assertNotIsInstance(s, collections.abc.MutableSet)
+
+    def test_hash(self):
+        s = PersistentNanoSet("abc")
+        self.assertEqual(hash(s), hash(frozenset("abc")))
+        self.assertEqual({frozenset("abc"): 1}[s], 1)
+        self.assertEqual(hash(PersistentNanoSet()), hash(frozenset()))
+        self.assertRaises(TypeError, PersistentNanoSet, [[]])
+
+    def test_add_discard(self):
+        s = PersistentNanoSet([1, 2, 3])
+        t = s.add(4)
+        self.assertEqual(s, {1, 2, 3})
+        self.assertEqual(t, {1, 2, 3, 4})
+        self.assertIs(t.add(4), t)
+        u = t.discard(1)
+        self.assertEqual(u, {2, 3, 4})
+        self.assertEqual(t, {1, 2, 3, 4})
+        self.assertIs(u.discard(1), u)
+        self.assertEqual(u.remove(2), {3, 4})
+        self.assertRaises(KeyError, u.remove, 1)
+
+    def test_copy(self):
+        s = PersistentNanoSet("abc")
+        self.assertIs(s.copy(), s)
+        self.assertIs(copy.copy(s), s)
+        self.assertEqual(copy.deepcopy(s), s)
+
+    def test_pickle(self):
+        for s in (PersistentNanoSet(), PersistentNanoSet(range(100))):
+            for proto in range(pickle.HIGHEST_PROTOCOL + 1):
+                t = pickle.loads(pickle.dumps(s, proto))
+                self.assertIs(type(t), PersistentNanoSet)
+                self.assertEqual(t, s)
+
+    def test_operations(self):
+        s = PersistentNanoSet("abcd")
+        self.assertEqual(s.union("ef", "g"), frozenset("abcdefg"))
+        self.assertEqual(s.intersection("abx", "bcx"), frozenset("b"))
+        self.assertEqual(s.difference("a", ["b"]), frozenset("cd"))
+        self.assertEqual(s.symmetric_difference("aaxx"), frozenset("bcdx"))
+        self.assertEqual(s | {"x"}, frozenset("abcdx"))
+        self.assertEqual(s & {"a", "x"}, frozenset("a"))
+        self.assertEqual(s - {"a", "x"}, frozenset("bcd"))
+        self.assertEqual(s ^ {"a", "x"}, frozenset("bcdx"))
+        self.assertIs(type(s | {"x"}), PersistentNanoSet)
+        self.assertTrue(s.issubset("abcde"))
+        self.assertFalse(s.issubset("abc"))
+        self.assertTrue(s.issuperset("ab"))
+        self.assertTrue(s.isdisjoint("xyz"))
+        self.assertFalse(s.isdisjoint("xya"))
+        with self.assertRaises(TypeError):
+            s | "abc"
+
+    def test_reflected_operations(self):
+        s = PersistentNanoSet("abcd")
+        for left in ({"a", "x"}, frozenset("ax"), nanoset.NanoSet("ax")):
+            with self.subTest(left=type(left).__name__):
+                self.assertEqual(left | s, frozenset("abcdx"))
+                self.assertEqual(left & s, frozenset("a"))
+                self.assertEqual(left - s, frozenset("x"))
+                self.assertEqual(left ^ s, frozenset("bcdx"))
+        self.assertIs(type({"x"} | s), PersistentNanoSet)
+        self.assertIs(type({"x"} - s), PersistentNanoSet)
+        self.assertEqual(frozenset("aaxx") - s, frozenset("x"))
+        with self.assertRaises(TypeError):
+            "abc" | s
+        with self.assertRaises(TypeError):
+            ["a"] - s
+
+    def test_comparisons(self):
+        s = PersistentNanoSet("abc")
+        self.assertEqual(s, frozenset("abc"))
+        self.assertEqual(s, PersistentNanoSet("cba"))
+        self.assertNotEqual(s, PersistentNanoSet("ab"))
+        self.assertNotEqual(s, "abc")
+        self.assertLess(PersistentNanoSet("ab"), s)
+        self.assertLessEqual(s, {"a", "b", "c"})
+        self.assertGreater(s, frozenset("b"))
+        self.assertGreaterEqual(s, PersistentNanoSet("abc"))
+        self.assertFalse(s < s)
+
+    def test_repr(self):
+        self.assertEqual(repr(PersistentNanoSet([1])), "PersistentNanoSet({1})")
+        self.assertEqual(eval(repr(PersistentNanoSet("abc"))), frozenset("abc"))
+
+    def test_cyclical_repr(self):
+        class Holder(object):
+            def __repr__(self):
+                return repr(self.set)
+        holder = Holder()
+        holder.set = PersistentNanoSet([holder])
+        self.assertEqual(repr(holder), "PersistentNanoSet({PersistentNanoSet(...)})")
+
+    def test_collisions(self):
+        keys = [Key(i, i % 3) for i in range(30)]
+        s = PersistentNanoSet(keys)
+        self.assertEqual(len(s), 30)
+        for key in keys:
+            self.assertIn(Key(key.value, key.hash), s)
+        self.assertNotIn(Key(100, 1), s)
+        for key in keys:
+            s = s.remove(key)
+        self.assertEqual(s, frozenset())
+
+    def test_structural_sharing(self):
+        # compare every version against a builtin set, keys are chosen to
+        # force both deep branches and collisions
+        rng = random.Random(42)
+        pool = [Key(i, rng.choice([i, i % 7, -i, 1 << (i % 64)])) for i in range(50)]
+        pool.extend(range(50))
+        expected, current, versions = set(), PersistentNanoSet(), []
+        for _ in range(1000):
+            key = rng.choice(pool)
+            if rng.random() < 0.6:
+                expected.add(key)
+                current = current.add(key)
+            else:
+                expected.discard(key)
+                current = current.discard(key)
+            versions.append((frozenset(expected), current))
+        for frozen, version in versions:
+            self.assertEqual(len(version), len(frozen))
+            self.assertEqual(version, frozen)
+            self.assertEqual(frozenset(version), frozen)
+            self.assertEqual(hash(version), hash(frozen))
+
+    def test_iterator(self):
+        s = PersistentNanoSet(range(100))
+        it = iter(s)
+        self.assertIs(iter(it), it)
+        self.assertEqual(sorted(it), list(range(100)))
+        self.assertRaises(StopIteration, next, it)
+
+    def test_class_getitem(self):
+        alias = PersistentNanoSet[int]
+        self.assertEqual(alias([1, 2]), PersistentNanoSet([1, 2]))
+
+
+class TestEvolver(unittest.TestCase):
+
+    def test_evolver(self):
+        s = PersistentNanoSet("abc")
+        e = s.evolver()
+        self.assertFalse(e.is_dirty())
+        e.add("d")
+        e.discard("a")
+        e.update("xy", "z")
+        self.assertTrue(e.is_dirty())
+        self.assertEqual(len(e), 6)
+        self.assertIn("d", e)
+        self.assertNotIn("a", e)
+        self.assertRaises(KeyError, e.remove, "a")
+        t = e.persistent()
+        self.assertFalse(e.is_dirty())
+        self.assertEqual(s, frozenset("abc"))
+        self.assertEqual(t, frozenset("bcdxyz"))
+
+    def test_evolver_after_persistent(self):
+        e = PersistentNanoSet().evolver()
+        e.update(range(100))
+        s = e.persistent()
+        e.remove(0)
+        e.add(100)
+        self.assertEqual(s, frozenset(range(100)))
+        self.assertEqual(e.persistent(), frozenset(range(1, 101)))
+
+    def test_evolver_empty(self):
+        e = PersistentNanoSet([1]).evolver()
+        e.remove(1)
+        self.assertEqual(len(e), 0)
+        self.assertEqual(e.persistent(), PersistentNanoSet())
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,209 @@
+# Tests with elements modifying the instance they are being added to, looked
+# up in, or removed from, while their `__hash__` or `__eq__` method is called.
+#
+# Mutating methods must give the same results as builtin sets, except for the
+# in-place operators, which cannot be called while another method of the same
+# instance is running (and conversely), and raise `RuntimeError` instead.
+
+import builtins
+import copy
+import unittest
+
+import nanoset
+from nanoset import NanoSet, PicoSet
+
+
+class Reentrant(object):
+    'element calling `attack` with its container when hashed or compared'
+
+    def __init__(self, key, when=None, attack=None):
+        self.key = key
+        self.when = when
+        self.attack = attack
+
+    def __repr__(self):
+        return "Reentrant({!r})".format(self.key)
+
+    def trigger(self, when):
+        # only attack once, so that nested calls do not recurse
+        if self.when == when and self.attack is not None:
+            attack, self.attack = self.attack, None
+            attack()
+
+    def __hash__(self):
+        self.trigger("hash")
+        # collide with other elements so that `__eq__` gets called
+        return hash(self.key) & 1
+
+    def __eq__(self, other):
+        self.trigger("eq")
+        if isinstance(other, Reentrant):
+            other.trigger("eq")
+            return self.key == other.key
+        return NotImplemented
+
+
+def attempt(func):
+    'call `func`, returning its result or the type of the raised exception'
+    try:
+        return ("ok", func())
+    except RuntimeError:
+        return ("raised", RuntimeError)
+    except Exception as err:
+        return ("raised", type(err))
+
+
+class TestReentrancy:
+
+    # the attacks performed by the elements on their container
+    ATTACKS = {
+        "add": lambda s: s.add(100),
+        "clear": lambda s: s.clear(),
+        "discard_all": lambda s: [s.discard(x) for x in list(s)],
+        "init": lambda s: s.__init__([7]),
+        "pop": lambda s: s.pop() if s else None,
+        "update": lambda s: s.update([101, 102]),
+    }
+
+    # the mutating methods, given a function creating attacking elements
+    METHODS = {
+        "add": lambda s, e: s.add(e(1, "eq")),
+        "add_hash": lambda s, e: s.add(e(1, "hash")),
+        "discard": lambda s, e: s.discard(e(1, "eq")),
+        "remove": lambda s, e: s.remove(e(1, "eq")),
+        "update": lambda s, e: s.update([e(1, "eq"), e(3, "eq"), 5]),
+        "update_set": lambda s, e: s.update(builtins.set([e(1, "eq"), e(3, "eq")])),
+        "difference_update": lambda s, e: s.difference_update([e(1, "eq"), e(3, "eq")]),
+        "intersection_update": lambda s, e: s.intersection_update([e(1, "eq"), e(3, "eq")]),
+        "symmetric_difference_update": lambda s, e: s.symmetric_difference_update([e(1, "eq"), e(3, "eq")]),
+        "init": lambda s, e: s.__init__([e(1, "eq"), e(3, "eq")]),
+    }
+
+    # the methods only reading their instance, which may still be modified
+    # by the elements they look up
+    READERS = {
+        "contains": lambda s, e: e(1, "eq") in s,
+        "isdisjoint": lambda s, e: s.isdisjoint([e(1, "eq")]),
+        "issubset": lambda s, e: s.issubset([e(1, "eq"), e(3, "eq"), 2]),
+        "issuperset": lambda s, e: s.issuperset([e(1, "eq")]),
+        "difference": lambda s, e: s.difference([e(1, "eq")]),
+        "intersection": lambda s, e: s.intersection([e(1, "eq"), e(3, "eq")]),
+        "symmetric_difference": lambda s, e: s.symmetric_difference([e(1, "eq")]),
+        "union": lambda s, e: s.union([e(1, "eq")]),
+        "eq": lambda s, e: s == builtins.set([e(1, "eq"), e(3, "eq"), 2]),
+    }
+
+    def run_attack(self, cls, method, attack, initial):
+        s = cls([Reentrant(1), Reentrant(3), 2] if initial else [])
+        def element(key, when):
+            return Reentrant(key, when, lambda: self.ATTACKS[attack](s))
+        methods = self.READERS if method in self.READERS else self.METHODS
+        outcome = attempt(lambda: methods[method](s, element))
+        if isinstance(outcome[1], (builtins.set, NanoSet, PicoSet)):
+            outcome = (outcome[0], sorted(map(repr, outcome[1])))
+        return outcome, s
+
+    def assertConsistent(self, s):
+        # check the allocation before iterating, since an exhausted iterator
+        # releases an empty set
+        allocated = s.__sizeof__() > type(s).__basicsize__
+        items = list(s)
+        self.assertEqual(len(s), len(items))
+        self.assertEqual(bool(s), bool(items))
+        self.assertEqual(allocated, bool(items))
+        self.assertEqual(len(s.copy()), len(items))
+
+    def test_mutating_methods(self):
+        for method in self.METHODS:
+            for attack in self.ATTACKS:
+                for initial in (False, True):
+                    with self.subTest(method=method, attack=attack, initial=initial):
+                        expected, r = self.run_attack(builtins.set, method, attack, initial)
+                        actual, s = self.run_attack(self.thetype, method, attack, initial)
+                        self.assertConsistent(s)
+                        self.assertEqual(actual, expected)
+                        self.assertEqual(sorted(map(repr, s)), sorted(map(repr, r)))
+
+    def test_reading_methods(self):
+        for method in self.READERS:
+            for attack in self.ATTACKS:
+                with self.subTest(method=method, attack=attack):
+                    expected, r = self.run_attack(builtins.set, method, attack, True)
+                    actual, s = self.run_attack(self.thetype, method, attack, True)
+                    self.assertConsistent(s)
+                    self.assertEqual(actual, expected)
+                    self.assertEqual(sorted(map(repr, s)), sorted(map(repr, r)))
+
+    def test_setstate(self):
+        # builtin sets have no `__setstate__`, but clearing and updating
+        # them is equivalent
+        for method in self.METHODS:
+            with self.subTest(method=method):
+                r = builtins.set([Reentrant(1), 2])
+                attack = lambda: (r.clear(), r.update([8]))
+                expected = attempt(lambda: self.METHODS[method](r, lambda key, when: Reentrant(key, when, attack)))
+                s = self.thetype([Reentrant(1), 2])
+                attack = lambda: s.__setstate__(builtins.set([8]))
+                actual = attempt(lambda: self.METHODS[method](s, lambda key, when: Reentrant(key, when, attack)))
+                self.assertConsistent(s)
+                self.assertEqual(actual, expected)
+                self.assertEqual(sorted(map(repr, s)), sorted(map(repr, r)))
+
+    def test_update_self(self):
+        s = self.thetype([Reentrant(1), 2])
+        s.update([Reentrant(1, "eq", lambda: s.update(s))], s)
+        self.assertConsistent(s)
+        self.assertEqual(sorted(map(repr, s)), ["2", "Reentrant(1)"])
+
+    def test_inplace_during_method(self):
+        s = self.thetype([Reentrant(1), 2])
+        attack = lambda: s.__iand__(builtins.set())
+        self.assertRaises(RuntimeError, s.add, Reentrant(1, "eq", attack))
+        self.assertConsistent(s)
+        self.assertEqual(sorted(map(repr, s)), ["2", "Reentrant(1)"])
+
+    def test_method_during_inplace(self):
+        attacks = {
+            "add": lambda s: s.add(3),
+            "clear": lambda s: s.clear(),
+            "contains": lambda s: s in self.thetype([frozenset()]),
+            "copy": lambda s: s.copy(),
+            "deepcopy": lambda s: copy.deepcopy(s),
+            "eq": lambda s: s == s,
+            "operator": lambda s: s | {1},
+            "remove": lambda s: self.thetype([frozenset()]).remove(s),
+        }
+        for name, attack in attacks.items():
+            with self.subTest(attack=name):
+                s = self.thetype([Reentrant(1)])
+                other = builtins.set([Reentrant(1, "eq", lambda: attack(s))])
+                with self.assertRaises(RuntimeError):
+                    s &= other
+                self.assertConsistent(s)
+                self.assertEqual(sorted(map(repr, s)), ["Reentrant(1)"])
+
+    def test_copy_on_write(self):
+        previous = nanoset.set_copy_on_write(True)
+        try:
+            copies = []
+            s = self.thetype([Reentrant(0)])
+            s.update([Reentrant(0, "eq", lambda: copies.append(s.copy())), 1, 2])
+            self.assertEqual(len(s), 3)
+            self.assertConsistent(copies[0])
+            self.assertEqual(sorted(map(repr, copies[0])), ["Reentrant(0)"])
+        finally:
+            nanoset.set_copy_on_write(previous)
+
+
+class TestNanoSetReentrancy(TestReentrancy, unittest.TestCase):
+    thetype = NanoSet
+
+
+class TestPicoSetReentrancy(TestReentrancy, unittest.TestCase):
+    thetype = PicoSet
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,228 @@
+# Reference leak regression tests.
+#
+# Every method is called many times in a row, and must not increase the
+# number of objects tracked by the garbage collector, the resident memory,
+# the reference counts of the elements and of the types involved, or the
+# total reference count on interpreters built with `--with-pydebug`.
+
+import array
+import builtins
+import copy
+import gc
+import os
+import pickle
+import sys
+import unittest
+
+import nanoset
+from nanoset import NanoSet, PicoSet
+
+
+def rss():
+    """Get the resident memory of the process, or `None` if unknown."""
+    try:
+        with open("/proc/self/statm") as statm:
+            return int(statm.read().split()[1]) * os.sysconf("SC_PAGE_SIZE")
+    except (OSError, ValueError, AttributeError):
+        return None
+
+
+def totalrefcount():
+    """Get the total reference count, or `None` with release interpreters."""
+    getter = getattr(sys, "gettotalrefcount", None)
+    return None if getter is None else getter()
+
+
+def ignore(func, *args):
+    """Call `func`, discarding any exception it raises."""
+    try:
+        func(*args)
+    except Exception:
+        pass
+
+
+class TestRefleaks(unittest.TestCase):
+
+    # each iteration calls every method several times
+    WARMUP = 50
+    ITERATIONS = 1000
+
+    # a leak of a reference or an object on every call is detected, but some
+    # growth is tolerated to avoid flaky failures from interpreter caches
+    TOLERANCE = ITERATIONS // 10
+    RSS_TOLERANCE = 1 << 20
+
+    # the types used by the recipes, whose reference counts are checked too
+    TYPES = [
+        NanoSet, PicoSet, nanoset.NanoSetIterator, nanoset.NanoSetView,
+        nanoset.Int64Array, builtins.set, frozenset,
+    ]
+
+    # the methods which are not checked, with the reason why
+    UNCHECKED = {
+        "__class_getitem__": "only builds a `types.GenericAlias`",
+        "__init_subclass__": "inherited from object",
+        "__subclasshook__": "inherited from object",
+    }
+
+    def setUp(self):
+        # elements created at runtime, which are neither cached nor immortal,
+        # so that only the recipes change their reference counts
+        self.items = [int("1001"), "".join(["a", "b"]), tuple([2, 3])]
+        self.other = [self.items[0], int("1004")]
+
+    def recipes(self, cls):
+        """Get a function calling each method of `cls` in various ways."""
+        items = self.items
+        other = self.other
+        key, missing = items[0], other[1]
+        data = array.array("q", [1, 2, 3])
+        recipes = {
+            "__new__": lambda: (cls(), cls(items), cls({1: 2}), cls(builtins.set(items)), cls(cls(items)), cls(iter(items))),
+            "__init__": lambda: (cls(items).__init__(other), ignore(cls().__init__, [[]])),
+            "__bool__": lambda: (bool(cls()), bool(cls(items))),
+            "__len__": lambda: (len(cls()), len(cls(items))),
+            "__contains__": lambda: (key in cls(), key in cls(items), cls() in cls([frozenset()]), ignore(cls(items).__contains__, [])),
+            "__iter__": lambda: (list(cls()), list(cls(items)), next(iter(cls(items)))),
+            "__repr__": lambda: (repr(cls()), repr(cls(items))),
+            "__sizeof__": lambda: (cls().__sizeof__(), cls(items).__sizeof__()),
+            "__eq__": lambda: (cls(items) == cls(items), cls() == builtins.set(), cls(items) == 1),
+            "__ne__": lambda: (cls(items) != frozenset(other), cls() != cls(items)),
+            "__lt__": lambda: (cls() < cls(items), ignore(cls(items).__lt__, 1)),
+            "__le__": lambda: (cls(items) <= builtins.set(items), cls() <= cls()),
+            "__gt__": lambda: (cls(items) > frozenset(), cls(items) > PicoSet(items)),
+            "__ge__": lambda: (cls(items) >= NanoSet(other), cls() >= builtins.set()),
+            "__and__": lambda: (cls(items) & cls(other), cls() & builtins.set(other)),
+            "__rand__": lambda: builtins.set(other) & cls(items),
+            "__or__": lambda: (cls(items) | cls(other), cls() | frozenset()),
+            "__ror__": lambda: frozenset(other) | cls(items),
+            "__sub__": lambda: (cls(items) - cls(other), cls() - cls()),
+            "__rsub__": lambda: builtins.set(other) - cls(items),
+            "__xor__": lambda: (cls(items) ^ cls(other), cls() ^ builtins.set(other)),
+            "__rxor__": lambda: frozenset(other) ^ cls(items),
+            "__iand__": lambda: self.inplace(cls, "__iand__", items, other),
+            "__ior__": lambda: self.inplace(cls, "__ior__", items, other),
+            "__isub__": lambda: self.inplace(cls, "__isub__", items, other),
+            "__ixor__": lambda: self.inplace(cls, "__ixor__", items, other),
+            "__copy__": lambda: (copy.copy(cls()), copy.copy(cls(items))),
+            "__deepcopy__": lambda: (copy.deepcopy(cls()), copy.deepcopy(cls(items))),
+            "__getstate__": lambda: (cls().__getstate__(), cls(items).__getstate__()),
+            "__setstate__": lambda: (cls().__setstate__(builtins.set(items)), cls(items).__setstate__(None), ignore(cls().__setstate__, 1)),
+            "__reduce__": lambda: (pickle.loads(pickle.dumps(cls())), pickle.loads(pickle.dumps(cls(items)))),
+            "add": lambda: (cls().add(key), cls(items).add(missing), ignore(cls().add, [])),
+            "clear": lambda: (cls().clear(), cls(items).clear()),
+            "copy": lambda: (cls().copy(), cls(items).copy()),
+            "difference": lambda: (cls(items).difference(other, [2]), cls().difference(other)),
+            "difference_update": lambda: self.update(cls, "difference_update", items, other),
+            "discard": lambda: (cls().discard(key), cls(items).discard(key), cls(items).discard(missing), ignore(cls().discard, [])),
+            "freeze": lambda: (cls().freeze(), cls(items).freeze()),
+            "from_array": lambda: (cls.from_array(data), cls.from_array(data[:0]), ignore(cls.from_array, b"x")),
+            "intersection": lambda: (cls(items).intersection(other, [key]), cls().intersection(other)),
+            "intersection_update": lambda: self.update(cls, "intersection_update", items, other),
+            "isdisjoint": lambda: (cls(items).isdisjoint(other), cls().isdisjoint(other)),
+            "issubset": lambda: (cls(items).issubset(other), cls().issubset(other)),
+            "issuperset": lambda: (cls(items).issuperset(other), cls().issuperset(other)),
+            "pop": lambda: (cls(items).pop(), ignore(cls().pop)),
+            "remove": lambda: (cls(items).remove(key), ignore(cls(items).remove, missing), ignore(cls().remove, key), ignore(cls().remove, [])),
+            "sorted_iter": lambda: (list(cls().sorted_iter()), list(cls(items).sorted_iter()), list(cls([2j, 1j]).sorted_iter())),
+            "symmetric_difference": lambda: (cls(items).symmetric_difference(other), cls().symmetric_difference(other)),
+            "symmetric_difference_update": lambda: self.update(cls, "symmetric_difference_update", items, other),
+            "to_array": lambda: (cls().to_array(), cls([1, 2]).to_array(), ignore(cls(items).to_array)),
+            "union": lambda: (cls(items).union(other, [5]), cls().union()),
+            "update": lambda: self.update(cls, "update", items, other),
+        }
+        if cls is NanoSet:
+            recipes["view"] = lambda: self.view(items, other)
+        return recipes
+
+    def update(self, cls, name, items, other):
+        # update non-empty and empty instances, with themselves, and with an
+        # iterable failing after some elements were processed
+        for s in (cls(items), cls()):
+            getattr(s, name)(other)
+            getattr(s, name)(s)
+            ignore(getattr(s, name), [1, []])
+
+    def view(self, items, other):
+        # use views over mappings, and copy their keys with every mutation
+        d = dict.fromkeys(items)
+        v = NanoSet.view(d)
+        key, missing = items[0], other[1]
+        (list(v), key in v, len(v), repr(v), v == builtins.set(items), v <= frozenset(other))
+        (v & builtins.set(other), v | NanoSet(other), v - frozenset(other), builtins.set(other) ^ v, v.copy())
+        ignore(NanoSet.view, items)
+        for name, args in (("add", (missing,)), ("discard", (key,)), ("remove", (missing,)), ("pop", ()), ("clear", ())):
+            ignore(getattr(NanoSet.view(d), name), *args)
+        for name in ("__iand__", "__ior__", "__isub__", "__ixor__"):
+            w = NanoSet.view(d)
+            getattr(w, name)(builtins.set(other))
+            getattr(w, name)(w)
+            ignore(getattr(w, name), other)
+
+    def inplace(self, cls, name, items, other):
+        for s in (cls(items), cls()):
+            getattr(s, name)(cls(other))
+            getattr(s, name)(s)
+            ignore(getattr(s, name), other)
+
+    def watched(self):
+        """Get the objects whose reference counts are checked."""
+        return self.items + self.other + self.TYPES + [nanoset.EMPTY]
+
+    def measure(self):
+        gc.collect()
+        refcounts = [sys.getrefcount(obj) for obj in self.watched()]
+        return totalrefcount(), len(gc.get_objects()), rss(), refcounts
+
+    def assertNoLeaks(self, func):
+        for _ in range(self.WARMUP):
+            func()
+        refs, objects, memory, refcounts = self.measure()
+        for _ in range(self.ITERATIONS):
+            func()
+        refs_after, objects_after, memory_after, refcounts_after = self.measure()
+
+        self.assertLess(objects_after - objects, self.TOLERANCE, "objects leaked")
+        # a missing decref grows the count, and an extra one shrinks it
+        for obj, count, count_after in zip(self.watched(), refcounts, refcounts_after):
+            self.assertLess(abs(count_after - count), self.TOLERANCE, "reference count of {!r} changed".format(obj))
+        if refs is not None:
+            self.assertLess(refs_after - refs, self.TOLERANCE, "references leaked")
+        if memory is not None:
+            self.assertLess(memory_after - memory, self.RSS_TOLERANCE, "memory leaked")
+
+    def test_recipes(self):
+        # make sure that new methods get a recipe
+        for cls in (NanoSet, PicoSet):
+            recipes = self.recipes(cls)
+            for name in dir(cls):
+                if callable(getattr(cls, name)) and getattr(cls, name) is not getattr(object, name, None):
+                    if name not in self.UNCHECKED:
+                        self.assertIn(name, recipes, "no recipe for {}.{}".format(cls.__name__, name))
+
+    def test_methods(self):
+        for cls in (NanoSet, PicoSet):
+            for name, func in sorted(self.recipes(cls).items()):
+                with self.subTest(cls=cls.__name__, method=name):
+                    self.assertNoLeaks(func)
+
+    def test_copy_on_write(self):
+        nanoset.set_copy_on_write(True)
+        try:
+            for cls in (NanoSet, PicoSet):
+                for name in ("copy", "add", "update", "clear", "pop"):
+                    with self.subTest(cls=cls.__name__, method=name):
+                        self.assertNoLeaks(self.recipes(cls)[name])
+        finally:
+            nanoset.set_copy_on_write(False)
+
+    def test_bulk(self):
+        sets = [NanoSet([1, 2]), PicoSet([2]), NanoSet(), [3]]
+        self.assertNoLeaks(lambda: (nanoset.union_all(sets), nanoset.intersection_all(sets)))
+        self.assertNoLeaks(lambda: nanoset.add_to_each(sets[:3], 4))
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,86 @@
+# Tests for the allocation counters, only available with the `stats` feature.
+
+import gc
+import unittest
+
+import nanoset
+
+
+@unittest.skipUnless(hasattr(nanoset, "stats"), "built without `stats` feature")
+class TestStats:
+
+    def setUp(self):
+        gc.collect()
+        nanoset.reset_stats()
+
+    def counters(self):
+        return nanoset.stats()[self.thetype.__name__]
+
+    def test_instances(self):
+        s = self.thetype()
+        t = self.thetype([1, 2])
+        u = t.copy()
+        self.assertEqual(self.counters(), {"created": 3, "dropped": 0})
+        del s, t, u
+        self.assertEqual(self.counters(), {"created": 3, "dropped": 3})
+
+    def test_allocated_released(self):
+        s = self.thetype([1, 2])
+        stats = nanoset.stats()
+        self.assertEqual(stats["sets_allocated"], 1)
+        self.assertEqual(stats["sets_released"], 0)
+        del s
+        stats = nanoset.stats()
+        self.assertEqual(stats["sets_allocated"], 1)
+        self.assertEqual(stats["sets_released"], 1)
+
+    def test_promotions_demotions(self):
+        s = self.thetype()
+        s.add(1)
+        s.update([2, 3])
+        stats = nanoset.stats()
+        self.assertEqual(stats["promotions"], 1)
+        self.assertEqual(stats["demotions"], 0)
+        s.clear()
+        s.update([1])
+        s.discard(1)
+        stats = nanoset.stats()
+        self.assertEqual(stats["promotions"], 2)
+        self.assertEqual(stats["demotions"], 2)
+        self.assertEqual(stats["sets_allocated"], 2)
+        self.assertEqual(stats["sets_released"], 2)
+
+    def test_peak(self):
+        live = nanoset.stats()["live_sets"]
+        sets = [self.thetype([i]) for i in range(10)]
+        stats = nanoset.stats()
+        self.assertEqual(stats["live_sets"], live + 10)
+        self.assertEqual(stats["peak_sets"], live + 10)
+        del sets
+        stats = nanoset.stats()
+        self.assertEqual(stats["live_sets"], live)
+        self.assertEqual(stats["peak_sets"], live + 10)
+        nanoset.reset_stats()
+        self.assertEqual(nanoset.stats()["peak_sets"], live)
+
+    def test_reset(self):
+        s = self.thetype([1])
+        nanoset.reset_stats()
+        stats = nanoset.stats()
+        self.assertEqual(stats["sets_allocated"], 0)
+        self.assertEqual(stats[self.thetype.__name__], {"created": 0, "dropped": 0})
+        self.assertEqual(stats["live_sets"], stats["peak_sets"])
+
+
+class TestNanoSetStats(TestStats, unittest.TestCase):
+    thetype = nanoset.NanoSet
+
+
+class TestPicoSetStats(TestStats, unittest.TestCase):
+    thetype = nanoset.PicoSet
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,401 @@
+# Check the type stubs describe the API actually exported by `nanoset`.
+
+import array
+import ast
+import builtins
+import collections
+import collections.abc
+import inspect
+import os
+import sys
+import types
+import unittest
+
+import nanoset
+import nanoset.json
+
+STUBS = os.path.join("nanoset", "__init__.pyi")
+DEBUG_STUBS = os.path.join("nanoset", "debug.pyi")
+JSON_STUBS = os.path.join("nanoset", "json.pyi")
+
+# `typing` names used in the stubs, mapped to classes usable with `isinstance`
+TYPING = {
+    "AbstractSet": collections.abc.Set,
+    "Dict": builtins.dict,
+    "FrozenSet": builtins.frozenset,
+    "Hashable": collections.abc.Hashable,
+    "Iterable": collections.abc.Iterable,
+    "Iterator": collections.abc.Iterator,
+    "List": builtins.list,
+    "Mapping": collections.abc.Mapping,
+    "MutableSet": collections.abc.MutableSet,
+    "Set": builtins.set,
+}
+
+# `typing` containers whose elements are checked as well
+CONTAINERS = {"FrozenSet", "List", "Set"}
+
+# arguments passed to the sample calls, by parameter annotation
+ARGUMENTS = {
+    "_T": lambda: 1,
+    "bool": lambda: False,
+    "object": lambda: frozenset({1}),
+    "Iterable[_T]": lambda: [1, 2],
+    "Iterable[_S]": lambda: [1, 2],
+    "Iterable[Any]": lambda: [1, 2],
+    "Iterable[Iterable[_T]]": lambda: [[1], [1, 2]],
+    "Iterable[MutableSet[_T]]": lambda: [nanoset.NanoSet([1])],
+    "AbstractSet[object]": lambda: builtins.set([1, 3]),
+    "AbstractSet[_T]": lambda: builtins.set([1, 3]),
+    "AbstractSet[_S]": lambda: builtins.set([1, 3]),
+    "AbstractSet[Optional[_T]]": lambda: builtins.set([1, 3]),
+    "Optional[Set[_T]]": lambda: builtins.set([1]),
+    "Dict[int, Any]": lambda: {},
+    "Dict[str, Any]": lambda: {"a": 1},
+    "Mapping[_S, Any]": lambda: {1: "a", 2: "b"},
+    "Union[str, bytes]": lambda: "[1]",
+}
+
+# sample instances of the stubbed classes, created afresh for every call
+INSTANCES = {
+    "Int64Array": lambda: nanoset.NanoSet([1, 2]).to_array(),
+    "NanoSetIterator": lambda: iter(nanoset.NanoSet([1, 2])),
+    "NanoSet": lambda: nanoset.NanoSet([1, 2]),
+    "NanoSetView": lambda: nanoset.NanoSet.view({1: "a", 2: "b"}),
+    "PicoSet": lambda: nanoset.PicoSet([1, 2]),
+    "PersistentNanoSetIterator": lambda: iter(nanoset.PersistentNanoSet([1, 2])),
+    "PersistentNanoSetEvolver": lambda: nanoset.PersistentNanoSet([1, 2]).evolver(),
+    "PersistentNanoSet": lambda: nanoset.PersistentNanoSet([1, 2]),
+    "ConcurrentNanoSetIterator": lambda: iter(nanoset.ConcurrentNanoSet([1, 2])),
+    "ConcurrentNanoSet": lambda: nanoset.ConcurrentNanoSet([1, 2]),
+    "Set": None,
+    "NanoSetEncoder": lambda: nanoset.json.NanoSetEncoder(),
+}
+
+# sample calls for the callables taking `Any`, or changing global state
+CALLS = {
+    "nanoset.set_legacy_repr": lambda f: f(f(False)),
+    "nanoset.set_copy_on_write": lambda f: f(f(False)),
+    "nanoset.set_deterministic": lambda f: f(f(False)),
+    "nanoset.configure": lambda f: f(None),
+    "nanoset.json.default": lambda f: f(nanoset.NanoSet([1, 2])),
+    "nanoset.json.dumps": lambda f: f(nanoset.NanoSet([1, 2])),
+    "nanoset.json.msgpack_default": lambda f: f(nanoset.NanoSet([1, 2])),
+    "nanoset.json.msgpack_ext_hook": lambda f: f(*nanoset.json.msgpack_default(nanoset.NanoSet([1, 2]))),
+    "NanoSet.__class_getitem__": lambda f: f(int),
+    "NanoSet.from_array": lambda f: f(array.array("q", [1, 2])),
+    "PicoSet.__class_getitem__": lambda f: f(int),
+    "PicoSet.from_array": lambda f: f(array.array("q", [1, 2])),
+    "PersistentNanoSet.__class_getitem__": lambda f: f(int),
+    "ConcurrentNanoSet.__class_getitem__": lambda f: f(int),
+    "Set.__class_getitem__": lambda f: f(int),
+    "NanoSetEncoder.default": lambda f: f(nanoset.NanoSet([1, 2])),
+}
+
+# callables without a runtime signature, checked to really not have one
+UNSIGNED = {"Set.__class_getitem__"}
+
+
+class TestStubs(unittest.TestCase):
+
+    @classmethod
+    def setUpClass(cls):
+        with open(STUBS) as f:
+            cls.source = f.read()
+            cls.stubs = ast.parse(cls.source, STUBS)
+        with open(DEBUG_STUBS) as f:
+            cls.debug_source = f.read()
+            cls.debug_stubs = ast.parse(cls.debug_source, DEBUG_STUBS)
+        with open(JSON_STUBS) as f:
+            cls.json_source = f.read()
+            cls.json_stubs = ast.parse(cls.json_source, JSON_STUBS)
+
+    def setUp(self):
+        # `msgpack_default` only needs `msgpack.ExtType`, use a stand-in for
+        # it if `msgpack` is not installed, like `test_json` does
+        self.msgpack = sys.modules.get("msgpack")
+        if self.msgpack is None:
+            try:
+                import msgpack
+            except ImportError:
+                msgpack = types.ModuleType("msgpack")
+                msgpack.ExtType = collections.namedtuple("ExtType", "code data")
+                sys.modules["msgpack"] = msgpack
+
+    def tearDown(self):
+        if self.msgpack is None:
+            sys.modules.pop("msgpack", None)
+
+    def stub_names(self, node):
+        names = set()
+        for child in node.body:
+            if isinstance(child, (ast.FunctionDef, ast.ClassDef)):
+                names.add(child.name)
+            elif isinstance(child, ast.AnnAssign):
+                names.add(child.target.id)
+            elif isinstance(child, ast.ImportFrom):
+                names.update(alias.asname for alias in child.names if alias.asname)
+        return names
+
+    def stub_class(self, name):
+        for node in self.stubs.body:
+            if isinstance(node, ast.ClassDef) and node.name == name:
+                return node
+        self.fail("no stub for class {!r}".format(name))
+
+    def stub_modules(self):
+        yield "nanoset", nanoset, self.stubs, self.source
+        yield "nanoset.debug", nanoset.debug, self.debug_stubs, self.debug_source
+        yield "nanoset.json", nanoset.json, self.json_stubs, self.json_source
+
+    def stub_callables(self):
+        """Yield `(name, stub, owner, runtime, source)` for stubbed functions.
+
+        `owner` is the class of methods, and `None` for module functions.
+        """
+        for modname, module, stubs, source in self.stub_modules():
+            for node in stubs.body:
+                if isinstance(node, ast.FunctionDef) and hasattr(module, node.name):
+                    name = "{}.{}".format(modname, node.name)
+                    yield name, node, None, getattr(module, node.name), source
+                elif isinstance(node, ast.ClassDef):
+                    cls = getattr(module, node.name)
+                    for child in node.body:
+                        if isinstance(child, ast.FunctionDef) and "property" not in self.decorators(child):
+                            name = "{}.{}".format(node.name, child.name)
+                            yield name, child, cls, getattr(cls, child.name), source
+
+    def decorators(self, stub):
+        return {d.id for d in stub.decorator_list if isinstance(d, ast.Name)}
+
+    # --- Parameters ---------------------------------------------------------
+
+    def stub_parameters(self, stub, method):
+        args = stub.args
+        positional = args.posonlyargs + args.args
+        required = len(positional) - len(args.defaults)
+        params = []
+        for i, arg in enumerate(positional):
+            if i < len(args.posonlyargs) or arg.arg.startswith("__"):
+                kind = inspect.Parameter.POSITIONAL_ONLY
+            else:
+                kind = inspect.Parameter.POSITIONAL_OR_KEYWORD
+            params.append((kind, arg.arg, i >= required, arg.annotation))
+        if method:
+            del params[:1]
+        if args.vararg is not None:
+            kind = inspect.Parameter.VAR_POSITIONAL
+            params.append((kind, args.vararg.arg, False, args.vararg.annotation))
+        for arg, default in zip(args.kwonlyargs, args.kw_defaults):
+            kind = inspect.Parameter.KEYWORD_ONLY
+            params.append((kind, arg.arg, default is not None, arg.annotation))
+        if args.kwarg is not None:
+            kind = inspect.Parameter.VAR_KEYWORD
+            params.append((kind, args.kwarg.arg, False, args.kwarg.annotation))
+        return params
+
+    def runtime_signature(self, name, stub, owner, runtime):
+        if owner is not None and stub.name in ("__init__", "__new__"):
+            if isinstance(vars(owner).get("__new__"), staticmethod):
+                # a Python-level `__new__` still takes the class explicitly
+                signature = inspect.signature(owner.__new__)
+                return signature.replace(parameters=list(signature.parameters.values())[1:])
+            return inspect.signature(owner)
+        if owner is not None and "classmethod" not in self.decorators(stub):
+            factory = INSTANCES.get(owner.__name__)
+            if factory is None:
+                self.fail("no sample instance of {}".format(owner.__name__))
+            runtime = getattr(factory(), stub.name)
+        return inspect.signature(runtime)
+
+    def assertParametersMatch(self, stub_params, signature):
+        runtime_params = list(signature.parameters.values())
+        self.assertEqual(
+            len(stub_params),
+            len(runtime_params),
+            "stub parameters {} do not match {}".format([p[1] for p in stub_params], signature),
+        )
+        for (kind, name, default, _), param in zip(stub_params, runtime_params):
+            if kind == inspect.Parameter.POSITIONAL_ONLY:
+                # stubs mark positional-only parameters that can also be
+                # passed by keyword, so only the position matters
+                self.assertIn(param.kind, (param.POSITIONAL_ONLY, param.POSITIONAL_OR_KEYWORD))
+            elif kind == inspect.Parameter.VAR_POSITIONAL:
+                self.assertEqual(param.kind, kind)
+            else:
+                self.assertEqual((param.kind, param.name), (kind, name))
+            self.assertEqual(
+                param.default is not param.empty,
+                default,
+                "default of {!r} differs".format(param.name),
+            )
+
+    def test_parameters(self):
+        for name, stub, owner, runtime, _ in self.stub_callables():
+            with self.subTest(function=name):
+                if name in UNSIGNED:
+                    with self.assertRaises(ValueError):
+                        inspect.signature(runtime)
+                    continue
+                method = owner is not None and "staticmethod" not in self.decorators(stub)
+                try:
+                    signature = self.runtime_signature(name, stub, owner, runtime)
+                except ValueError as err:
+                    self.fail("{} has no signature: {}".format(name, err))
+                self.assertParametersMatch(self.stub_parameters(stub, method), signature)
+
+    # --- Return values ------------------------------------------------------
+
+    def resolve(self, node):
+        """Get the class named by an annotation node."""
+        if isinstance(node, ast.Subscript):
+            node = node.value
+        if isinstance(node, ast.Name):
+            if node.id in TYPING:
+                return TYPING[node.id]
+            for _, module, _, _ in self.stub_modules():
+                if isinstance(getattr(module, node.id, None), type):
+                    return getattr(module, node.id)
+            if isinstance(getattr(builtins, node.id, None), type):
+                return getattr(builtins, node.id)
+        self.fail("can not resolve annotation {}".format(ast.dump(node)))
+
+    def subscript(self, node):
+        elts = node.slice
+        if sys.version_info < (3, 9):
+            elts = elts.value
+        return list(elts.elts) if isinstance(elts, ast.Tuple) else [elts]
+
+    def assertMatches(self, value, node):
+        """Check `value` has the type given by the annotation node."""
+        if isinstance(node, ast.Constant) and node.value is None:
+            self.assertIs(value, None)
+        elif isinstance(node, ast.Name) and (node.id == "Any" or node.id.startswith("_")):
+            pass
+        elif isinstance(node, ast.Subscript) and isinstance(node.value, ast.Name):
+            kind, args = node.value.id, self.subscript(node)
+            if kind in ("Optional", "Union"):
+                if kind == "Optional":
+                    args.append(ast.Constant(value=None))
+                for arg in args:
+                    try:
+                        self.assertMatches(value, arg)
+                        return
+                    except self.failureException:
+                        pass
+                self.fail("{!r} does not match {}".format(value, ast.dump(node)))
+            elif kind == "Tuple":
+                self.assertIsInstance(value, tuple)
+                if len(args) == 2 and isinstance(args[1], ast.Constant) and args[1].value is Ellipsis:
+                    args = args[:1] * len(value)
+                self.assertEqual(len(value), len(args))
+                for item, arg in zip(value, args):
+                    self.assertMatches(item, arg)
+            elif kind == "Callable":
+                self.assertTrue(callable(value), "{!r} is not callable".format(value))
+            elif kind == "Type":
+                self.assertIsInstance(value, type)
+                self.assertTrue(issubclass(value, self.resolve(args[0])))
+            else:
+                self.assertIsInstance(value, self.resolve(node))
+                if kind in CONTAINERS:
+                    for item in value:
+                        self.assertMatches(item, args[0])
+                elif kind == "Dict":
+                    for key, item in value.items():
+                        self.assertMatches(key, args[0])
+                        self.assertMatches(item, args[1])
+        elif isinstance(node, ast.Name):
+            self.assertIsInstance(value, self.resolve(node))
+        else:
+            self.fail("can not check annotation {}".format(ast.dump(node)))
+
+    def sample_call(self, name, stub, owner, runtime, source):
+        """Call a stubbed function with arguments built from its stub."""
+        if stub.name in ("__init__", "__new__"):
+            target = owner
+        elif owner is not None and not self.decorators(stub) & {"classmethod", "staticmethod"}:
+            target = getattr(INSTANCES[owner.__name__](), stub.name)
+        else:
+            target = runtime
+        if name in CALLS:
+            return CALLS[name](target)
+        args = []
+        method = owner is not None and "staticmethod" not in self.decorators(stub)
+        for kind, param, default, annotation in self.stub_parameters(stub, method):
+            if default or kind == inspect.Parameter.VAR_KEYWORD:
+                continue
+            text = ast.get_source_segment(source, annotation)
+            if text not in ARGUMENTS:
+                self.fail("no sample argument for {} in {}".format(text, name))
+            args.append(ARGUMENTS[text]())
+        return target(*args)
+
+    def test_returns(self):
+        for name, stub, owner, runtime, source in self.stub_callables():
+            with self.subTest(function=name):
+                if name == "nanoset.debug.iter_hidden_sets" and not nanoset.debug.ENABLED:
+                    continue
+                if stub.returns is None:
+                    self.fail("{} has no return annotation".format(name))
+                result = self.sample_call(name, stub, owner, runtime, source)
+                if stub.name == "__init__":
+                    self.assertIsInstance(result, owner)
+                else:
+                    self.assertMatches(result, stub.returns)
+
+    def test_attributes(self):
+        for modname, module, stubs, _ in self.stub_modules():
+            for node in stubs.body:
+                if isinstance(node, ast.AnnAssign) and hasattr(module, node.target.id):
+                    with self.subTest(attribute="{}.{}".format(modname, node.target.id)):
+                        self.assertMatches(getattr(module, node.target.id), node.annotation)
+                elif isinstance(node, ast.ClassDef):
+                    cls = getattr(module, node.name)
+                    for child in node.body:
+                        with self.subTest(attribute="{}.{}".format(node.name, getattr(child, "name", None))):
+                            if isinstance(child, ast.AnnAssign) and child.target.id == "__hash__":
+                                self.assertIs(vars(cls)["__hash__"], None)
+                            elif isinstance(child, ast.AnnAssign):
+                                value = getattr(INSTANCES[node.name](), child.target.id)
+                                self.assertMatches(value, child.annotation)
+                            elif "property" in self.decorators(child):
+                                self.assertTrue(inspect.isdatadescriptor(vars(cls)[child.name]))
+                                value = getattr(INSTANCES[node.name](), child.name)
+                                self.assertMatches(value, child.returns)
+
+    # --- Names --------------------------------------------------------------
+
+    def test_module(self):
+        # functions only compiled with an optional cargo feature
+        optional = {"stats", "reset_stats"}
+        exported = {name for name in vars(nanoset) if not name.startswith("_")}
+        stubbed = {name for name in self.stub_names(self.stubs) if not name.startswith("_")}
+        self.assertEqual(exported | optional, stubbed | optional)
+        self.assertLessEqual(optional, stubbed)
+
+    def test_debug_module(self):
+        exported = {name for name in vars(nanoset.debug) if not name.startswith("_")}
+        self.assertEqual(exported, self.stub_names(self.debug_stubs))
+
+    def test_json_module(self):
+        exported = {name for name in vars(nanoset.json) if not name.startswith("_")}
+        self.assertEqual(exported, self.stub_names(self.json_stubs))
+
+    def test_classes(self):
+        # `__new__` is generated by pyo3 and described by `__init__` instead
+        ignored = {"__doc__", "__module__", "__new__", "__slots__", "__abstractmethods__", "_abc_impl"}
+        # buffer slots are only exposed as methods since Python 3.12
+        ignored |= {"__buffer__", "__release_buffer__"}
+        for name, obj in vars(nanoset).items():
+            if isinstance(obj, type):
+                with self.subTest(cls=name):
+                    exported = set(vars(obj)) - ignored
+                    stubbed = self.stub_names(self.stub_class(name)) - ignored
+                    self.assertEqual(exported, stubbed)
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,114 @@
+# Stress tests running several threads against shared nanoset instances.
+#
+# The low-level `_thread` module is used instead of `threading`, since the
+# latter blocks interpreter finalization when imported outside of the main
+# thread, which is the case with the `cargo test` harness.
+
+import _thread
+import sys
+import time
+import unittest
+
+import nanoset
+
+
+class SlowHash(int):
+    'int-like object that releases the GIL while being hashed'
+    def __hash__(self):
+        time.sleep(0)
+        return int.__hash__(self)
+
+
+class TestThreading:
+
+    n_threads = 8
+    n_items = 200
+
+    def setUp(self):
+        self.interval = sys.getswitchinterval()
+        sys.setswitchinterval(1e-6)
+
+    def tearDown(self):
+        sys.setswitchinterval(self.interval)
+
+    def run_threads(self, target):
+        errors = []
+        locks = [_thread.allocate_lock() for _ in range(self.n_threads)]
+        def wrapper(i):
+            try:
+                target(i)
+            except Exception as err:
+                errors.append(err)
+            finally:
+                locks[i].release()
+        for i, lock in enumerate(locks):
+            lock.acquire()
+            _thread.start_new_thread(wrapper, (i,))
+        for lock in locks:
+            lock.acquire()
+        self.assertEqual(errors, [])
+
+    def test_add_disjoint(self):
+        s = self.thetype()
+        def target(i):
+            for x in range(i, self.n_items * self.n_threads, self.n_threads):
+                s.add(SlowHash(x))
+        self.run_threads(target)
+        self.assertEqual(s, frozenset(range(self.n_items * self.n_threads)))
+
+    def test_add_discard(self):
+        s = self.thetype()
+        def target(i):
+            items = range(i, self.n_items * self.n_threads, self.n_threads)
+            for x in items:
+                s.add(SlowHash(x))
+                s.discard(SlowHash(x))
+            for x in items:
+                if x % 2 == 0:
+                    s.add(SlowHash(x))
+        self.run_threads(target)
+        self.assertEqual(s, frozenset(range(0, self.n_items * self.n_threads, 2)))
+
+    def test_update_pop(self):
+        s = self.thetype()
+        popped = []
+        def target(i):
+            items = range(i, self.n_items * self.n_threads, self.n_threads)
+            s.update(SlowHash(x) for x in items)
+            for _ in range(self.n_items // 2):
+                popped.append(s.pop())
+        self.run_threads(target)
+        self.assertEqual(len(popped), len(frozenset(popped)))
+        self.assertEqual(s | frozenset(popped), frozenset(range(self.n_items * self.n_threads)))
+        self.assertEqual(len(s) + len(popped), self.n_items * self.n_threads)
+
+    def test_iterate_while_mutating(self):
+        s = self.thetype()
+        def target(i):
+            for x in range(self.n_items):
+                if i % 2 == 0:
+                    s.add(SlowHash(x))
+                    s.discard(SlowHash(x))
+                else:
+                    try:
+                        for y in s:
+                            self.assertIsInstance(y, int)
+                    except RuntimeError:
+                        pass
+        self.run_threads(target)
+        self.assertEqual(len(s), 0)
+        self.assertFalse(s)
+
+
+class TestNanoSetThreading(TestThreading, unittest.TestCase):
+    thetype = nanoset.NanoSet
+
+
+class TestPicoSetThreading(TestThreading, unittest.TestCase):
+    thetype = nanoset.PicoSet
+
+
+#==============================================================================
+
+if __name__ == "__main__":
+    unittest.main(exit=False)
@@ -0,0 +1,215 @@
+# Tests for `nanoset.NanoSet.view` and `nanoset.NanoSetView`.
+
+import builtins
+import collections.abc
+import gc
+import types
+import unittest
+
+import nanoset
+from nanoset import NanoSet, NanoSetView
+
+
+class ViewKey(object):
+    'key calling `attack` the first time it is hashed'
+
+    def __init__(self, key, attack=None):
+        self.key = key
+        self.attack = attack
+
+    def __repr__(self):
+        return "ViewKey({!r})".format(self.key)
+
+    def __hash__(self):
+        if self.attack is not None:
+            attack, self.attack = self.attack, None
+            attack()
+        return hash(self.key)
+
+    def __eq__(self, other):
+        return isinstance(other, ViewKey) and self.key == other.key
+
+
+class TestNanoSetView(unittest.TestCase):
+
+    def test_view(self):
+        d = {"a": 1, "b": 2}
+        v = NanoSet.view(d)
+        self.assertIsInstance(v, NanoSetView)
+        self.assertIs(v.mapping, d)
+        self.assertEqual(len(v), 2)
+        self.assertTrue(v)
+        self.assertIn("a", v)
+        self.assertNotIn("c", v)
+        self.assertEqual(sorted(v), ["a", "b"])
+        self.assertEqual(repr(v), "NanoSetView({'a': 1, 'b': 2})")
+        self.assertFalse(NanoSet.view({}))
+
+    def test_mapping(self):
+        v = NanoSet.view(types.MappingProxyType({1: 2}))
+        self.assertIn(1, v)
+        self.assertEqual(v, {1})
+        self.assertRaises(TypeError, NanoSet.view, [1, 2])
+        self.assertRaises(TypeError, NanoSet.view, {1, 2})
+
+    def test_abc(self):
+        v = NanoSet.view({})
+        self.assertIsInstance(v, collections.abc.Set)
+        self.assertNotIsInstance(v, collections.abc.Hashable)
+
+    def test_live(self):
+        d = {"a": 1}
+        v = NanoSet.view(d)
+        d["b"] = 2
+        del d["a"]
+        self.assertEqual(v, {"b"})
+
+    def test_operations(self):
+        v = NanoSet.view(dict.fromkeys("abcd"))
+        for result in (v & {"a", "e"}, v | {"e"}, v - {"a"}, v ^ {"a", "e"}):
+            self.assertIs(type(result), NanoSet)
+        self.assertEqual(v & {"a", "e"}, {"a"})
+        self.assertEqual(v | {"e"}, builtins.set("abcde"))
+        self.assertEqual(v - {"a"}, builtins.set("bcd"))
+        self.assertEqual(v ^ {"a", "e"}, builtins.set("bcde"))
+        self.assertEqual({"a", "e"} & v, {"a"})
+        self.assertEqual({"e"} | v, builtins.set("abcde"))
+        self.assertEqual({"a", "e"} - v, {"e"})
+        self.assertEqual(frozenset("ae") ^ v, builtins.set("bcde"))
+        self.assertEqual(v & NanoSet("ab"), builtins.set("ab"))
+        self.assertEqual(NanoSet("ae") - v, {"e"})
+        self.assertTrue(v.isdisjoint("xyz"))
+        self.assertFalse(v.isdisjoint("ax"))
+        self.assertRaises(TypeError, lambda: v & [1])
+        self.assertIsNotNone(v.mapping)
+
+    def test_comparison(self):
+        v = NanoSet.view({1: None, 2: None})
+        self.assertTrue(v == {1, 2})
+        self.assertTrue(v == NanoSet([1, 2]))
+        self.assertTrue(NanoSet([1, 2]) == v)
+        self.assertTrue(frozenset([1, 2]) == v)
+        self.assertTrue(v <= {1, 2})
+        self.assertTrue(v < {1, 2, 3})
+        self.assertTrue(v > {1})
+        self.assertFalse(v >= {3})
+        self.assertTrue(v != [1, 2])
+
+    def test_copy(self):
+        d = {"a": 1}
+        v = NanoSet.view(d)
+        s = v.copy()
+        self.assertIs(type(s), NanoSet)
+        self.assertEqual(s, {"a"})
+        self.assertIs(v.mapping, d)
+
+    def test_modify(self):
+        d = {"a": 1, "b": 2}
+        v = NanoSet.view(d)
+        v.add("c")
+        self.assertIsNone(v.mapping)
+        self.assertEqual(v, builtins.set("abc"))
+        self.assertEqual(repr(v), "NanoSetView({!r})".format(NanoSet("abc")))
+        # the mapping is left untouched, and is not reflected anymore
+        self.assertEqual(d, {"a": 1, "b": 2})
+        d["d"] = 4
+        self.assertNotIn("d", v)
+        v.discard("a")
+        v.remove("b")
+        self.assertRaises(KeyError, v.remove, "b")
+        self.assertEqual(v.pop(), "c")
+        self.assertRaises(KeyError, v.pop)
+
+    def test_inplace(self):
+        v = NanoSet.view(dict.fromkeys("abcd"))
+        t = v
+        v |= {"e"}
+        v &= builtins.set("abce")
+        v -= {"a"}
+        v ^= {"b", "f"}
+        self.assertIs(v, t)
+        self.assertEqual(v, builtins.set("cef"))
+        with self.assertRaises(TypeError):
+            v |= [1]
+        v ^= v
+        self.assertFalse(v)
+
+    def test_inplace_self(self):
+        d = {1: 1}
+        v = NanoSet.view(d)
+        v |= v
+        v &= v
+        self.assertIs(v.mapping, d)
+        v -= v
+        self.assertIsNone(v.mapping)
+        self.assertFalse(v)
+        self.assertEqual(d, {1: 1})
+
+    def test_clear(self):
+        d = {1: 1}
+        v = NanoSet.view(d)
+        v.clear()
+        self.assertIsNone(v.mapping)
+        self.assertFalse(v)
+        self.assertEqual(d, {1: 1})
+
+    def test_reentrant_copy(self):
+        # the keys are hashed when they are first copied, and may modify the
+        # view while it is still delegating to the mapping
+        key = ViewKey("a")
+        d = {key: 1}
+        v = NanoSet.view(d)
+        key.attack = lambda: (v.add(ViewKey("b")), self.assertIs(v.mapping, None))
+        v.add(ViewKey("c"))
+        self.assertEqual(v, builtins.set(map(ViewKey, "abc")))
+        self.assertEqual(d, {key: 1})
+
+    def test_reentrant_methods(self):
+        attacks = {
+            "add": lambda v: v.add(ViewKey("x")),
+            "clear": lambda v: v.clear(),
+            "discard": lambda v: v.discard(ViewKey("a")),
+            "pop": lambda v: v.pop() if v else None,
+            "read": lambda v: (len(v), ViewKey("a") in v, repr(v), v == builtins.set(), list(v)),
+        }
+        methods = {
+            "add": lambda s, key: s.add(key),
+            "discard": lambda s, key: s.discard(key),
+            "remove": lambda s, key: s.remove(key),
+        }
+        def outcome(s, call, key):
+            try:
+                call(s, key)
+            except Exception as err:
+                return type(err), sorted(map(repr, s))
+            return None, sorted(map(repr, s))
+        for method, call in methods.items():
+            for name, attack in attacks.items():
+                for copied in (False, True):
+                    with self.subTest(method=method, attack=name, copied=copied):
+                        # builtin sets are modified in place like the wrapped set
+                        r = builtins.set([ViewKey("a"), ViewKey("b")])
+                        expected = outcome(r, call, ViewKey("b", lambda: attack(r)))
+                        v = NanoSet.view({ViewKey("a"): 1, ViewKey("b"): 2})
+                        if copied:
+                            v.add(ViewKey("a"))
+                        actual = outcome(v, call, ViewKey("b", lambda: attack(v)))
+                        self.assertEqual(actual, expected)
+
+    def test_reentrant_inplace(self):
+        # like with `NanoSet`, the view is borrowed during in-place operators
+        key = ViewKey("a")
+        d = {key: 1}
+        v = NanoSet.view(d)
+        key.attack = lambda: v.add(ViewKey("b"))
+        with self.assertRaises(RuntimeError):
+            v |= {ViewKey("c")}
+        self.assertIs(v.mapping, d)
+        self.assertEqual(v, {key})
+
+    def test_cycle(self):
+        d = {}
+        d["view"] = NanoSet.view(d)
+        self.assertEqual(repr(d["view"]), "NanoSetView({'view': NanoSetView({...})})")
+        del d
+        gc.collect()
@@ -5,6 +5,7 @@ extern crate pyo3;
 use std::path::Path;
 use std::sync::Mutex;
 
+use pyo3::exceptions::ImportError;
 use pyo3::Python;
 use pyo3::types::PyDict;
 use pyo3::types::PyModule;
@@ -58,5 +59,48 @@ macro_rules! unittest {
     }
 }
 
+unittest!(test_array);
+unittest!(test_bulk);
+unittest!(test_json);
 unittest!(test_nanoset);
 unittest!(test_picoset);
+unittest!(test_refleaks);
+unittest!(test_reentrancy);
+unittest!(test_malicious);
+unittest!(test_parity);
+unittest!(test_operators);
+unittest!(test_threading);
+unittest!(test_stubs);
+unittest!(test_stats);
+unittest!(test_debug);
+unittest!(test_factory);
+unittest!(test_persistent);
+unittest!(test_concurrent);
+unittest!(test_view);
+
+#[test]
+fn gil_disabled() {
+    let _l = LOCK.lock().unwrap();
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+
+    // pretend to be a free-threaded interpreter running without the GIL
+    let locals = PyDict::new(py);
+    py.run(
+        "import sys; previous = vars(sys).get('_is_gil_enabled'); sys._is_gil_enabled = lambda: False",
+        None,
+        Some(locals),
+    )
+    .unwrap();
+    let module = PyModule::new(py, "nanoset").unwrap();
+    let result = nanoset_py::init(py, module);
+    py.run(
+        "if previous is None: del sys._is_gil_enabled\nelse: sys._is_gil_enabled = previous",
+        None,
+        Some(locals),
+    )
+    .unwrap();
+
+    let err = result.unwrap_err();
+    assert!(err.is_instance::<ImportError>(py));
+}